@@ -8,14 +8,16 @@ use controller::consts::{WEBSOCKET_IP, WEBSOCKET_PATH, WEBSOCKET_PORT};
 use controller::dio_controller::DioController;
 use controller::embassy_websocket::EmbassyWebSocket;
 use controller::macros::mk_static;
-use controller::tasks::{connection, keep_alive, net_task, read_websocket};
-use controller::types::DioControllerMutex;
+use controller::tasks::{
+    connection, count_flow_pulses, keep_alive, net_task, rain_sensor, read_websocket, report_flow,
+};
+use controller::types::{device_id, DeviceId, DioControllerMutex, VerifierMutex};
 use embassy_executor::Spawner;
 use embassy_net::{Stack, StackResources};
 use embassy_sync::mutex::Mutex;
 use esp_backtrace as _;
 use esp_hal::clock::CpuClock;
-use esp_hal::gpio::AnyPin;
+use esp_hal::gpio::{AnyPin, Input, InputConfig, Pull};
 use esp_hal::timer::systimer::SystemTimer;
 use esp_hal::timer::timg::TimerGroup;
 use esp_wifi::wifi::WifiController;
@@ -40,6 +42,14 @@ async fn main(spawner: Spawner) {
         peripherals.GPIO18.into(),
     ];
     let controller = DioController::new(zone_pins);
+    let rain_sensor_input = Input::new(
+        peripherals.GPIO4,
+        InputConfig::default().with_pull(Pull::Up),
+    );
+    let flow_meter_input = Input::new(
+        peripherals.GPIO5,
+        InputConfig::default().with_pull(Pull::Up),
+    );
     let controller_mutex = mk_static!(DioControllerMutex, Mutex::new(controller));
 
     esp_alloc::heap_allocator!(size: 72 * 1024);
@@ -61,6 +71,7 @@ async fn main(spawner: Spawner) {
     let wifi_interface = interfaces.sta;
 
     info!("MAC: {:02X?}", wifi_interface.mac_address());
+    let device_id = mk_static!(DeviceId, device_id(wifi_interface.mac_address()));
 
     controller
         .set_power_saving(esp_wifi::config::PowerSaveMode::None)
@@ -85,18 +96,34 @@ async fn main(spawner: Spawner) {
             Ipv4Addr::from_str(WEBSOCKET_IP).unwrap(),
             WEBSOCKET_PORT.parse::<u16>().unwrap(),
             path,
-            rng,
+            rng.clone(),
         )
         .unwrap()
     );
 
+    let verifier = mk_static!(VerifierMutex, Mutex::new(None));
+
     let controller = mk_static!(WifiController<'static>, controller);
     let stack = mk_static!(Stack<'static>, stack);
 
-    spawner.spawn(connection(controller, stack, websocket)).ok();
+    spawner
+        .spawn(connection(controller, stack, websocket, verifier))
+        .ok();
     spawner.spawn(net_task(runner)).ok();
-    spawner.spawn(keep_alive(websocket)).ok();
+    spawner.spawn(keep_alive(websocket, verifier)).ok();
+    spawner
+        .spawn(rain_sensor(websocket, verifier, rain_sensor_input))
+        .ok();
+    spawner.spawn(count_flow_pulses(flow_meter_input)).ok();
+    spawner.spawn(report_flow(websocket, verifier)).ok();
     spawner
-        .spawn(read_websocket(websocket, controller_mutex, spawner))
+        .spawn(read_websocket(
+            websocket,
+            controller_mutex,
+            verifier,
+            device_id,
+            rng,
+            spawner,
+        ))
         .ok();
 }
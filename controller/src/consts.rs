@@ -5,7 +5,18 @@ pub const WEBSOCKET_PATH: &str = env!("WEBSOCKET_PATH");
 pub const WIFI_SSID: &str = env!("WIFI_SSID");
 pub const WIFI_PASSWORD: &str = env!("WIFI_PASSWORD");
 
+/// Presented to the server on connect, saved there once an admin approves
+/// this device.
+pub const DEVICE_SECRET: &str = env!("DEVICE_SECRET");
+
 pub const BUFFER_SIZE: usize = 4_000;
 
 pub const KEEP_ALIVE_DURATION_MS: u64 = 2_500;
 pub const READ_TIMEOUT_MS: u64 = 100;
+
+pub const RAIN_SENSOR_POLL_MS: u64 = 50;
+pub const RAIN_SENSOR_DEBOUNCE_MS: u64 = 2_000;
+
+/// K-factor of the flow meter, from its datasheet.
+pub const FLOW_PULSES_PER_LITER: f32 = 450.0;
+pub const FLOW_REPORT_MS: u64 = 5_000;
@@ -7,6 +7,7 @@ use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
 use embassy_time::{Duration, Instant};
 use esp_hal::rng::Rng;
 use heapless::String;
+use shared::{write_text_frame, MAX_HEADER_LEN};
 
 const REQUEST_BUFFER_SIZE: usize = 256;
 const SOCKET_TIMEOUT_SECONDS: u64 = 20;
@@ -90,7 +91,7 @@ impl<'a> EmbassyWebSocket<'a> {
         text: String<N>,
     ) -> Result<(), EmbassyWebSocketError>
     where
-        [u8; N + 6]: Sized,
+        [u8; N + MAX_HEADER_LEN]: Sized,
     {
         let masking_key = {
             let mut rng_guard = self.rng.lock().await;
@@ -103,13 +104,14 @@ impl<'a> EmbassyWebSocket<'a> {
             }
         };
 
-        let frame = bytes_to_websocket_frame(text, masking_key)
+        let mut frame = [0; N + MAX_HEADER_LEN];
+        let frame_len = write_text_frame(text.as_bytes(), masking_key, &mut frame)
             .map_err(|_| EmbassyWebSocketError::FrameCreationFailed)?;
 
         let mut socket_guard = self.socket.lock().await;
         if let Some(socket) = socket_guard.as_mut() {
             socket
-                .write(&frame)
+                .write(&frame[..frame_len])
                 .await
                 .map_err(|_e| EmbassyWebSocketError::SendFailed)?;
             socket
@@ -233,37 +235,6 @@ async fn wait_for_handshake_response(
     }
 }
 
-pub fn bytes_to_websocket_frame<const N: usize>(
-    text: String<N>,
-    masking_key: [u8; 4],
-) -> Result<[u8; N + 6], &'static str>
-where
-    [u8; N + 6]: Sized,
-{
-    let mut frame = [0; N + 6];
-
-    // FIN + Opcode
-    frame[0] = 0x81;
-
-    // MASK + Payload Length
-    if N > 125 {
-        return Err("Payload length is too long");
-    }
-
-    frame[1] = 0x80 | (N as u8); // 0x80 for MASK bit
-
-    // Masking Key
-    frame[2..6].copy_from_slice(&masking_key);
-
-    // Masked Payload
-    let bytes = text.as_bytes();
-    for i in 0..N {
-        frame[6 + i] = bytes[i] ^ masking_key[i % 4];
-    }
-
-    Ok(frame)
-}
-
 #[derive(Debug)]
 pub enum EmbassyWebSocketError {
     ConnectionFailed,
@@ -1,11 +1,14 @@
-use crate::consts::{BUFFER_SIZE, WIFI_PASSWORD, WIFI_SSID};
+use crate::consts::{BUFFER_SIZE, DEVICE_SECRET, WIFI_PASSWORD, WIFI_SSID};
 use crate::embassy_websocket::EmbassyWebSocket;
+use crate::types::{DeviceId, VerifierMutex};
 use embassy_net::Stack;
 use embassy_time::{Duration, Timer};
 use esp_backtrace as _;
+use esp_hal::rng::Rng;
 use esp_wifi::wifi::{ClientConfiguration, Configuration, WifiController, WifiEvent, WifiState};
 use heapless::String;
 use log::{info, warn};
+use shared::{identify_proof, ControllerHandshake, IdentifyPayload, Verifier};
 
 static mut RX_BUFFER: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE];
 static mut TX_BUFFER: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE];
@@ -15,6 +18,7 @@ pub async fn connection(
     controller: &'static mut WifiController<'static>,
     stack: &'static Stack<'static>,
     websocket: &'static EmbassyWebSocket<'static>,
+    verifier: &'static VerifierMutex,
 ) {
     loop {
         match esp_wifi::wifi::wifi_state() {
@@ -79,20 +83,13 @@ pub async fn connection(
                     let rx_buffer = unsafe { &mut RX_BUFFER };
                     let tx_buffer = unsafe { &mut TX_BUFFER };
 
+                    // the new connection starts with the server's challenge,
+                    // which read_websocket answers
+                    *verifier.lock().await = None;
                     info!("Connecting to websocket");
                     match websocket.connect(&stack, rx_buffer, tx_buffer).await {
                         Ok(()) => {
                             info!("Connected to websocket");
-                            let mut connection_text = String::<10>::new();
-                            let _ = connection_text.push_str("controller");
-                            match websocket.write_text(connection_text).await {
-                                Ok(()) => {
-                                    info!("Sent controller identification");
-                                }
-                                Err(e) => {
-                                    warn!("Failed to send controller identification: {:?}", e);
-                                }
-                            }
                         }
                         Err(e) => {
                             warn!("failed to connect to websocket: {e:?}");
@@ -112,3 +109,49 @@ pub async fn connection(
         }
     }
 }
+
+/// Whether the server will take messages on this connection. It closes any
+/// connection that sends something before identifying.
+pub async fn is_identified(
+    websocket: &EmbassyWebSocket<'static>,
+    verifier: &VerifierMutex,
+) -> bool {
+    websocket.is_connected().await && verifier.lock().await.is_some()
+}
+
+/// Answers the server's challenge, proving this controller holds
+/// DEVICE_SECRET without sending it. Returns the verifier for the messages
+/// the server sends on this connection.
+pub async fn identify(
+    websocket: &EmbassyWebSocket<'static>,
+    device_id: &DeviceId,
+    rng: &mut Rng,
+    challenge: &str,
+) -> Option<Verifier> {
+    // a fresh nonce per connection, so the server's messages can't be
+    // replayed on a later one
+    let nonce = (rng.random() as u64) << 32 | rng.random() as u64;
+    let proof = identify_proof(
+        DEVICE_SECRET.as_bytes(),
+        device_id.as_str(),
+        challenge,
+        nonce,
+    );
+    let mut connection_text = String::<256>::new();
+    let handshake = ControllerHandshake::Identify(IdentifyPayload {
+        device_id: device_id.as_str(),
+        nonce,
+        proof: &proof,
+    });
+    let _ = connection_text.push_str(serde_json::to_string(&handshake).unwrap().as_str());
+    match websocket.write_text(connection_text).await {
+        Ok(()) => {
+            info!("Sent controller identification");
+            Some(Verifier::new(DEVICE_SECRET.as_bytes(), nonce))
+        }
+        Err(e) => {
+            warn!("Failed to send controller identification: {:?}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::consts::{FLOW_PULSES_PER_LITER, FLOW_REPORT_MS};
+use crate::embassy_websocket::EmbassyWebSocket;
+use crate::tasks::connection::is_identified;
+use crate::types::VerifierMutex;
+use core::sync::atomic::{AtomicU32, Ordering};
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::gpio::Input;
+use heapless::String;
+use log::warn;
+use shared::{ControllerMessage, FlowPayload};
+
+static PULSES: AtomicU32 = AtomicU32::new(0);
+
+/// Counts pulses from a hall-effect flow meter, one per falling edge.
+#[embassy_executor::task]
+pub async fn count_flow_pulses(mut input: Input<'static>) {
+    loop {
+        input.wait_for_falling_edge().await;
+        PULSES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Reports the flow rate over each interval along with the running total.
+#[embassy_executor::task]
+pub async fn report_flow(
+    websocket: &'static EmbassyWebSocket<'static>,
+    verifier: &'static VerifierMutex,
+) {
+    let mut total_pulses: u64 = 0;
+    let mut last_report = Instant::now();
+
+    loop {
+        Timer::after(Duration::from_millis(FLOW_REPORT_MS)).await;
+
+        let pulses = PULSES.swap(0, Ordering::Relaxed);
+        let now = Instant::now();
+        let elapsed_ms = (now - last_report).as_millis().max(1);
+        last_report = now;
+        total_pulses += pulses as u64;
+
+        if !is_identified(websocket, verifier).await {
+            continue;
+        }
+
+        let liters = pulses as f32 / FLOW_PULSES_PER_LITER;
+        let payload = ControllerMessage::Flow(FlowPayload {
+            liters_per_minute: liters * 60_000.0 / elapsed_ms as f32,
+            total_liters: total_pulses as f32 / FLOW_PULSES_PER_LITER,
+        });
+
+        let mut packet = String::<96>::new();
+        let _ = packet.push_str(serde_json::to_string(&payload).unwrap().as_str());
+
+        if let Err(e) = websocket.write_text(packet).await {
+            warn!("Failed to send flow report: {:?}", e);
+        }
+    }
+}
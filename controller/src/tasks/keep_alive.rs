@@ -1,17 +1,22 @@
 use crate::consts::KEEP_ALIVE_DURATION_MS;
 use crate::embassy_websocket::EmbassyWebSocket;
+use crate::tasks::connection::is_identified;
+use crate::types::VerifierMutex;
 use embassy_time::{Duration, Timer};
 use heapless::String;
 use log::{info, warn};
 use shared::{ControllerMessage, KeepAlivePayload};
 
 #[embassy_executor::task]
-pub async fn keep_alive(websocket: &'static EmbassyWebSocket<'static>) {
+pub async fn keep_alive(
+    websocket: &'static EmbassyWebSocket<'static>,
+    verifier: &'static VerifierMutex,
+) {
     loop {
         Timer::after(Duration::from_millis(KEEP_ALIVE_DURATION_MS)).await;
 
-        if !websocket.is_connected().await {
-            info!("Websocket not connected");
+        if !is_identified(websocket, verifier).await {
+            info!("Websocket not identified");
             Timer::after(Duration::from_millis(KEEP_ALIVE_DURATION_MS)).await;
             continue;
         }
@@ -1,13 +1,17 @@
 pub mod connection;
+pub mod flow_meter;
 pub mod keep_alive;
 pub mod net_task;
+pub mod rain_sensor;
 pub mod read_websocket;
 pub mod scan_networks;
 pub mod toggle_zone;
 
 pub use connection::connection;
+pub use flow_meter::{count_flow_pulses, report_flow};
 pub use keep_alive::keep_alive;
 pub use net_task::net_task;
+pub use rain_sensor::rain_sensor;
 pub use read_websocket::read_websocket;
 pub use scan_networks::scan_networks;
 pub use toggle_zone::toggle_zone;
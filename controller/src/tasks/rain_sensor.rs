@@ -0,0 +1,51 @@
+use crate::consts::{RAIN_SENSOR_DEBOUNCE_MS, RAIN_SENSOR_POLL_MS};
+use crate::embassy_websocket::EmbassyWebSocket;
+use crate::tasks::connection::is_identified;
+use crate::types::VerifierMutex;
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::gpio::Input;
+use heapless::String;
+use log::{info, warn};
+use shared::{ControllerMessage, Debouncer, RainSensorPayload};
+
+/// Watches a normally-closed rain sensor wired between the input and ground.
+/// The pull-up takes the input high when the sensor opens, so a wet sensor
+/// and a cut wire both read as active.
+#[embassy_executor::task]
+pub async fn rain_sensor(
+    websocket: &'static EmbassyWebSocket<'static>,
+    verifier: &'static VerifierMutex,
+    input: Input<'static>,
+) {
+    let mut debouncer = Debouncer::new(input.is_high(), RAIN_SENSOR_DEBOUNCE_MS);
+    // resent once every new connection identifies, so the server never
+    // holds a stale state
+    let mut reported: Option<bool> = None;
+
+    loop {
+        Timer::after(Duration::from_millis(RAIN_SENSOR_POLL_MS)).await;
+
+        if let Some(active) = debouncer.update(input.is_high(), Instant::now().as_millis()) {
+            info!("Rain sensor {}", if active { "active" } else { "clear" });
+        }
+
+        if !is_identified(websocket, verifier).await {
+            reported = None;
+            continue;
+        }
+
+        let active = debouncer.level();
+        if reported == Some(active) {
+            continue;
+        }
+
+        let mut packet = String::<64>::new();
+        let payload = ControllerMessage::RainSensor(RainSensorPayload { active });
+        let _ = packet.push_str(serde_json::to_string(&payload).unwrap().as_str());
+
+        match websocket.write_text(packet).await {
+            Ok(()) => reported = Some(active),
+            Err(e) => warn!("Failed to send rain sensor state: {:?}", e),
+        }
+    }
+}
@@ -1,31 +1,41 @@
 use embassy_executor::Spawner;
 use embassy_time::{Duration, Timer};
 use esp_hal::gpio::Level;
-use heapless::{String, Vec};
-use log::{error, info};
-use shared::ServerMessage;
+use esp_hal::rng::Rng;
+use log::{error, info, warn};
+use shared::{read_frame, FrameError, ServerHandshake, ServerMessage, OPCODE_TEXT};
 
 use crate::consts::{BUFFER_SIZE, READ_TIMEOUT_MS};
 use crate::embassy_websocket::EmbassyWebSocket;
+use crate::tasks::connection::identify;
 use crate::tasks::toggle_zone;
-use crate::types::DioControllerMutex;
+use crate::types::{DeviceId, DioControllerMutex, VerifierMutex};
 
 #[embassy_executor::task]
 pub async fn read_websocket(
     websocket: &'static EmbassyWebSocket<'static>,
     controller: &'static DioControllerMutex,
+    verifier: &'static VerifierMutex,
+    device_id: &'static DeviceId,
+    mut rng: Rng,
     spawner: Spawner,
 ) {
+    let mut buffer = [0; BUFFER_SIZE];
+    // bytes read but not yet made into a whole frame
+    let mut pending = 0;
+
     loop {
         if !websocket.is_connected().await {
+            pending = 0;
             Timer::after(Duration::from_millis(2_500)).await;
             continue;
         }
 
-        let mut buffer = [0; BUFFER_SIZE];
-
-        let message_len = match websocket
-            .read_with_timeout(&mut buffer, Duration::from_millis(READ_TIMEOUT_MS))
+        let read_len = match websocket
+            .read_with_timeout(
+                &mut buffer[pending..],
+                Duration::from_millis(READ_TIMEOUT_MS),
+            )
             .await
         {
             Ok(len) => len,
@@ -35,53 +45,94 @@ pub async fn read_websocket(
             }
         };
 
-        if message_len == 0 {
+        if read_len == 0 {
             Timer::after(Duration::from_millis(50)).await;
             continue;
         }
+        pending += read_len;
 
-        let vectorized: Vec<u8, BUFFER_SIZE> = match Vec::from_slice(&buffer[2..message_len]) {
-            Ok(vec) => vec,
-            Err(_) => {
-                error!("Failed to vectorize message");
-                continue;
-            }
-        };
+        // one read can hold several frames, or only part of one
+        loop {
+            let frame = match read_frame(&mut buffer[..pending]) {
+                Ok(frame) => frame,
+                Err(FrameError::Incomplete) if pending < BUFFER_SIZE => break,
+                Err(e) => {
+                    error!("Dropped a frame that doesn't fit the buffer: {:?}", e);
+                    pending = 0;
+                    break;
+                }
+            };
 
-        let message: String<BUFFER_SIZE> = match String::from_utf8(vectorized) {
-            Ok(string) => string,
-            Err(e) => {
-                error!("Failed to convert message to string: {:?}", e);
-                continue;
+            if frame.opcode == OPCODE_TEXT && frame.fin {
+                match core::str::from_utf8(&buffer[frame.payload]) {
+                    Ok(message) => {
+                        handle_message(
+                            message, websocket, controller, verifier, device_id, &mut rng, spawner,
+                        )
+                        .await
+                    }
+                    Err(e) => error!("Failed to convert message to string: {:?}", e),
+                }
+            } else {
+                warn!("Ignored a frame with opcode {}", frame.opcode);
             }
-        };
 
-        info!("Received message: {}", message);
+            buffer.copy_within(frame.len..pending, 0);
+            pending -= frame.len;
+        }
+    }
+}
 
-        let parsed: ServerMessage = match serde_json::from_str(&message) {
-            Ok(parsed) => parsed,
-            Err(e) => {
-                error!("Failed to parse message: {:?}", e);
-                continue;
-            }
-        };
+async fn handle_message(
+    message: &str,
+    websocket: &'static EmbassyWebSocket<'static>,
+    controller: &'static DioControllerMutex,
+    verifier: &'static VerifierMutex,
+    device_id: &'static DeviceId,
+    rng: &mut Rng,
+    spawner: Spawner,
+) {
+    info!("Received message: {}", message);
 
-        match parsed {
-            ServerMessage::ToggleZone(payload) => {
-                info!("Activating zone: {:?}, {}", payload.zone, payload.activate);
-                match spawner.spawn(toggle_zone(
-                    controller,
-                    payload.zone as usize,
-                    if payload.activate {
-                        Level::High
-                    } else {
-                        Level::Low
-                    },
-                )) {
-                    Ok(_) => (),
-                    Err(e) => error!("Failed to spawn toggle_zone task: {:?}", e),
-                };
+    let mut verifier_guard = verifier.lock().await;
+    let verified = match verifier_guard.as_mut() {
+        Some(verifier) => verifier.verify(message),
+        None => {
+            // the first message on a connection is the challenge to
+            // identify with
+            match serde_json::from_str(message) {
+                Ok(ServerHandshake::Challenge(payload)) => {
+                    *verifier_guard = identify(websocket, device_id, rng, payload.challenge).await;
+                }
+                Err(_) => error!("Dropped a message received before identifying"),
             }
+            return;
+        }
+    };
+    drop(verifier_guard);
+    let parsed: ServerMessage = match verified {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            error!("Rejected message: {}", e);
+            return;
+        }
+    };
+
+    match parsed {
+        ServerMessage::ToggleZone(payload) => {
+            info!("Activating zone: {:?}, {}", payload.zone, payload.activate);
+            match spawner.spawn(toggle_zone(
+                controller,
+                payload.zone as usize,
+                if payload.activate {
+                    Level::High
+                } else {
+                    Level::Low
+                },
+            )) {
+                Ok(_) => (),
+                Err(e) => error!("Failed to spawn toggle_zone task: {:?}", e),
+            };
         }
     }
 }
@@ -1,4 +1,20 @@
 use crate::dio_controller::DioController;
+use core::fmt::Write;
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+use heapless::String;
+use shared::Verifier;
 
 pub type DioControllerMutex = Mutex<CriticalSectionRawMutex, DioController>;
+/// Checks messages from the server, replaced on every connection.
+pub type VerifierMutex = Mutex<CriticalSectionRawMutex, Option<Verifier>>;
+
+/// The MAC address the server knows this controller by, written
+/// `AA:BB:CC:DD:EE:FF`.
+pub type DeviceId = String<17>;
+
+pub fn device_id(mac: [u8; 6]) -> DeviceId {
+    let mut device_id = DeviceId::new();
+    let [a, b, c, d, e, f] = mac;
+    let _ = write!(device_id, "{a:02X}:{b:02X}:{c:02X}:{d:02X}:{e:02X}:{f:02X}");
+    device_id
+}
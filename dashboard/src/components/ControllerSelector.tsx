@@ -0,0 +1,41 @@
+import { cn } from "@/lib/utils";
+import { ControllerState } from "@/types";
+
+interface Props {
+  controllers: ControllerState[];
+  selectedController?: string;
+  setSelectedController: (deviceId: string) => void;
+  className?: string;
+}
+
+export const ControllerSelector = ({
+  controllers,
+  selectedController,
+  setSelectedController,
+  className,
+}: Props) => {
+  // nothing to pick between with a single box
+  if (controllers.length < 2) {
+    return null;
+  }
+
+  return (
+    <div className={cn("w-fit flex gap-2", className)}>
+      {controllers.map((controller) => (
+        <button
+          key={controller.deviceId}
+          className={cn(
+            "px-4 py-1 rounded-full border-2 border-black text-md font-semibold",
+            controller.deviceId === selectedController
+              ? "bg-primary"
+              : "bg-zinc-200",
+            !controller.isConnected && "text-zinc-400"
+          )}
+          onClick={() => setSelectedController(controller.deviceId)}
+        >
+          {controller.name ?? controller.deviceId}
+        </button>
+      ))}
+    </div>
+  );
+};
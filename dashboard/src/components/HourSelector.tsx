@@ -1,8 +1,8 @@
-import { ActivePeriod, Zone } from "@/types";
+import { ActivePeriod, ZoneId } from "@/types";
 import { useState, useRef, useEffect } from "react";
 
 interface Props {
-  zone: Zone;
+  zone: ZoneId;
   setActivePeriod: (activePeriod: ActivePeriod) => any;
   activePeriod?: ActivePeriod;
 }
@@ -165,7 +165,7 @@ export const HourSelector = ({
             })}
             <div className="text-center select-none">
               <div className="text-sm font-bold text-zinc-800">
-                Zone {zone.replace("zone", "")}
+                Zone {zone.slice(zone.lastIndexOf("zone") + 4)}
               </div>
               <div className="text-lg font-bold text-zinc-900">
                 {Math.floor(durationMinutes)}m
@@ -3,3 +3,4 @@ export * from "./Tab";
 export * from "./HandledSwitch";
 export * from "./HourSelector";
 export * from "./DaySelector";
+export * from "./ControllerSelector";
@@ -1,6 +1,7 @@
 import {
   ClientMessage,
   ClientMessageResponse,
+  ControllerState,
   GetConfigPayload,
   Schedules,
   ServerMessage,
@@ -17,12 +18,42 @@ interface WebSocketContextType {
   ws: WebSocket | null;
   isClientConnected: boolean;
   isControllerConnected: boolean;
+  controllers: ControllerState[];
+  selectedController?: string;
+  setSelectedController: (deviceId: string) => void;
   sendMessage: <T extends ClientMessage>(message: T) => void;
   schedules: Schedules;
   staggerOn: boolean;
   staggerZones: boolean;
+  // the config revision changes have to be based on
+  revision: number;
 }
 
+// where the dashboard keeps the token minted with `websocket-server mint-token`
+const TOKEN_STORAGE_KEY = "websocketToken";
+
+// sent by the server when the handshake is refused
+const POLICY_VIOLATION = 1008;
+
+const getToken = () => {
+  const stored = localStorage.getItem(TOKEN_STORAGE_KEY);
+  if (stored) {
+    return stored;
+  }
+
+  const token = window.prompt("Access token") ?? "";
+  localStorage.setItem(TOKEN_STORAGE_KEY, token);
+  return token;
+};
+
+const getConfig = (websocket: WebSocket) => {
+  const getConfigPayload: GetConfigPayload = {
+    type: "getConfig",
+    payload: {},
+  };
+  websocket.send(JSON.stringify(getConfigPayload));
+};
+
 const WebSocketContext = createContext<WebSocketContextType | undefined>(
   undefined
 );
@@ -35,36 +66,43 @@ export const WebSocketProvider = ({ children }: WebSocketProviderProps) => {
   const [ws, setWs] = useState<WebSocket | null>(null);
   const [isClientConnected, setIsClientConnected] = useState(false);
   const [isControllerConnected, setIsControllerConnected] = useState(false);
+  const [controllers, setControllers] = useState<ControllerState[]>([]);
+  const [selectedController, setSelectedController] = useState<string>();
   const [schedules, setSchedules] = useState<Schedules>([]);
   const [staggerOn, setStaggerOn] = useState(false);
   const [staggerZones, setStaggerZones] = useState(false);
+  const [revision, setRevision] = useState(0);
   const [latestResponse, setLatestResponse] =
     useState<ClientMessageResponse | null>(null);
 
   const PORT = process.env.NEXT_PUBLIC_WEBSOCKET_PORT;
   const IP = process.env.NEXT_PUBLIC_WEBSOCKET_IP;
   const PATH = process.env.NEXT_PUBLIC_WEBSOCKET_PATH;
+  // "wss" when the server has a TLS listener
+  const PROTOCOL = process.env.NEXT_PUBLIC_WEBSOCKET_PROTOCOL ?? "ws";
 
   useEffect(() => {
-    const websocket = new WebSocket(`ws://${IP}:${PORT}${PATH}`);
+    const websocket = new WebSocket(`${PROTOCOL}://${IP}:${PORT}${PATH}`);
 
     websocket.onopen = () => {
       console.log("connected to server");
       setIsClientConnected(true);
 
-      // identifier string
-      websocket.send("user");
+      // authenticate
+      websocket.send(
+        JSON.stringify({ type: "authenticate", payload: { token: getToken() } })
+      );
 
-      // get config
-      const getConfigPayload: GetConfigPayload = {
-        type: "getConfig",
-        payload: {},
-      };
-      websocket.send(JSON.stringify(getConfigPayload));
+      getConfig(websocket);
     };
 
-    websocket.onclose = () => {
+    websocket.onclose = (event) => {
       console.log("disconnected from server");
+      if (event.code === POLICY_VIOLATION) {
+        // ask for a new token next time
+        console.error("Authentication failed: ", event.reason);
+        localStorage.removeItem(TOKEN_STORAGE_KEY);
+      }
       setIsClientConnected(false);
     };
 
@@ -78,22 +116,65 @@ export const WebSocketProvider = ({ children }: WebSocketProviderProps) => {
         | ServerMessage
         | ClientMessageResponse;
       switch (data.type) {
+        case "challenge":
+          break;
         case "controllerHeartbeat":
           console.log("Controller heartbeat: ", data.payload);
           const isControllerConnected = data.payload.isControllerConnected;
           setIsControllerConnected(isControllerConnected);
+          setControllers(data.payload.controllers);
+          setSelectedController(
+            (selected) => selected ?? data.payload.controllers[0]?.deviceId
+          );
+          break;
+        case "flowAlert":
+          console.warn("Flow alert: ", data.payload.alert);
+          break;
+        case "freezeGuard":
+          console.warn("Freeze guard: ", data.payload);
+          break;
+        case "pairingRequest":
+          console.warn("Controller awaiting approval: ", data.payload.controller);
           break;
         case "getConfigResponse":
           console.log("Config: ", data.payload);
-          const { schedules, staggerOn, staggerZones } = data.payload;
+          const { revision, schedules, staggerOn, staggerZones } =
+            data.payload;
+          setRevision(revision);
           setSchedules(schedules);
           setStaggerOn(staggerOn);
           setStaggerZones(staggerZones);
           break;
+        case "setScheduleResponse":
+        case "setRestrictionStageResponse":
+        case "approveControllerResponse":
+        case "createScheduleResponse":
+        case "updateScheduleResponse":
+        case "deleteScheduleResponse":
+        case "duplicateScheduleResponse":
+        case "reorderSchedulesResponse":
+        case "setRainDelayResponse":
+        case "setSeasonalAdjustmentResponse":
+          setRevision(data.payload.revision);
+          setLatestResponse(data);
+          break;
+        case "conflictResponse":
+          // someone else saved first, start over from their config
+          console.warn("Conflict: ", data.payload.error);
+          getConfig(websocket);
+          setLatestResponse(data);
+          break;
         case "keepAliveResponse":
         case "toggleZoneResponse":
         case "statusResponse":
-        case "setScheduleResponse":
+        case "getUpcomingRunsResponse":
+        case "getHistoryResponse":
+        case "getUsageReportResponse":
+        case "reportSoilMoistureResponse":
+        case "getPendingControllersResponse":
+        case "rejectControllerResponse":
+        case "forbiddenResponse":
+        case "getAuditLogResponse":
           setLatestResponse(data);
           break;
         default:
@@ -106,7 +187,7 @@ export const WebSocketProvider = ({ children }: WebSocketProviderProps) => {
     return () => {
       websocket.close();
     };
-  }, [IP, PORT, PATH]);
+  }, [PROTOCOL, IP, PORT, PATH]);
 
   const sendMessage = <T extends ClientMessage>(message: T) => {
     if (ws && ws.readyState === WebSocket.OPEN) {
@@ -122,10 +203,14 @@ export const WebSocketProvider = ({ children }: WebSocketProviderProps) => {
         ws,
         isClientConnected,
         isControllerConnected,
+        controllers,
+        selectedController,
+        setSelectedController,
         sendMessage,
         schedules,
         staggerOn,
         staggerZones,
+        revision,
       }}
     >
       {children}
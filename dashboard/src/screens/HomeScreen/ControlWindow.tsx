@@ -1,7 +1,12 @@
-import { HandledSwitch } from "@/components";
+import { ControllerSelector, HandledSwitch } from "@/components";
 import { useWebSocket } from "@/contexts";
 import { cn } from "@/lib/utils";
-import { ToggleZonePayload, ToggleZoneResponse } from "@/types";
+import {
+  ToggleZonePayload,
+  ToggleZoneResponse,
+  Zone,
+  zoneId,
+} from "@/types";
 import { useEffect, useState } from "react";
 
 interface ControlItemProps {
@@ -31,9 +36,19 @@ const ControlItem = ({
 );
 
 export const ControlWindow = () => {
-  const { sendMessage, isClientConnected, isControllerConnected } =
-    useWebSocket();
-  const isConnected = isClientConnected && isControllerConnected;
+  const {
+    sendMessage,
+    isClientConnected,
+    controllers,
+    selectedController,
+    setSelectedController,
+  } = useWebSocket();
+  const isConnected =
+    isClientConnected &&
+    controllers.some(
+      (controller) =>
+        controller.deviceId === selectedController && controller.isConnected
+    );
 
   const [isZone1On, setIsZone1On] = useState(false);
   const [isZone2On, setIsZone2On] = useState(false);
@@ -74,12 +89,20 @@ export const ControlWindow = () => {
 
     sendMessage<ToggleZonePayload>({
       type: "toggleZone",
-      payload: { zone, activate: isOn },
+      payload: {
+        zone: zoneId(selectedController ?? "", `zone${zone}` as Zone),
+        activate: isOn,
+      },
     });
   };
 
   return (
     <div className="w-full flex-1 flex flex-col justify-center items-center gap-6">
+      <ControllerSelector
+        controllers={controllers}
+        selectedController={selectedController}
+        setSelectedController={setSelectedController}
+      />
       <div className="w-fit grid grid-cols-3 gap-x-32 gap-y-24 justify-items-center">
         <ControlItem
           title="Zone 1"
@@ -1,5 +1,6 @@
 import {
   Button,
+  ControllerSelector,
   DaySelector,
   HourSelector,
   Switch,
@@ -13,7 +14,9 @@ import {
   Schedules,
   SetSchedulePayload,
   Zone,
+  ZoneId,
   ActivePeriod,
+  zoneId,
 } from "@/types";
 import debounce from "debounce";
 import {
@@ -32,7 +35,12 @@ export const ScheduleWindow = () => {
     sendMessage,
     isClientConnected,
     schedules: serverSchedules,
+    revision,
+    controllers,
+    selectedController,
+    setSelectedController,
   } = useWebSocket();
+  const controller = selectedController ?? "";
 
   const [schedules, setSchedules] = useState<Schedules>(serverSchedules || []);
 
@@ -58,7 +66,7 @@ export const ScheduleWindow = () => {
     if (isClientConnected) {
       sendMessage<SetSchedulePayload>({
         type: "setSchedule",
-        payload: { schedules },
+        payload: { schedules, revision },
       });
     }
   };
@@ -182,7 +190,7 @@ export const ScheduleWindow = () => {
     handleUpdateSchedule(updatedSchedule);
   };
 
-  const getActivePeriodForZone = (zone: Zone): ActivePeriod | undefined => {
+  const getActivePeriodForZone = (zone: ZoneId): ActivePeriod | undefined => {
     return selectedSchedule?.activePeriods.find(
       (period) => period.zone === zone
     );
@@ -314,35 +322,52 @@ export const ScheduleWindow = () => {
         {/* <span className="text-lg font-semibold w-full text-center">
           Run Times
         </span> */}
+        <ControllerSelector
+          controllers={controllers}
+          selectedController={selectedController}
+          setSelectedController={setSelectedController}
+        />
         <div className="w-fit grid grid-cols-3 gap-4">
           <HourSelector
-            zone={Zone.Zone1}
-            activePeriod={getActivePeriodForZone(Zone.Zone1)}
+            zone={zoneId(controller, Zone.Zone1)}
+            activePeriod={getActivePeriodForZone(
+              zoneId(controller, Zone.Zone1)
+            )}
             setActivePeriod={handleUpdateActivePeriod}
           />
           <HourSelector
-            zone={Zone.Zone2}
-            activePeriod={getActivePeriodForZone(Zone.Zone2)}
+            zone={zoneId(controller, Zone.Zone2)}
+            activePeriod={getActivePeriodForZone(
+              zoneId(controller, Zone.Zone2)
+            )}
             setActivePeriod={handleUpdateActivePeriod}
           />
           <HourSelector
-            zone={Zone.Zone3}
-            activePeriod={getActivePeriodForZone(Zone.Zone3)}
+            zone={zoneId(controller, Zone.Zone3)}
+            activePeriod={getActivePeriodForZone(
+              zoneId(controller, Zone.Zone3)
+            )}
             setActivePeriod={handleUpdateActivePeriod}
           />
           <HourSelector
-            zone={Zone.Zone4}
-            activePeriod={getActivePeriodForZone(Zone.Zone4)}
+            zone={zoneId(controller, Zone.Zone4)}
+            activePeriod={getActivePeriodForZone(
+              zoneId(controller, Zone.Zone4)
+            )}
             setActivePeriod={handleUpdateActivePeriod}
           />
           <HourSelector
-            zone={Zone.Zone5}
-            activePeriod={getActivePeriodForZone(Zone.Zone5)}
+            zone={zoneId(controller, Zone.Zone5)}
+            activePeriod={getActivePeriodForZone(
+              zoneId(controller, Zone.Zone5)
+            )}
             setActivePeriod={handleUpdateActivePeriod}
           />
           <HourSelector
-            zone={Zone.Zone6}
-            activePeriod={getActivePeriodForZone(Zone.Zone6)}
+            zone={zoneId(controller, Zone.Zone6)}
+            activePeriod={getActivePeriodForZone(
+              zoneId(controller, Zone.Zone6)
+            )}
             setActivePeriod={handleUpdateActivePeriod}
           />
         </div>
@@ -1,4 +1,5 @@
-import { Schedules } from "./schedules";
+import { Day, Schedule, Schedules, ZoneId } from "./schedules";
+import { ControllerState, PendingController } from "./serverMessages";
 
 interface BaseMessage {
   type: string;
@@ -20,7 +21,7 @@ export interface KeepAliveResponse extends BaseMessage {
 export interface ToggleZonePayload extends BaseMessage {
   type: "toggleZone";
   payload: {
-    zone: number;
+    zone: ZoneId;
     activate: boolean;
   };
 }
@@ -39,10 +40,20 @@ export interface StatusPayload extends BaseMessage {
   payload: {};
 }
 
+export interface LastSkip {
+  schedule: string;
+  timestamp: string;
+  reason: SkipReason;
+}
+
 export interface StatusResponse extends BaseMessage {
   type: "statusResponse";
   payload: {
     isControllerConnected: boolean;
+    rainSensorActive: boolean;
+    controllers: ControllerState[];
+    freezeGuard?: { temp: number; since: string } | null;
+    lastSkip?: LastSkip | null;
   };
 }
 
@@ -51,14 +62,130 @@ export interface SetSchedulePayload extends BaseMessage {
   type: "setSchedule";
   payload: {
     schedules: Schedules;
+    revision: number;
   };
 }
 
+export interface FieldError {
+  field: string;
+  message: string;
+}
+
+export interface ProgramWindow {
+  schedule: string;
+  day: Day;
+  startMinutes: number;
+  endMinutes: number;
+}
+
+export interface ScheduleConflict {
+  first: ProgramWindow;
+  second: ProgramWindow;
+}
+
 export interface SetScheduleResponse extends BaseMessage {
   type: "setScheduleResponse";
   payload: {
     success: boolean;
-    error?: string;
+    errors: FieldError[];
+    conflicts: ScheduleConflict[];
+    revision: number;
+  };
+}
+
+// Create Schedule
+export interface CreateSchedulePayload extends BaseMessage {
+  type: "createSchedule";
+  payload: {
+    schedule: Schedule;
+    revision: number;
+  };
+}
+
+export interface CreateScheduleResponse extends BaseMessage {
+  type: "createScheduleResponse";
+  payload: {
+    success: boolean;
+    id?: string;
+    errors: FieldError[];
+    conflicts: ScheduleConflict[];
+    revision: number;
+  };
+}
+
+// Update Schedule
+export interface UpdateSchedulePayload extends BaseMessage {
+  type: "updateSchedule";
+  payload: {
+    schedule: Schedule;
+    revision: number;
+  };
+}
+
+export interface UpdateScheduleResponse extends BaseMessage {
+  type: "updateScheduleResponse";
+  payload: {
+    success: boolean;
+    errors: FieldError[];
+    conflicts: ScheduleConflict[];
+    revision: number;
+  };
+}
+
+// Delete Schedule
+export interface DeleteSchedulePayload extends BaseMessage {
+  type: "deleteSchedule";
+  payload: {
+    id: string;
+    revision: number;
+  };
+}
+
+export interface DeleteScheduleResponse extends BaseMessage {
+  type: "deleteScheduleResponse";
+  payload: {
+    success: boolean;
+    errors: FieldError[];
+    revision: number;
+  };
+}
+
+// Duplicate Schedule
+export interface DuplicateSchedulePayload extends BaseMessage {
+  type: "duplicateSchedule";
+  payload: {
+    id: string;
+    name?: string;
+    revision: number;
+  };
+}
+
+export interface DuplicateScheduleResponse extends BaseMessage {
+  type: "duplicateScheduleResponse";
+  payload: {
+    success: boolean;
+    id?: string;
+    errors: FieldError[];
+    conflicts: ScheduleConflict[];
+    revision: number;
+  };
+}
+
+// Reorder Schedules
+export interface ReorderSchedulesPayload extends BaseMessage {
+  type: "reorderSchedules";
+  payload: {
+    ids: string[];
+    revision: number;
+  };
+}
+
+export interface ReorderSchedulesResponse extends BaseMessage {
+  type: "reorderSchedulesResponse";
+  payload: {
+    success: boolean;
+    errors: FieldError[];
+    revision: number;
   };
 }
 
@@ -68,12 +195,334 @@ export interface GetConfigPayload extends BaseMessage {
   payload: {};
 }
 
+export interface DerivedDuration {
+  schedule: string;
+  zone: ZoneId;
+  durationMinutes: number;
+}
+
+export type RestrictionRule =
+  | { type: "forbiddenWindow"; startMinutes: number; endMinutes: number }
+  | { type: "addressParity" }
+  | { type: "maxDaysPerWeek"; days: number };
+
+export interface RestrictionStage {
+  stage: number;
+  rules: RestrictionRule[];
+}
+
+export interface Restrictions {
+  addressNumber: number | null;
+  rules: RestrictionRule[];
+  stages: RestrictionStage[];
+  activeStage: number;
+}
+
 export interface GetConfigResponse extends BaseMessage {
   type: "getConfigResponse";
   payload: {
+    revision: number;
     schedules: Schedules;
     staggerOn: boolean;
     staggerZones: boolean;
+    derivedDurations: DerivedDuration[];
+    restrictions: Restrictions;
+    seasonalAdjustmentPercent: number;
+    rainDelayUntil: string | null;
+  };
+}
+
+// Get Upcoming Runs
+export interface GetUpcomingRunsPayload extends BaseMessage {
+  type: "getUpcomingRuns";
+  payload: {
+    from: string;
+    days: number;
+  };
+}
+
+export interface ZoneRun {
+  schedule: string;
+  open: string;
+  close: string;
+}
+
+export interface SkipReason {
+  type: string;
+  [key: string]: unknown;
+}
+
+export interface SkippedRun {
+  schedule: string;
+  start: string;
+  reason: SkipReason;
+}
+
+export interface GetUpcomingRunsResponse extends BaseMessage {
+  type: "getUpcomingRunsResponse";
+  payload: {
+    zones: Partial<Record<ZoneId, ZoneRun[]>>;
+    skipped: SkippedRun[];
+    error?: string;
+  };
+}
+
+// Get History
+export interface GetHistoryPayload extends BaseMessage {
+  type: "getHistory";
+  payload: {
+    from?: string;
+    to?: string;
+    zones?: ZoneId[];
+    offset?: number;
+    limit?: number;
+  };
+}
+
+export interface HistoryEvent {
+  type: string;
+  [key: string]: unknown;
+}
+
+export interface HistoryEntry {
+  id: number;
+  timestamp: string;
+  event: HistoryEvent;
+}
+
+export interface GetHistoryResponse extends BaseMessage {
+  type: "getHistoryResponse";
+  payload: {
+    entries: HistoryEntry[];
+    total: number;
+    error?: string;
+  };
+}
+
+// Get Usage Report
+export interface GetUsageReportPayload extends BaseMessage {
+  type: "getUsageReport";
+  payload: {
+    from: string;
+    to: string;
+    includeCsv?: boolean;
+  };
+}
+
+export interface PeriodUsage {
+  minutes: number;
+  volume: number | null;
+}
+
+export interface RunUsage extends PeriodUsage {
+  source: { type: string; schedule?: string };
+  open: string;
+  close: string;
+}
+
+export interface ZoneUsage {
+  flowRate: number | null;
+  total: PeriodUsage;
+  runs: RunUsage[];
+  daily: Record<string, PeriodUsage>;
+  weekly: Record<string, PeriodUsage>;
+}
+
+export interface UsageReport {
+  unit: "gallons" | "liters";
+  zones: Partial<Record<ZoneId, ZoneUsage>>;
+}
+
+export interface GetUsageReportResponse extends BaseMessage {
+  type: "getUsageReportResponse";
+  payload: {
+    report: UsageReport | null;
+    csv: string | null;
+    error?: string;
+  };
+}
+
+// Report Soil Moisture
+export interface ReportSoilMoisturePayload extends BaseMessage {
+  type: "reportSoilMoisture";
+  payload: {
+    zone: ZoneId;
+    percent: number;
+  };
+}
+
+export interface ReportSoilMoistureResponse extends BaseMessage {
+  type: "reportSoilMoistureResponse";
+  payload: {
+    success: boolean;
+    error?: string;
+  };
+}
+
+// Set Restriction Stage
+export interface SetRestrictionStagePayload extends BaseMessage {
+  type: "setRestrictionStage";
+  payload: {
+    stage: number;
+    revision: number;
+  };
+}
+
+export interface SetRestrictionStageResponse extends BaseMessage {
+  type: "setRestrictionStageResponse";
+  payload: {
+    success: boolean;
+    error?: string;
+    violations: FieldError[];
+    revision: number;
+  };
+}
+
+// Set Rain Delay
+export interface SetRainDelayPayload extends BaseMessage {
+  type: "setRainDelay";
+  payload: {
+    until: string | null;
+    revision: number;
+  };
+}
+
+export interface SetRainDelayResponse extends BaseMessage {
+  type: "setRainDelayResponse";
+  payload: {
+    success: boolean;
+    error?: string;
+    revision: number;
+  };
+}
+
+// Set Seasonal Adjustment
+export interface SetSeasonalAdjustmentPayload extends BaseMessage {
+  type: "setSeasonalAdjustment";
+  payload: {
+    percent: number;
+    revision: number;
+  };
+}
+
+export interface SetSeasonalAdjustmentResponse extends BaseMessage {
+  type: "setSeasonalAdjustmentResponse";
+  payload: {
+    success: boolean;
+    error?: string;
+    revision: number;
+  };
+}
+
+// Get Pending Controllers
+export interface GetPendingControllersPayload extends BaseMessage {
+  type: "getPendingControllers";
+  payload: {};
+}
+
+export interface GetPendingControllersResponse extends BaseMessage {
+  type: "getPendingControllersResponse";
+  payload: {
+    controllers: PendingController[];
+  };
+}
+
+// Approve Controller
+export interface ApproveControllerPayload extends BaseMessage {
+  type: "approveController";
+  payload: {
+    deviceId: string;
+    name?: string;
+    // the secret flashed into the controller
+    secret: string;
+    revision: number;
+  };
+}
+
+export interface ApproveControllerResponse extends BaseMessage {
+  type: "approveControllerResponse";
+  payload: {
+    success: boolean;
+    error?: string;
+    revision: number;
+  };
+}
+
+// Reject Controller
+export interface RejectControllerPayload extends BaseMessage {
+  type: "rejectController";
+  payload: {
+    deviceId: string;
+  };
+}
+
+export interface RejectControllerResponse extends BaseMessage {
+  type: "rejectControllerResponse";
+  payload: {
+    success: boolean;
+    error?: string;
+  };
+}
+
+// Get Audit Log
+export interface GetAuditLogPayload extends BaseMessage {
+  type: "getAuditLog";
+  payload: {
+    offset?: number;
+    limit?: number;
+  };
+}
+
+export interface ConfigChange {
+  path: string;
+  before?: unknown;
+  after?: unknown;
+}
+
+export interface AuditAction {
+  type: string;
+  [key: string]: unknown;
+}
+
+export interface AuditEntry {
+  id: number;
+  timestamp: string;
+  principal: string;
+  session: number;
+  address: string;
+  action: AuditAction;
+}
+
+export interface GetAuditLogResponse extends BaseMessage {
+  type: "getAuditLogResponse";
+  payload: {
+    entries: AuditEntry[];
+    total: number;
+    error?: string;
+  };
+}
+
+// Forbidden
+export type Role = "viewer" | "operator" | "admin";
+
+export interface ForbiddenResponse extends BaseMessage {
+  type: "forbiddenResponse";
+  payload: {
+    request: ClientMessage["type"];
+    role: Role;
+    requiredRole: Role;
+    error: string;
+  };
+}
+
+// Conflict
+export interface ConflictResponse extends BaseMessage {
+  type: "conflictResponse";
+  payload: {
+    request: ClientMessage["type"];
+    revision: number;
+    currentRevision: number;
+    error: string;
   };
 }
 
@@ -83,11 +532,45 @@ export type ClientMessage =
   | ToggleZonePayload
   | StatusPayload
   | SetSchedulePayload
-  | GetConfigPayload;
+  | GetConfigPayload
+  | GetUpcomingRunsPayload
+  | GetHistoryPayload
+  | GetUsageReportPayload
+  | ReportSoilMoisturePayload
+  | SetRestrictionStagePayload
+  | GetPendingControllersPayload
+  | ApproveControllerPayload
+  | RejectControllerPayload
+  | GetAuditLogPayload
+  | CreateSchedulePayload
+  | UpdateSchedulePayload
+  | DeleteSchedulePayload
+  | DuplicateSchedulePayload
+  | ReorderSchedulesPayload
+  | SetRainDelayPayload
+  | SetSeasonalAdjustmentPayload;
 
 export type ClientMessageResponse =
   | KeepAliveResponse
   | ToggleZoneResponse
   | StatusResponse
   | SetScheduleResponse
-  | GetConfigResponse;
+  | GetConfigResponse
+  | GetUpcomingRunsResponse
+  | GetHistoryResponse
+  | GetUsageReportResponse
+  | ReportSoilMoistureResponse
+  | SetRestrictionStageResponse
+  | GetPendingControllersResponse
+  | ApproveControllerResponse
+  | RejectControllerResponse
+  | ForbiddenResponse
+  | GetAuditLogResponse
+  | ConflictResponse
+  | CreateScheduleResponse
+  | UpdateScheduleResponse
+  | DeleteScheduleResponse
+  | DuplicateScheduleResponse
+  | ReorderSchedulesResponse
+  | SetRainDelayResponse
+  | SetSeasonalAdjustmentResponse;
@@ -17,17 +17,39 @@ export enum Zone {
   Zone6 = "zone6",
 }
 
+// a zone on one controller, like "AA:BB:CC:DD:EE:FF/zone1"
+export type ZoneId = `${string}/${Zone}`;
+
+export const zoneId = (controller: string, zone: Zone): ZoneId =>
+  `${controller}/${zone}`;
+
+export type MoistureRule =
+  | { type: "skipAbove"; percent: number }
+  | { type: "shortenToward"; percent: number };
+
 export interface ActivePeriod {
-  zone: Zone;
+  zone: ZoneId;
   durationMinutes: number;
+  moisture?: MoistureRule | null;
 }
 
+export type SkipRule =
+  | { type: "recentRain"; mm: number }
+  | { type: "rainForecast"; percent: number }
+  | { type: "wind"; speed: number }
+  | { type: "freezing" };
+
 export interface Schedule {
+  // picked by the server, so unset until it's seen the schedule
+  id?: string;
   name: string;
   days: Day[];
   activePeriods: ActivePeriod[];
   startTimeMinutes: number;
   isActive: boolean;
+  staggerSecs?: number | null;
+  skipRules?: SkipRule[];
+  obeyRainSensor?: boolean;
 }
 
 export type Schedules = Schedule[];
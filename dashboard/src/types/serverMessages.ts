@@ -1,13 +1,70 @@
+import { ZoneId } from "./schedules";
+
 export interface BaseMessage {
   type: string;
   payload: Record<string, unknown>;
 }
 
+// sent first on every connection, only controllers answer it
+export interface ChallengePayload extends BaseMessage {
+  type: "challenge";
+  payload: {
+    challenge: string;
+  };
+}
+
+export interface ControllerState {
+  deviceId: string;
+  name: string | null;
+  isConnected: boolean;
+  rainSensorActive: boolean;
+  flow: { litersPerMinute: number; totalLiters: number } | null;
+}
+
 export interface ControllerHeartbeatPayload extends BaseMessage {
   type: "controllerHeartbeat";
   payload: {
     isControllerConnected: boolean;
+    controllers: ControllerState[];
+  };
+}
+
+export type FlowAlert =
+  | { type: "highFlow"; zones: ZoneId[]; flow: number; expected: number }
+  | { type: "leak"; flow: number };
+
+export interface FlowAlertPayload extends BaseMessage {
+  type: "flowAlert";
+  payload: {
+    alert: FlowAlert;
+  };
+}
+
+export interface FreezeGuardPayload extends BaseMessage {
+  type: "freezeGuard";
+  payload: {
+    active: boolean;
+    temp: number;
+  };
+}
+
+export interface PendingController {
+  deviceId: string;
+  address: string;
+  firstSeen: string;
+  lastSeen: string;
+}
+
+export interface PairingRequestPayload extends BaseMessage {
+  type: "pairingRequest";
+  payload: {
+    controller: PendingController;
   };
 }
 
-export type ServerMessage = ControllerHeartbeatPayload;
+export type ServerMessage =
+  | ChallengePayload
+  | ControllerHeartbeatPayload
+  | FlowAlertPayload
+  | FreezeGuardPayload
+  | PairingRequestPayload;
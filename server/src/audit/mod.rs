@@ -0,0 +1,322 @@
+use crate::config::Config;
+use crate::error::ServerError;
+use crate::types::{AuditMutex, DeviceId, SessionId, ZoneId};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+
+pub const AUDIT_FILE_PATH: &str = ".audit.jsonl";
+/// The log is rotated once it grows past this.
+pub const MAX_AUDIT_FILE_BYTES: u64 = 1024 * 1024;
+/// Rotated logs kept besides the current one, `.audit.1.jsonl` the newest.
+pub const ROTATED_AUDIT_FILES: usize = 4;
+
+pub const REDACTED: &str = "<redacted>";
+/// Config fields whose values never make it into the log.
+const REDACTED_FIELDS: [&str; 2] = ["secret", "hash"];
+
+/// Who made a change, and from where.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Actor {
+    /// The name of the token the session authenticated with.
+    pub principal: String,
+    pub session: SessionId,
+    pub address: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigChange {
+    /// Where in the config, like `schedules[0].days`.
+    pub path: String,
+    /// Unset when the value was added.
+    pub before: Option<Value>,
+    /// Unset when the value was removed.
+    pub after: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum AuditAction {
+    ZoneToggled {
+        zone: ZoneId,
+        activate: bool,
+    },
+    SoilMoistureReported {
+        zone: ZoneId,
+        percent: f64,
+    },
+    ConfigChanged {
+        field: String,
+        changes: Vec<ConfigChange>,
+    },
+    ControllerRejected {
+        controller: DeviceId,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub id: u64,
+    pub timestamp: DateTime<Local>,
+    #[serde(flatten)]
+    pub actor: Actor,
+    pub action: AuditAction,
+}
+
+/// Append-only log of changes made by users, one JSON entry per line,
+/// rotated to keep a bounded amount on disk.
+pub struct Audit {
+    path: String,
+    next_id: u64,
+}
+
+impl Audit {
+    pub fn open(path: &str) -> Result<Self, ServerError> {
+        let last_id = read_all(path)?.last().map(|entry| entry.id);
+
+        Ok(Self {
+            path: path.to_string(),
+            next_id: last_id.map_or(0, |id| id + 1),
+        })
+    }
+
+    pub fn record(&mut self, actor: &Actor, action: AuditAction) -> Result<(), ServerError> {
+        let entry = AuditEntry {
+            id: self.next_id,
+            timestamp: Local::now(),
+            actor: actor.clone(),
+            action,
+        };
+
+        if fs::metadata(&self.path).is_ok_and(|metadata| metadata.len() >= MAX_AUDIT_FILE_BYTES) {
+            self.rotate()?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|_| ServerError::FailedToCreateFile(self.path.clone()))?;
+        writeln!(file, "{}", serde_json::to_string(&entry).unwrap())
+            .map_err(|_| ServerError::FailedToWriteToFile(self.path.clone()))?;
+
+        self.next_id += 1;
+        Ok(())
+    }
+
+    /// Entries newest first, skipping `offset` and returning at most
+    /// `limit`, along with the total kept.
+    pub fn query(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<AuditEntry>, usize), ServerError> {
+        let entries = read_all(&self.path)?;
+        let total = entries.len();
+        let page = entries.into_iter().rev().skip(offset).take(limit).collect();
+        Ok((page, total))
+    }
+
+    // shifts every log down one, dropping the oldest
+    fn rotate(&self) -> Result<(), ServerError> {
+        for n in (1..ROTATED_AUDIT_FILES).rev() {
+            let from = rotated_path(&self.path, n);
+            if fs::metadata(&from).is_ok() {
+                let to = rotated_path(&self.path, n + 1);
+                fs::rename(&from, &to).map_err(|_| ServerError::FailedToWriteToFile(to))?;
+            }
+        }
+        let to = rotated_path(&self.path, 1);
+        fs::rename(&self.path, &to).map_err(|_| ServerError::FailedToWriteToFile(to))
+    }
+}
+
+/// `.audit.jsonl` rotated `n` times is `.audit.n.jsonl`, and the same for
+/// the history log.
+pub fn rotated_path(path: &str, n: usize) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, extension)) => format!("{stem}.{n}.{extension}"),
+        None => format!("{path}.{n}"),
+    }
+}
+
+/// Every entry still on disk, oldest first.
+fn read_all(path: &str) -> Result<Vec<AuditEntry>, ServerError> {
+    let mut entries = vec![];
+    for n in (1..=ROTATED_AUDIT_FILES).rev() {
+        entries.extend(read_entries(&rotated_path(path, n))?);
+    }
+    entries.extend(read_entries(path)?);
+    Ok(entries)
+}
+
+fn read_entries(path: &str) -> Result<Vec<AuditEntry>, ServerError> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) => match e.kind() {
+            std::io::ErrorKind::NotFound => return Ok(vec![]),
+            _ => return Err(ServerError::FailedToReadFile(path.to_string())),
+        },
+    };
+
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|_| ServerError::FailedToReadFile(path.to_string()))?;
+        // a line torn by a crash mid-write is dropped rather than failing the log
+        if let Ok(entry) = serde_json::from_str(&line) {
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Records an action, logging rather than failing if the log can't be
+/// written.
+pub async fn record(audit: &AuditMutex, actor: &Actor, action: AuditAction) {
+    if let Err(e) = audit.lock().await.record(actor, action) {
+        println!("Failed to record audit entry: {e}");
+    }
+}
+
+/// Every value that differs between `before` and `after`, with secrets
+/// redacted.
+pub fn config_diff(before: &Config, after: &Config) -> Vec<ConfigChange> {
+    let before = serde_json::to_value(before).unwrap();
+    let after = serde_json::to_value(after).unwrap();
+    let mut changes = vec![];
+    diff(String::new(), Some(&before), Some(&after), &mut changes);
+    changes
+}
+
+fn diff(
+    path: String,
+    before: Option<&Value>,
+    after: Option<&Value>,
+    changes: &mut Vec<ConfigChange>,
+) {
+    match (before, after) {
+        (Some(Value::Object(before)), Some(Value::Object(after))) => {
+            let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                if REDACTED_FIELDS.contains(&key.as_str()) {
+                    if before.get(key) != after.get(key) {
+                        let redacted = |value: Option<&Value>| value.map(|_| REDACTED.into());
+                        changes.push(ConfigChange {
+                            path,
+                            before: redacted(before.get(key)),
+                            after: redacted(after.get(key)),
+                        });
+                    }
+                    continue;
+                }
+                diff(path, before.get(key), after.get(key), changes);
+            }
+        }
+        (Some(Value::Array(before)), Some(Value::Array(after))) => {
+            for index in 0..before.len().max(after.len()) {
+                diff(
+                    format!("{path}[{index}]"),
+                    before.get(index),
+                    after.get(index),
+                    changes,
+                );
+            }
+        }
+        _ if before == after => {}
+        _ => changes.push(ConfigChange {
+            path,
+            before: before.cloned().map(redact),
+            after: after.cloned().map(redact),
+        }),
+    }
+}
+
+fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| {
+                    if REDACTED_FIELDS.contains(&key.as_str()) {
+                        (key, REDACTED.into())
+                    } else {
+                        (key, redact(value))
+                    }
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(redact).collect()),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::ControllerConfig;
+
+    #[test]
+    fn diffs_changed_values_only() {
+        let before = Config::default();
+        let mut after = before.clone();
+        after.stagger_on = true;
+        after.restrictions.active_stage = 2;
+
+        assert_eq!(
+            config_diff(&before, &after),
+            vec![
+                ConfigChange {
+                    path: "restrictions.activeStage".to_string(),
+                    before: Some(0.into()),
+                    after: Some(2.into()),
+                },
+                ConfigChange {
+                    path: "stagger_on".to_string(),
+                    before: Some(false.into()),
+                    after: Some(true.into()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn redacts_secrets() {
+        let before = Config::default();
+        let mut after = before.clone();
+        after.controllers.push(ControllerConfig {
+            device_id: DeviceId::UNKNOWN,
+            name: None,
+            secret: "hunter2".to_string(),
+        });
+
+        let changes = config_diff(&before, &after);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "controllers[0]");
+        assert_eq!(changes[0].after.as_ref().unwrap()["secret"], REDACTED);
+
+        let mut rotated = after.clone();
+        rotated.controllers[0].secret = "correct horse".to_string();
+        let changes = config_diff(&after, &rotated);
+        assert_eq!(
+            changes,
+            vec![ConfigChange {
+                path: "controllers[0].secret".to_string(),
+                before: Some(REDACTED.into()),
+                after: Some(REDACTED.into()),
+            }]
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use crate::config::Config;
+use crate::error::ServerError;
+
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Display;
+use std::str::FromStr;
+
+const TOKEN_BYTES: usize = 32;
+
+/// A dashboard token. Only the hash is kept, so a leaked config can't be
+/// used to connect.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenConfig {
+    /// Who the token was minted for, shown in the logs.
+    pub name: String,
+    /// SHA-256 of the token, hex encoded.
+    pub hash: String,
+    /// Tokens minted before roles existed keep full access.
+    #[serde(default = "default_role")]
+    pub role: Role,
+}
+
+/// What a user can do, each role allowing everything the ones before it do.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum Role {
+    /// Reads status, history and config.
+    Viewer,
+    /// Opens and closes zones.
+    Operator,
+    /// Changes schedules and config, and approves controllers.
+    Admin,
+}
+
+impl Role {
+    pub fn allows(&self, required: Role) -> bool {
+        *self >= required
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "viewer"),
+            Role::Operator => write!(f, "operator"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(ServerError::InvalidRole(s.to_string())),
+        }
+    }
+}
+
+fn default_role() -> Role {
+    Role::Admin
+}
+
+/// The user behind a session.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub name: String,
+    pub role: Role,
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+/// Who `token` belongs to, if it's configured.
+pub fn authenticate(config: &Config, token: &str) -> Option<Principal> {
+    let hash = hash_token(token);
+    config
+        .tokens
+        .iter()
+        .find(|candidate| secrets_match(&candidate.hash, &hash))
+        .map(|token| Principal {
+            name: token.name.clone(),
+            role: token.role,
+        })
+}
+
+/// Adds a new random token for `name` to `config` and returns it. This is
+/// the only time the token itself is available.
+pub fn mint_token(config: &mut Config, name: &str, role: Role) -> Result<String, ServerError> {
+    if name.is_empty() {
+        return Err(ServerError::InvalidTokenName(name.to_string()));
+    }
+    if config.tokens.iter().any(|token| token.name == name) {
+        return Err(ServerError::DuplicateTokenName(name.to_string()));
+    }
+
+    let mut bytes = [0; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    let token = to_hex(&bytes);
+    config.tokens.push(TokenConfig {
+        name: name.to_string(),
+        hash: hash_token(&token),
+        role,
+    });
+    Ok(token)
+}
+
+// compares every byte so the time taken doesn't give away how much matched
+pub fn secrets_match(expected: &str, presented: &str) -> bool {
+    expected.len() == presented.len()
+        && expected
+            .bytes()
+            .zip(presented.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_tokens_with_sha256() {
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn keeps_only_the_hash_of_minted_tokens() {
+        let mut config = Config::default();
+        let token = mint_token(&mut config, "alice", Role::Operator).unwrap();
+
+        assert_eq!(token.len(), TOKEN_BYTES * 2);
+        assert_eq!(config.tokens.len(), 1);
+        assert_eq!(config.tokens[0].hash, hash_token(&token));
+        assert_ne!(config.tokens[0].hash, token);
+
+        let principal = authenticate(&config, &token).unwrap();
+        assert_eq!(principal.name, "alice");
+        assert_eq!(principal.role, Role::Operator);
+        assert!(authenticate(&config, &config.tokens[0].hash).is_none());
+        assert!(authenticate(&config, "").is_none());
+    }
+
+    #[test]
+    fn refuses_duplicate_and_empty_names() {
+        let mut config = Config::default();
+        mint_token(&mut config, "alice", Role::Admin).unwrap();
+
+        assert!(matches!(
+            mint_token(&mut config, "alice", Role::Viewer),
+            Err(ServerError::DuplicateTokenName(name)) if name == "alice"
+        ));
+        assert!(matches!(
+            mint_token(&mut config, "", Role::Viewer),
+            Err(ServerError::InvalidTokenName(_))
+        ));
+        assert_eq!(config.tokens.len(), 1);
+    }
+
+    #[test]
+    fn compares_secrets_in_full() {
+        assert!(secrets_match("hunter2", "hunter2"));
+        assert!(!secrets_match("hunter2", "hunter3"));
+        assert!(!secrets_match("hunter2", "hunter"));
+        assert!(!secrets_match("", "hunter2"));
+    }
+
+    #[test]
+    fn roles_allow_the_ones_below() {
+        assert!(Role::Admin.allows(Role::Operator));
+        assert!(Role::Operator.allows(Role::Operator));
+        assert!(!Role::Viewer.allows(Role::Operator));
+        assert_eq!("operator".parse::<Role>().unwrap(), Role::Operator);
+        assert!("root".parse::<Role>().is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::config::Config;
+use crate::types::{Schedule, ZoneId};
+
+use serde::{Deserialize, Serialize};
+
+/// Float error can leave an exact number of minutes a hair over, which
+/// mustn't round up to another whole minute.
+const ROUNDING_SLACK: f64 = 1e-9;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DerivedDuration {
+    pub schedule: String,
+    pub zone: ZoneId,
+    pub duration_minutes: u32,
+}
+
+/// How many times a week `zone` is watered across the active schedules.
+fn weekly_runs(config: &Config, zone: ZoneId) -> usize {
+    config
+        .schedules
+        .iter()
+        .filter(|schedule| {
+            schedule.is_active && schedule.active_periods.iter().any(|p| p.zone == zone)
+        })
+        .map(|schedule| schedule.days.len())
+        .sum()
+}
+
+/// The minutes `zone` needs per run of `schedule` to meet its weekly depth
+/// target, or `None` if the zone has no budget. The target is split evenly
+/// across every run of the zone in the week, so a zone watered by two
+/// schedules isn't given its target twice.
+pub fn derived_minutes(config: &Config, schedule: &Schedule, zone: ZoneId) -> Option<u32> {
+    let zone_config = config.zones.get(&zone)?;
+    let target = zone_config.weekly_depth_target?;
+    let rate = zone_config.precipitation_rate?;
+    if rate <= 0.0 || target <= 0.0 || !schedule.is_active {
+        return None;
+    }
+
+    let runs = weekly_runs(config, zone);
+    if runs == 0 {
+        return None;
+    }
+
+    let minutes = target / runs as f64 / rate * 60.0;
+    Some((minutes - ROUNDING_SLACK).ceil() as u32)
+}
+
+/// Every scheduled period whose duration comes from a water budget.
+pub fn derived_durations(config: &Config) -> Vec<DerivedDuration> {
+    let mut durations = vec![];
+    for schedule in config.schedules.iter() {
+        let mut zones: Vec<ZoneId> = schedule.active_periods.iter().map(|p| p.zone).collect();
+        zones.sort();
+
+        for zone in zones {
+            if let Some(duration_minutes) = derived_minutes(config, schedule, zone) {
+                durations.push(DerivedDuration {
+                    schedule: schedule.name.clone(),
+                    zone,
+                    duration_minutes,
+                });
+            }
+        }
+    }
+    durations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ZoneConfig;
+    use crate::types::{DeviceId, Zone};
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+    const ZONE_2: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone2,
+    };
+
+    fn schedule(name: &str, days: &[&str], zones: &[ZoneId]) -> Schedule {
+        let periods: Vec<_> = zones
+            .iter()
+            .map(|zone| serde_json::json!({ "zone": zone, "durationMinutes": 5 }))
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "days": days,
+            "activePeriods": periods,
+            "startTimeMinutes": 360,
+            "isActive": true,
+        }))
+        .unwrap()
+    }
+
+    fn budgeted(target: f64, rate: f64, schedules: Vec<Schedule>) -> Config {
+        let mut config = Config {
+            schedules,
+            ..Config::default()
+        };
+        config.zones.insert(
+            ZONE_1,
+            ZoneConfig {
+                enabled: true,
+                flow_rate: None,
+                weekly_depth_target: Some(target),
+                precipitation_rate: Some(rate),
+                crop_coefficient: None,
+            },
+        );
+        config
+    }
+
+    fn minutes(config: &Config) -> Option<u32> {
+        derived_minutes(config, &config.schedules[0], ZONE_1)
+    }
+
+    #[test]
+    fn splits_the_target_across_the_week() {
+        // 1 inch a week at half an inch an hour is two hours
+        let config = budgeted(1.0, 0.5, vec![schedule("a", &["monday"], &[ZONE_1])]);
+        assert_eq!(minutes(&config), Some(120));
+
+        let config = budgeted(
+            1.0,
+            0.5,
+            vec![
+                schedule("a", &["monday", "wednesday"], &[ZONE_1]),
+                schedule("b", &["friday"], &[ZONE_1]),
+            ],
+        );
+        assert_eq!(minutes(&config), Some(40));
+    }
+
+    #[test]
+    fn rounds_up_to_whole_minutes() {
+        // 13⅓ minutes a run
+        let config = budgeted(
+            1.0,
+            1.5,
+            vec![schedule("a", &["monday", "wednesday", "friday"], &[ZONE_1])],
+        );
+        assert_eq!(minutes(&config), Some(14));
+
+        // exactly 20, which float division puts a hair over
+        let config = budgeted(0.1, 0.3, vec![schedule("a", &["monday"], &[ZONE_1])]);
+        assert_eq!(minutes(&config), Some(20));
+    }
+
+    #[test]
+    fn needs_a_rate_and_a_target() {
+        let schedules = vec![schedule("a", &["monday"], &[ZONE_1])];
+        assert_eq!(minutes(&budgeted(1.0, 0.0, schedules.clone())), None);
+        assert_eq!(minutes(&budgeted(0.0, 0.5, schedules.clone())), None);
+
+        let mut inactive = budgeted(1.0, 0.5, schedules);
+        inactive.schedules[0].is_active = false;
+        assert_eq!(minutes(&inactive), None);
+    }
+
+    #[test]
+    fn lists_budgeted_periods_only() {
+        let config = budgeted(
+            1.0,
+            0.5,
+            vec![
+                schedule("a", &["monday"], &[ZONE_2, ZONE_1]),
+                schedule("b", &["friday"], &[ZONE_2]),
+            ],
+        );
+        assert_eq!(
+            derived_durations(&config),
+            [DerivedDuration {
+                schedule: "a".to_string(),
+                zone: ZONE_1,
+                duration_minutes: 120,
+            }]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use crate::auth::{Role, mint_token};
+use crate::config::Config;
+use crate::tls::{DEFAULT_TLS_PORT, TlsConfig};
+
+pub const DEFAULT_PORT: u16 = 9001;
+
+pub const USAGE: &str = "Usage:
+  websocket-server [--port <port>] [--tls-cert <path> --tls-key <path>] [--tls-port <port>]
+  websocket-server mint-token <name> <viewer|operator|admin>";
+
+pub enum Command {
+    Serve(ServeOptions),
+    MintToken { name: String, role: Role },
+}
+
+/// Listener settings given on the command line, which win over the config.
+#[derive(Default, Debug)]
+pub struct ServeOptions {
+    pub port: Option<u16>,
+    pub tls_port: Option<u16>,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+}
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    if let [command, rest @ ..] = args
+        && command == "mint-token"
+    {
+        return match rest {
+            [name, role] => Ok(Command::MintToken {
+                name: name.clone(),
+                role: role.parse().map_err(|e| format!("{e}"))?,
+            }),
+            _ => Err("mint-token needs a name and a role".to_string()),
+        };
+    }
+
+    let mut options = ServeOptions::default();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{flag} needs a value"))
+        };
+        let port = |value: String| {
+            value
+                .parse::<u16>()
+                .map_err(|_| format!("Invalid port: {value}"))
+        };
+        match flag.as_str() {
+            "--port" => options.port = Some(port(value()?)?),
+            "--tls-port" => options.tls_port = Some(port(value()?)?),
+            "--tls-cert" => options.tls_cert = Some(value()?),
+            "--tls-key" => options.tls_key = Some(value()?),
+            _ => return Err(format!("Unknown argument: {flag}")),
+        }
+    }
+    Ok(Command::Serve(options))
+}
+
+impl ServeOptions {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_PORT)
+    }
+
+    /// The TLS listener to open, if any. A certificate and key on the
+    /// command line replace the configured ones.
+    pub fn tls(&self, config: &Config) -> Result<Option<TlsConfig>, String> {
+        let mut tls = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert_path), Some(key_path)) => Some(TlsConfig {
+                port: config.tls.as_ref().map_or(DEFAULT_TLS_PORT, |tls| tls.port),
+                cert_path: cert_path.clone(),
+                key_path: key_path.clone(),
+            }),
+            (None, None) => config.tls.clone(),
+            _ => return Err("--tls-cert and --tls-key go together".to_string()),
+        };
+        if let (Some(tls), Some(port)) = (&mut tls, self.tls_port) {
+            tls.port = port;
+        }
+        Ok(tls)
+    }
+}
+
+/// Adds a token to the config and prints it. Returns the exit code.
+pub fn mint(name: &str, role: Role) -> i32 {
+    let result = Config::load().and_then(|mut config| {
+        let token = mint_token(&mut config, name, role)?;
+        config.save()?;
+        Ok(token)
+    });
+
+    match result {
+        Ok(token) => {
+            println!("{token}");
+            eprintln!("Minted a token for {name} with the {role} role. It won't be shown again.");
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to mint a token: {e}");
+            1
+        }
+    }
+}
@@ -1,6 +1,8 @@
-use crate::config::{CONFIG_FILE_PATH, Config};
+use crate::auth::TokenConfig;
+use crate::config::{CONFIG_FILE_PATH, CONFIG_VERSION, Config};
 use crate::error::ServerError;
 
+use serde::Deserialize;
 use std::fs;
 
 pub fn load() -> Result<Config, ServerError> {
@@ -11,6 +13,162 @@ pub fn load() -> Result<Config, ServerError> {
             _ => return Err(ServerError::InvalidConfig),
         },
     };
-    let config: Config = toml::from_str(&file).map_err(|_| ServerError::InvalidConfig)?;
+    let (mut config, migrated) = parse(&file)?;
+    if migrated {
+        config.save()?;
+    }
+
     Ok(config)
 }
+
+/// The tokens saved in the config file. `mint-token` adds them there while
+/// the server may be running, so the server reads them back rather than
+/// trusting its own copy.
+pub fn load_tokens() -> Result<Vec<TokenConfig>, ServerError> {
+    #[derive(Deserialize)]
+    struct Tokens {
+        #[serde(default)]
+        tokens: Vec<TokenConfig>,
+    }
+
+    let file = match fs::read_to_string(CONFIG_FILE_PATH) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(_) => return Err(ServerError::InvalidConfig),
+    };
+    let tokens: Tokens = toml::from_str(&file).map_err(|_| ServerError::InvalidConfig)?;
+    Ok(tokens.tokens)
+}
+
+/// Reads a config, bringing one saved by an older version up to date.
+/// Returns whether anything changed that has to be saved.
+fn parse(file: &str) -> Result<(Config, bool), ServerError> {
+    let mut config: Config = toml::from_str(file).map_err(|_| ServerError::InvalidConfig)?;
+
+    // schedules saved before they had ids were given new ones, which have to
+    // be saved to stay the same across restarts
+    let raw: toml::Table = toml::from_str(file).map_err(|_| ServerError::InvalidConfig)?;
+    let missing_ids = raw
+        .get("schedules")
+        .and_then(|schedules| schedules.as_array())
+        .is_some_and(|schedules| {
+            schedules
+                .iter()
+                .any(|schedule| schedule.get("id").is_none())
+        });
+
+    // stagger_zones used to stagger on its own, before stagger_on switched
+    // staggering off whatever the schedules ask for
+    let outdated = config.version < CONFIG_VERSION;
+    if config.version < 1 && config.stagger_zones && !config.stagger_on {
+        println!("Turned stagger_on on, as stagger_zones now only staggers while it's on");
+        config.stagger_on = true;
+    }
+    config.version = CONFIG_VERSION;
+
+    let adopted = config.adopt_legacy_zones();
+    if config.has_legacy_zones() && !config.controllers.is_empty() {
+        println!(
+            "Some zones were saved before controllers had IDs and can't be run until they're \
+             moved to a controller, written like AA:BB:CC:DD:EE:FF/zone1"
+        );
+    }
+
+    Ok((config, missing_ids || outdated || adopted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DeviceId, Zone, ZoneId};
+
+    const LEGACY: &str = r#"
+stagger_on = false
+stagger_zones = false
+
+[[schedules]]
+name = "a"
+days = ["monday"]
+startTimeMinutes = 360
+isActive = true
+
+[[schedules.activePeriods]]
+zone = "zone2"
+durationMinutes = 7
+
+[[hydraulic_groups]]
+name = "main"
+zones = ["zone2", "zone3"]
+
+[zones.zone2]
+enabled = false
+"#;
+
+    fn controller(device_id: &str) -> String {
+        format!("\n[[controllers]]\ndeviceId = \"{device_id}\"\nsecret = \"s3cret\"\n")
+    }
+
+    fn zone(controller: DeviceId, zone: Zone) -> ZoneId {
+        ZoneId::new(controller, zone)
+    }
+
+    #[test]
+    fn legacy_zones_load_without_a_controller() {
+        let (config, migrated) = parse(LEGACY).unwrap();
+
+        // saved for the ids the schedules were given
+        assert!(migrated);
+        assert!(config.has_legacy_zones());
+        let period = config.schedules[0].active_periods.iter().next().unwrap();
+        assert_eq!(period.zone, zone(DeviceId::UNKNOWN, Zone::Zone2));
+    }
+
+    #[test]
+    fn legacy_zones_move_to_the_only_controller() {
+        let device_id: DeviceId = "AA:BB:CC:DD:EE:01".parse().unwrap();
+        let file = LEGACY.to_string() + &controller("AA:BB:CC:DD:EE:01");
+        let (config, migrated) = parse(&file).unwrap();
+
+        assert!(migrated);
+        assert!(!config.has_legacy_zones());
+        let period = config.schedules[0].active_periods.iter().next().unwrap();
+        assert_eq!(period.zone, zone(device_id, Zone::Zone2));
+        assert_eq!(period.duration_minutes, 7);
+        assert!(!config.is_zone_enabled(zone(device_id, Zone::Zone2)));
+        assert!(
+            config.hydraulic_groups[0]
+                .zones
+                .contains(&zone(device_id, Zone::Zone3))
+        );
+        assert!(config.is_zone_known(period.zone));
+    }
+
+    #[test]
+    fn legacy_zones_stay_put_with_several_controllers() {
+        let file = LEGACY.to_string()
+            + &controller("AA:BB:CC:DD:EE:01")
+            + &controller("AA:BB:CC:DD:EE:02");
+        let (config, _) = parse(&file).unwrap();
+
+        assert!(config.has_legacy_zones());
+    }
+
+    #[test]
+    fn stagger_zones_keeps_staggering_after_an_upgrade() {
+        let (config, migrated) =
+            parse("schedules = []\nstagger_on = false\nstagger_zones = true\n").unwrap();
+        assert!(migrated);
+        assert!(config.stagger_on);
+        assert_eq!(config.version, CONFIG_VERSION);
+
+        let file = toml::to_string(&Config {
+            stagger_on: false,
+            stagger_zones: true,
+            ..Config::default()
+        })
+        .unwrap();
+        let (config, migrated) = parse(&file).unwrap();
+        assert!(!migrated);
+        assert!(!config.stagger_on);
+    }
+}
@@ -1,29 +1,138 @@
 pub mod load;
 pub mod save;
+pub mod validate;
 
+use crate::auth::TokenConfig;
+use crate::controllers::ControllerConfig;
 use crate::error::ServerError;
-use crate::types::Schedules;
+use crate::freeze::FreezeGuardConfig;
+use crate::restrictions::Restrictions;
+use crate::tls::TlsConfig;
+use crate::types::{
+    ActivePeriod, DepthUnit, DeviceId, HydraulicGroups, OverlapPolicy, Schedule, Schedules,
+    VolumeUnit, ZoneId,
+};
+use crate::weather::WeatherConfig;
 
-use core::default::Default;
-use load::load;
+use chrono::{DateTime, Local};
+use load::{load, load_tokens};
 use save::save;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+use validate::{FieldError, validate_schedules};
 
 pub const CONFIG_FILE_PATH: &str = ".config.toml";
+/// Bumped when the meaning of a saved setting changes, so that configs saved
+/// before can be migrated on load.
+pub const CONFIG_VERSION: u32 = 1;
+pub const DEFAULT_STAGGER_SECS: u32 = 10;
+pub const DEFAULT_SEASONAL_ADJUSTMENT_PERCENT: u32 = 100;
+pub const MAX_SEASONAL_ADJUSTMENT_PERCENT: u32 = 300;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Config {
+    /// The `CONFIG_VERSION` the config was saved with, 0 before there was one.
+    #[serde(default)]
+    pub version: u32,
+    /// Bumped on every save. Changes carry the revision they were based on,
+    /// so one made against a stale copy can't overwrite newer edits.
+    #[serde(default)]
+    pub revision: u64,
     pub schedules: Schedules,
+    /// Master switch for staggering. While off, consecutive zones in a
+    /// program never overlap, whatever the schedules ask for.
     pub stagger_on: bool,
+    /// Whether schedules without their own `stagger_secs` overlap consecutive
+    /// zones by `DEFAULT_STAGGER_SECS`.
     pub stagger_zones: bool,
+    #[serde(default)]
+    pub max_concurrent_zones: Option<u8>,
+    #[serde(default)]
+    pub hydraulic_groups: HydraulicGroups,
+    /// Installed zones. While empty, every zone is treated as installed.
+    #[serde(default)]
+    pub zones: BTreeMap<ZoneId, ZoneConfig>,
+    /// Scales every scheduled duration, e.g. 120 waters 20% longer.
+    #[serde(default = "default_seasonal_adjustment_percent")]
+    pub seasonal_adjustment_percent: u32,
+    /// Automatic runs starting before this time are skipped.
+    #[serde(default)]
+    pub rain_delay_until: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub overlap_policy: OverlapPolicy,
+    /// The unit zone flow rates are measured in, per minute.
+    #[serde(default)]
+    pub volume_unit: VolumeUnit,
+    /// The unit water depths and precipitation rates are measured in.
+    #[serde(default)]
+    pub depth_unit: DepthUnit,
+    #[serde(default)]
+    pub weather: Option<WeatherConfig>,
+    /// Uses the temperatures from `weather`.
+    #[serde(default)]
+    pub freeze_guard: Option<FreezeGuardConfig>,
+    #[serde(default)]
+    pub restrictions: Restrictions,
+    #[serde(default)]
+    pub controllers: Vec<ControllerConfig>,
+    /// Tokens dashboards can connect with, minted with `mint-token`.
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ZoneConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// In `Config::volume_unit` per minute.
+    #[serde(default)]
+    pub flow_rate: Option<f64>,
+    /// Water depth the zone should get each week. Together with
+    /// `precipitation_rate` this replaces the scheduled minutes.
+    #[serde(default)]
+    pub weekly_depth_target: Option<f64>,
+    /// Depth the zone applies per hour, in `Config::depth_unit`.
+    #[serde(default)]
+    pub precipitation_rate: Option<f64>,
+    /// Scales reference ET to the zone's planting. Treated as 1.0 when unset.
+    #[serde(default)]
+    pub crop_coefficient: Option<f64>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_seasonal_adjustment_percent() -> u32 {
+    DEFAULT_SEASONAL_ADJUSTMENT_PERCENT
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
+            revision: 0,
             schedules: vec![],
             stagger_on: false,
             stagger_zones: false,
+            max_concurrent_zones: None,
+            hydraulic_groups: vec![],
+            zones: BTreeMap::new(),
+            seasonal_adjustment_percent: DEFAULT_SEASONAL_ADJUSTMENT_PERCENT,
+            rain_delay_until: None,
+            overlap_policy: OverlapPolicy::default(),
+            volume_unit: VolumeUnit::default(),
+            depth_unit: DepthUnit::default(),
+            weather: None,
+            freeze_guard: None,
+            restrictions: Restrictions::default(),
+            controllers: vec![],
+            tokens: vec![],
+            tls: None,
         }
     }
 }
@@ -33,10 +142,23 @@ impl Config {
         load()
     }
 
-    pub fn save(&self) -> Result<(), ServerError> {
+    pub fn save(&mut self) -> Result<(), ServerError> {
+        // keep tokens minted since this copy was loaded
+        self.reload_tokens()?;
+        self.revision += 1;
         save(self)
     }
 
+    /// Picks up tokens minted with `mint-token` while the server was running.
+    pub fn reload_tokens(&mut self) -> Result<(), ServerError> {
+        for token in load_tokens()? {
+            if !self.tokens.iter().any(|known| known.name == token.name) {
+                self.tokens.push(token);
+            }
+        }
+        Ok(())
+    }
+
     pub fn set_schedules(&mut self, schedules: Schedules) {
         self.schedules = schedules;
     }
@@ -48,4 +170,97 @@ impl Config {
     pub fn set_stagger_zones(&mut self, stagger_zones: bool) {
         self.stagger_zones = stagger_zones;
     }
+
+    pub fn validate_schedules(&self, schedules: &Schedules) -> Result<(), Vec<FieldError>> {
+        validate_schedules(self, schedules)
+    }
+
+    pub fn schedule(&self, id: Uuid) -> Option<&Schedule> {
+        self.schedules.iter().find(|schedule| schedule.id == id)
+    }
+
+    pub fn controller(&self, device_id: DeviceId) -> Option<&ControllerConfig> {
+        self.controllers
+            .iter()
+            .find(|controller| controller.device_id == device_id)
+    }
+
+    /// Moves zones saved before controllers had IDs, which read as
+    /// `DeviceId::UNKNOWN`, onto the only approved controller. Returns whether
+    /// any moved. With several controllers there's no telling which one they
+    /// were on, so they're left for an admin to fix.
+    pub fn adopt_legacy_zones(&mut self) -> bool {
+        let [controller] = self.controllers.as_slice() else {
+            return false;
+        };
+        if !self.has_legacy_zones() {
+            return false;
+        }
+        let device_id = controller.device_id;
+        let adopt = |zone: ZoneId| match zone.controller {
+            DeviceId::UNKNOWN => ZoneId::new(device_id, zone.zone),
+            _ => zone,
+        };
+
+        for schedule in self.schedules.iter_mut() {
+            schedule.active_periods = std::mem::take(&mut schedule.active_periods)
+                .into_iter()
+                .map(|period| ActivePeriod {
+                    zone: adopt(period.zone),
+                    ..period
+                })
+                .collect();
+        }
+        self.zones = std::mem::take(&mut self.zones)
+            .into_iter()
+            .map(|(zone, zone_config)| (adopt(zone), zone_config))
+            .collect();
+        for group in self.hydraulic_groups.iter_mut() {
+            group.zones = group.zones.iter().copied().map(adopt).collect();
+        }
+
+        true
+    }
+
+    /// Whether any zone is still on `DeviceId::UNKNOWN`.
+    pub fn has_legacy_zones(&self) -> bool {
+        self.schedules
+            .iter()
+            .flat_map(|schedule| schedule.active_periods.iter())
+            .map(|period| period.zone)
+            .chain(self.zones.keys().copied())
+            .chain(
+                self.hydraulic_groups
+                    .iter()
+                    .flat_map(|group| group.zones.iter().copied()),
+            )
+            .any(|zone| zone.controller == DeviceId::UNKNOWN)
+    }
+
+    pub fn is_zone_known(&self, zone: ZoneId) -> bool {
+        (self.zones.is_empty() || self.zones.contains_key(&zone))
+            && (self.controllers.is_empty() || self.controller(zone.controller).is_some())
+    }
+
+    pub fn is_zone_enabled(&self, zone: ZoneId) -> bool {
+        self.zones.get(&zone).is_none_or(|zone| zone.enabled)
+    }
+
+    pub fn adjusted_minutes(&self, duration_minutes: u32) -> u32 {
+        let adjusted = duration_minutes as u64 * self.seasonal_adjustment_percent as u64;
+        ((adjusted + 50) / 100) as u32
+    }
+
+    /// How long consecutive zones of `schedule` should run together.
+    pub fn stagger_secs(&self, schedule: &Schedule) -> u32 {
+        if !self.stagger_on {
+            return 0;
+        }
+
+        match schedule.stagger_secs {
+            Some(stagger_secs) => stagger_secs,
+            None if self.stagger_zones => DEFAULT_STAGGER_SECS,
+            None => 0,
+        }
+    }
 }
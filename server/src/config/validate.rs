@@ -0,0 +1,284 @@
+use crate::budget;
+use crate::config::Config;
+use crate::restrictions;
+use crate::scheduler_runner::rules;
+use crate::types::{MoistureRule, Schedules};
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+pub fn validate_schedules(config: &Config, schedules: &Schedules) -> Result<(), Vec<FieldError>> {
+    // budgets and program lengths depend on the whole set of schedules
+    let config = &Config {
+        schedules: schedules.clone(),
+        ..config.clone()
+    };
+    let mut errors = vec![];
+    let mut names = HashSet::new();
+    let mut ids = HashSet::new();
+
+    for (index, schedule) in schedules.iter().enumerate() {
+        let field = |name: &str| format!("schedules[{index}].{name}");
+
+        if !names.insert(schedule.name.as_str()) {
+            errors.push(FieldError::new(
+                field("name"),
+                format!("Another schedule is already named \"{}\"", schedule.name),
+            ));
+        }
+
+        if !ids.insert(schedule.id) {
+            errors.push(FieldError::new(
+                field("id"),
+                format!("Another schedule already has id {}", schedule.id),
+            ));
+        }
+
+        if schedule.days.is_empty() {
+            errors.push(FieldError::new(field("days"), "No days selected"));
+        }
+
+        if schedule.start_time_minutes >= MINUTES_PER_DAY {
+            errors.push(FieldError::new(
+                field("startTimeMinutes"),
+                format!("Start time must be before {MINUTES_PER_DAY} minutes"),
+            ));
+        }
+
+        for period in schedule.active_periods.iter() {
+            let field = field(&format!("activePeriods[{}]", period.zone.key()));
+
+            if !config.is_zone_known(period.zone) {
+                errors.push(FieldError::new(
+                    format!("{field}.zone"),
+                    format!("{} is not configured", period.zone),
+                ));
+            } else if !config.is_zone_enabled(period.zone) {
+                errors.push(FieldError::new(
+                    format!("{field}.zone"),
+                    format!("{} is disabled", period.zone),
+                ));
+            }
+
+            if let Some(
+                MoistureRule::SkipAbove { percent } | MoistureRule::ShortenToward { percent },
+            ) = period.moisture
+                && !(percent > 0.0 && percent <= 100.0)
+            {
+                errors.push(FieldError::new(
+                    format!("{field}.moisture.percent"),
+                    "Moisture threshold must be above 0% and at most 100%",
+                ));
+            }
+
+            if period.duration_minutes == 0
+                && budget::derived_minutes(config, schedule, period.zone).is_none()
+            {
+                errors.push(FieldError::new(
+                    format!("{field}.durationMinutes"),
+                    "Duration must be at least one minute",
+                ));
+            }
+        }
+
+        let events = rules::program(config, schedule);
+        if events
+            .last()
+            .is_some_and(|event| event.offset_secs > SECS_PER_DAY)
+        {
+            errors.push(FieldError::new(
+                field("activePeriods"),
+                "Program runs for longer than 24 hours",
+            ));
+        }
+    }
+    errors.extend(restrictions::schedule_violations(config));
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ZoneConfig;
+    use crate::restrictions::{RestrictionRule, Restrictions};
+    use crate::types::{ActivePeriod, DeviceId, Schedule, Zone, ZoneId};
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+    const ZONE_2: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone2,
+    };
+    const ZONE_3: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone3,
+    };
+
+    fn schedule(name: &str, zone: ZoneId, duration_minutes: u32) -> Schedule {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "days": ["monday"],
+            "activePeriods": [{ "zone": zone, "durationMinutes": duration_minutes }],
+            "startTimeMinutes": 360,
+            "isActive": true,
+        }))
+        .unwrap()
+    }
+
+    // zone 1 is enabled, zone 2 disabled and zone 3 not configured
+    fn config() -> Config {
+        let zone = |enabled| ZoneConfig {
+            enabled,
+            flow_rate: None,
+            weekly_depth_target: None,
+            precipitation_rate: None,
+            crop_coefficient: None,
+        };
+        let mut config = Config::default();
+        config.zones.insert(ZONE_1, zone(true));
+        config.zones.insert(ZONE_2, zone(false));
+        config
+    }
+
+    fn errors(schedules: Schedules) -> Vec<FieldError> {
+        validate_schedules(&config(), &schedules).unwrap_err()
+    }
+
+    fn period_field(index: usize, zone: ZoneId, name: &str) -> String {
+        format!("schedules[{index}].activePeriods[{}].{name}", zone.key())
+    }
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|error| error.field.as_str()).collect()
+    }
+
+    #[test]
+    fn accepts_valid_schedules() {
+        let schedules = vec![schedule("a", ZONE_1, 10), schedule("b", ZONE_1, 20)];
+        assert_eq!(validate_schedules(&config(), &schedules), Ok(()));
+    }
+
+    #[test]
+    fn names_and_ids_are_unique() {
+        let first = schedule("a", ZONE_1, 10);
+        let same_id = Schedule {
+            name: "b".to_string(),
+            ..first.clone()
+        };
+        let same_name = schedule("a", ZONE_1, 10);
+        let errors = errors(vec![first, same_id, same_name]);
+        assert_eq!(fields(&errors), ["schedules[1].id", "schedules[2].name"]);
+    }
+
+    #[test]
+    fn needs_days_and_a_start_within_the_day() {
+        let mut schedule = schedule("a", ZONE_1, 10);
+        schedule.days.clear();
+        schedule.start_time_minutes = MINUTES_PER_DAY;
+        let errors = errors(vec![schedule]);
+        assert_eq!(
+            fields(&errors),
+            ["schedules[0].days", "schedules[0].startTimeMinutes"]
+        );
+    }
+
+    #[test]
+    fn zones_must_be_configured_and_enabled() {
+        let errors = errors(vec![schedule("a", ZONE_2, 10), schedule("b", ZONE_3, 10)]);
+        assert_eq!(
+            fields(&errors),
+            [
+                period_field(0, ZONE_2, "zone"),
+                period_field(1, ZONE_3, "zone")
+            ]
+        );
+        assert_eq!(errors[0].message, format!("{ZONE_2} is disabled"));
+        assert_eq!(errors[1].message, format!("{ZONE_3} is not configured"));
+    }
+
+    #[test]
+    fn moisture_thresholds_are_percentages() {
+        for percent in [0.0, 100.5, f64::NAN] {
+            let mut schedule = schedule("a", ZONE_1, 10);
+            schedule.active_periods = schedule
+                .active_periods
+                .into_iter()
+                .map(|period| ActivePeriod {
+                    moisture: Some(MoistureRule::SkipAbove { percent }),
+                    ..period
+                })
+                .collect();
+            let errors = errors(vec![schedule]);
+            assert_eq!(
+                fields(&errors),
+                [period_field(0, ZONE_1, "moisture.percent")],
+                "{percent}"
+            );
+        }
+    }
+
+    #[test]
+    fn periods_need_a_duration() {
+        let errors = errors(vec![schedule("a", ZONE_1, 0)]);
+        assert_eq!(
+            fields(&errors),
+            [period_field(0, ZONE_1, "durationMinutes")]
+        );
+
+        // unless a water budget sets it
+        let mut config = config();
+        let zone = config.zones.get_mut(&ZONE_1).unwrap();
+        zone.weekly_depth_target = Some(1.0);
+        zone.precipitation_rate = Some(1.0);
+        assert_eq!(
+            validate_schedules(&config, &vec![schedule("a", ZONE_1, 0)]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn programs_fit_in_a_day() {
+        let errors = errors(vec![schedule("a", ZONE_1, MINUTES_PER_DAY + 1)]);
+        assert_eq!(fields(&errors), ["schedules[0].activePeriods"]);
+    }
+
+    #[test]
+    fn includes_restriction_violations() {
+        let mut config = config();
+        config.restrictions = Restrictions {
+            rules: vec![RestrictionRule::ForbiddenWindow {
+                start_minutes: 6 * 60,
+                end_minutes: 10 * 60,
+            }],
+            ..Restrictions::default()
+        };
+        let errors = validate_schedules(&config, &vec![schedule("a", ZONE_1, 10)]).unwrap_err();
+        assert_eq!(fields(&errors), ["schedules[0].startTimeMinutes"]);
+    }
+}
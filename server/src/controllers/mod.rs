@@ -0,0 +1,65 @@
+use crate::config::Config;
+use crate::sensors::{FlowReading, Sensors};
+use crate::types::{ControllerTimestamps, DeviceId};
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+/// How long a controller can go quiet before it counts as offline.
+pub const CONTROLLER_TIMEOUT_SECS: u64 = 15;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ControllerConfig {
+    pub device_id: DeviceId,
+    /// What the dashboard calls it, like "Front yard".
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Presented by the controller when it connects, set when it's approved.
+    pub secret: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ControllerState {
+    pub device_id: DeviceId,
+    pub name: Option<String>,
+    pub is_connected: bool,
+    pub rain_sensor_active: bool,
+    pub flow: Option<FlowReading>,
+}
+
+/// The configured controllers and any others that have been heard from.
+pub async fn controller_states(
+    config: &Config,
+    controller_timestamps: &ControllerTimestamps,
+    sensors: &Sensors,
+) -> Vec<ControllerState> {
+    let timestamps = controller_timestamps.lock().await;
+    let device_ids: BTreeSet<DeviceId> = config
+        .controllers
+        .iter()
+        .map(|controller| controller.device_id)
+        .chain(timestamps.keys().copied())
+        .collect();
+
+    device_ids
+        .into_iter()
+        .map(|device_id| ControllerState {
+            device_id,
+            name: config
+                .controller(device_id)
+                .and_then(|controller| controller.name.clone()),
+            is_connected: timestamps.get(&device_id).is_some_and(|last_message| {
+                last_message.elapsed() < Duration::from_secs(CONTROLLER_TIMEOUT_SECS)
+            }),
+            rain_sensor_active: sensors
+                .rain_sensors
+                .get(&device_id)
+                .copied()
+                .unwrap_or_default(),
+            flow: sensors.flow.get(&device_id).cloned(),
+        })
+        .collect()
+}
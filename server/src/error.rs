@@ -1,3 +1,6 @@
+use crate::auth::Role;
+use crate::types::{DeviceId, ZoneId};
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -13,4 +16,65 @@ pub enum ServerError {
 
     #[error("Failed to write to file: {0}")]
     FailedToWriteToFile(String),
+
+    #[error("Failed to read file: {0}")]
+    FailedToReadFile(String),
+
+    #[error("Invalid zone: {0}")]
+    InvalidZone(u8),
+
+    #[error("Invalid zone ID: {0}")]
+    InvalidZoneId(String),
+
+    #[error("Invalid device ID: {0}")]
+    InvalidDeviceId(String),
+
+    #[error("Invalid weather data: {0}")]
+    InvalidWeatherData(String),
+
+    #[error("Invalid soil moisture reading: {0}%")]
+    InvalidMoistureReading(f64),
+
+    #[error("Freeze guard is on at {temp:.1}°C")]
+    FreezeGuardActive { temp: f64 },
+
+    #[error("Invalid token name: \"{0}\"")]
+    InvalidTokenName(String),
+
+    #[error("A token named \"{0}\" already exists")]
+    DuplicateTokenName(String),
+
+    #[error("Invalid TLS certificate or key: {0}")]
+    InvalidTlsIdentity(String),
+
+    #[error("Invalid role: \"{0}\", expected viewer, operator or admin")]
+    InvalidRole(String),
+
+    #[error("Forbidden: this needs the {required} role, but the token is {role}")]
+    Forbidden { role: Role, required: Role },
+
+    #[error("The config has changed since revision {revision}, it's at revision {current} now")]
+    RevisionConflict { revision: u64, current: u64 },
+
+    #[error("Controller {0} is not connected")]
+    ControllerNotConnected(DeviceId),
+
+    #[error("Cannot open {zone}: the maximum of {max} concurrent zones are already open")]
+    TooManyOpenZones { zone: ZoneId, max: u8 },
+
+    #[error("Cannot open {zone}: {open_zone} is already open in hydraulic group \"{group}\"")]
+    HydraulicGroupConflict {
+        zone: ZoneId,
+        open_zone: ZoneId,
+        group: String,
+    },
+}
+
+impl ServerError {
+    pub fn is_zone_conflict(&self) -> bool {
+        matches!(
+            self,
+            ServerError::TooManyOpenZones { .. } | ServerError::HydraulicGroupConflict { .. }
+        )
+    }
 }
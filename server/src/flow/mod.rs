@@ -0,0 +1,258 @@
+use crate::config::Config;
+use crate::types::ZoneId;
+
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+
+/// Flow this many times above normal means something has broken.
+const HIGH_FLOW_FACTOR: f64 = 1.5;
+/// Flow above this with every zone closed is a leak, in L/min.
+const LEAK_FLOW_LPM: f64 = 0.5;
+/// Readings this soon after a zone opens or closes are ignored while the
+/// pipes fill or drain.
+const SETTLE_SECS: i64 = 30;
+/// How many readings in a row have to be off before raising an alert.
+const ALERT_READINGS: u32 = 3;
+/// How much each reading moves a zone's learned flow.
+const LEARNING_WEIGHT: f64 = 0.1;
+/// Readings needed before a learned flow is trusted.
+const MIN_LEARNED_READINGS: u32 = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum FlowAlert {
+    /// Flow is far above normal for the open zones, like a broken head.
+    HighFlow {
+        zones: Vec<ZoneId>,
+        flow: f64,
+        expected: f64,
+    },
+    /// Water is flowing with every zone closed.
+    Leak { flow: f64 },
+}
+
+impl Display for FlowAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlowAlert::HighFlow { flow, expected, .. } => write!(
+                f,
+                "High flow of {flow:.1}L/min, expected {expected:.1}L/min"
+            ),
+            FlowAlert::Leak { flow } => write!(f, "Leak of {flow:.1}L/min with all zones closed"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LearnedFlow {
+    lpm: f64,
+    readings: u32,
+}
+
+/// Learns each zone's normal flow from the readings taken while it runs on
+/// its own, and flags readings that don't fit the open zones.
+#[derive(Debug, Default)]
+pub struct FlowMonitor {
+    learned: BTreeMap<ZoneId, LearnedFlow>,
+    open_zones: BTreeSet<ZoneId>,
+    changed_at: Option<DateTime<Local>>,
+    high_readings: u32,
+    leak_readings: u32,
+}
+
+impl FlowMonitor {
+    /// The learned flow of `zone` in L/min, once there's enough to go on.
+    pub fn learned_flow(&self, zone: ZoneId) -> Option<f64> {
+        self.learned
+            .get(&zone)
+            .filter(|learned| learned.readings >= MIN_LEARNED_READINGS)
+            .map(|learned| learned.lpm)
+    }
+
+    /// Feeds a reading of `flow` L/min taken at `at` while `open_zones` were
+    /// open. `configured` holds the flows to expect of zones that haven't
+    /// been learned yet. Each problem is only raised once, when it has lasted
+    /// `ALERT_READINGS` readings.
+    pub fn update(
+        &mut self,
+        flow: f64,
+        open_zones: &BTreeSet<ZoneId>,
+        at: DateTime<Local>,
+        configured: &BTreeMap<ZoneId, f64>,
+    ) -> Option<FlowAlert> {
+        if *open_zones != self.open_zones {
+            self.open_zones = open_zones.clone();
+            self.changed_at = Some(at);
+            self.high_readings = 0;
+            self.leak_readings = 0;
+        }
+        if self
+            .changed_at
+            .is_some_and(|changed_at| at - changed_at < TimeDelta::seconds(SETTLE_SECS))
+        {
+            return None;
+        }
+
+        if open_zones.is_empty() {
+            if flow <= LEAK_FLOW_LPM {
+                self.leak_readings = 0;
+                return None;
+            }
+            self.leak_readings += 1;
+            return (self.leak_readings == ALERT_READINGS).then_some(FlowAlert::Leak { flow });
+        }
+
+        let expected: Option<f64> = open_zones
+            .iter()
+            .map(|zone| {
+                self.learned_flow(*zone)
+                    .or_else(|| configured.get(zone).copied())
+            })
+            .sum();
+
+        if let Some(expected) = expected
+            && flow > expected * HIGH_FLOW_FACTOR
+        {
+            self.high_readings += 1;
+            return (self.high_readings == ALERT_READINGS).then(|| FlowAlert::HighFlow {
+                zones: open_zones.iter().copied().collect(),
+                flow,
+                expected,
+            });
+        }
+        self.high_readings = 0;
+
+        // only a zone running alone can be told apart from the others
+        if let [zone] = open_zones.iter().collect::<Vec<_>>()[..] {
+            let learned = self.learned.entry(*zone).or_insert(LearnedFlow {
+                lpm: flow,
+                readings: 0,
+            });
+            learned.lpm += (flow - learned.lpm) * LEARNING_WEIGHT;
+            learned.readings += 1;
+        }
+        None
+    }
+}
+
+/// The configured zone flow rates, in L/min.
+pub fn configured_flows(config: &Config) -> BTreeMap<ZoneId, f64> {
+    config
+        .zones
+        .iter()
+        .filter_map(|(zone, zone_config)| {
+            let flow_rate = zone_config.flow_rate?;
+            Some((*zone, config.volume_unit.to_liters(flow_rate)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DeviceId, Zone};
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+    const ZONE_2: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone2,
+    };
+
+    struct Feed {
+        monitor: FlowMonitor,
+        at: DateTime<Local>,
+        configured: BTreeMap<ZoneId, f64>,
+    }
+
+    impl Feed {
+        fn new() -> Self {
+            Self {
+                monitor: FlowMonitor::default(),
+                at: Local::now(),
+                configured: BTreeMap::new(),
+            }
+        }
+
+        /// Feeds a reading every 5 seconds, returning any alerts raised.
+        fn readings(&mut self, flows: &[f64], open_zones: &[ZoneId]) -> Vec<FlowAlert> {
+            let open_zones: BTreeSet<ZoneId> = open_zones.iter().copied().collect();
+            flows
+                .iter()
+                .filter_map(|flow| {
+                    self.at += TimeDelta::seconds(5);
+                    self.monitor
+                        .update(*flow, &open_zones, self.at, &self.configured)
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn learns_a_zone_running_alone() {
+        let mut feed = Feed::new();
+
+        assert!(feed.readings(&[10.0; 20], &[ZONE_1]).is_empty());
+        assert!(feed.readings(&[20.0; 20], &[ZONE_1, ZONE_2]).is_empty());
+
+        let learned = feed.monitor.learned_flow(ZONE_1).unwrap();
+        assert!((learned - 10.0).abs() < 0.01);
+        assert_eq!(feed.monitor.learned_flow(ZONE_2), None);
+    }
+
+    #[test]
+    fn alerts_once_on_sustained_high_flow() {
+        let mut feed = Feed::new();
+        feed.readings(&[10.0; 20], &[ZONE_1]);
+
+        let alerts = feed.readings(&[25.0; 10], &[ZONE_1]);
+        assert_eq!(
+            alerts,
+            vec![FlowAlert::HighFlow {
+                zones: vec![ZONE_1],
+                flow: 25.0,
+                expected: feed.monitor.learned_flow(ZONE_1).unwrap(),
+            }]
+        );
+    }
+
+    #[test]
+    fn ignores_brief_spikes_and_settling() {
+        let mut feed = Feed::new();
+        feed.readings(&[10.0; 20], &[ZONE_1]);
+
+        assert!(
+            feed.readings(&[30.0, 30.0, 10.0, 30.0, 10.0], &[ZONE_1])
+                .is_empty()
+        );
+        // pipes still draining after the zone closes
+        assert!(
+            feed.readings(&[8.0, 4.0, 2.0, 1.0, 0.0, 0.0], &[])
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn falls_back_to_configured_flow() {
+        let mut feed = Feed::new();
+        feed.configured.insert(ZONE_2, 8.0);
+
+        let alerts = feed.readings(&[20.0; 10], &[ZONE_2]);
+        assert!(matches!(alerts[..], [FlowAlert::HighFlow { .. }]));
+    }
+
+    #[test]
+    fn alerts_on_flow_with_every_zone_closed() {
+        let mut feed = Feed::new();
+
+        assert!(feed.readings(&[0.0; 10], &[]).is_empty());
+        assert_eq!(
+            feed.readings(&[2.0; 10], &[]),
+            vec![FlowAlert::Leak { flow: 2.0 }]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use crate::history::{self, HistoryEvent, ZoneSource};
+use crate::message::handle_server_message;
+use crate::message::server::ServerResponse;
+use crate::message::server::freeze_guard::FreezeGuardPayload;
+use crate::types::{ClientMap, ClientType, ConfigMutex, HistoryMutex, ZoneStateMutex};
+use crate::weather::{self, HourlyWeather};
+use crate::zone_state::toggle_zone;
+
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const DEFAULT_FREEZE_FORECAST_HOURS: u32 = 12;
+const FREEZE_CHECK_SECS: u64 = 300;
+/// How far above the minimum it has to warm up before the guard lifts, so
+/// it doesn't flap around the threshold.
+const FREEZE_HYSTERESIS_C: f64 = 1.0;
+/// How old the last reported temperature can be.
+const LAST_REPORTED_HOURS: i64 = 3;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FreezeGuardConfig {
+    /// Automatic watering is blocked below this, in °C.
+    pub min_temp: f64,
+    /// How far ahead forecast temperatures count.
+    #[serde(default = "default_forecast_hours")]
+    pub forecast_hours: u32,
+}
+
+fn default_forecast_hours() -> u32 {
+    DEFAULT_FREEZE_FORECAST_HOURS
+}
+
+/// Why automatic watering is blocked.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FreezeGuard {
+    /// The temperature that tripped the guard, in °C.
+    pub temp: f64,
+    pub since: DateTime<Local>,
+}
+
+/// The coldest of the last reported temperature and the forecast up to
+/// `forecast_hours` ahead. Reports older than a few hours are ignored.
+pub fn lowest_temp(
+    weather: &[HourlyWeather],
+    now: DateTime<Local>,
+    forecast_hours: u32,
+) -> Option<f64> {
+    let stale = now - TimeDelta::hours(LAST_REPORTED_HOURS);
+    let last_reported = weather
+        .iter()
+        .filter(|hour| hour.time <= now && hour.time >= stale && hour.temp.is_some())
+        .max_by_key(|hour| hour.time)
+        .and_then(|hour| hour.temp);
+    let until = now + TimeDelta::hours(forecast_hours as i64);
+    let forecast = weather
+        .iter()
+        .filter(|hour| hour.time > now && hour.time <= until)
+        .filter_map(|hour| hour.temp);
+
+    last_reported.into_iter().chain(forecast).reduce(f64::min)
+}
+
+/// Whether the guard turns on or off at `temp`. It only lifts once it's
+/// warmed up past the hysteresis, so it doesn't flap around `min_temp`.
+fn switch_to(is_active: bool, temp: f64, min_temp: f64) -> Option<bool> {
+    if !is_active && temp < min_temp {
+        Some(true)
+    } else if is_active && temp >= min_temp + FREEZE_HYSTERESIS_C {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Checks the temperature every few minutes and blocks automatic watering
+/// while it's too cold, closing any zones that are on.
+pub async fn freeze_guard_task(
+    clients: ClientMap,
+    config: ConfigMutex,
+    zone_state: ZoneStateMutex,
+    history: HistoryMutex,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(FREEZE_CHECK_SECS));
+
+    loop {
+        interval.tick().await;
+
+        let (guard_config, weather_config) = {
+            let config = config.lock().await;
+            (config.freeze_guard.clone(), config.weather.clone())
+        };
+        let (Some(guard_config), Some(weather_config)) = (guard_config, weather_config) else {
+            continue;
+        };
+
+        let now = Local::now();
+        let from = now - TimeDelta::hours(LAST_REPORTED_HOURS);
+        let to = now + TimeDelta::hours(guard_config.forecast_hours as i64);
+        let temp = match weather::provider(&weather_config).hourly(from, to) {
+            Ok(weather) => lowest_temp(&weather, now, guard_config.forecast_hours),
+            Err(e) => {
+                println!("Failed to read weather for the freeze guard: {e}");
+                continue;
+            }
+        };
+        let Some(temp) = temp else {
+            continue;
+        };
+
+        let is_active = zone_state.lock().await.freeze_guard().is_some();
+        match switch_to(is_active, temp, guard_config.min_temp) {
+            Some(true) => activate(&clients, &zone_state, &history, temp, now).await,
+            Some(false) => deactivate(&clients, &zone_state, &history, temp).await,
+            None => {}
+        }
+    }
+}
+
+async fn activate(
+    clients: &ClientMap,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    temp: f64,
+    now: DateTime<Local>,
+) {
+    println!("Freeze guard on at {temp:.1}°C");
+    let open_zones = {
+        let mut zone_state = zone_state.lock().await;
+        zone_state.set_freeze_guard(Some(FreezeGuard { temp, since: now }));
+        zone_state.open_zones().clone()
+    };
+
+    for zone in open_zones {
+        if let Err(e) = toggle_zone(
+            clients,
+            zone_state,
+            history,
+            zone,
+            false,
+            ZoneSource::FreezeGuard,
+        )
+        .await
+        {
+            println!("Failed to close {zone} for the freeze guard: {e}");
+        }
+    }
+
+    notify(clients, history, true, temp).await;
+}
+
+async fn deactivate(
+    clients: &ClientMap,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    temp: f64,
+) {
+    println!("Freeze guard off at {temp:.1}°C");
+    zone_state.lock().await.set_freeze_guard(None);
+    notify(clients, history, false, temp).await;
+}
+
+async fn notify(clients: &ClientMap, history: &HistoryMutex, active: bool, temp: f64) {
+    history::record(history, HistoryEvent::FreezeGuardChanged { active, temp }).await;
+    handle_server_message(
+        clients,
+        ClientType::User,
+        ServerResponse::FreezeGuard(FreezeGuardPayload { active, temp }),
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2025, 1, 15, 6, 0, 0).unwrap()
+    }
+
+    // `temp` at `hours` from now
+    fn hour(hours: i64, temp: f64) -> HourlyWeather {
+        HourlyWeather {
+            time: now() + TimeDelta::hours(hours),
+            temp: Some(temp),
+            rainfall: None,
+            rain_probability: None,
+            wind_speed: None,
+        }
+    }
+
+    #[test]
+    fn takes_the_coldest_of_the_last_report_and_the_forecast() {
+        let weather = [hour(-2, -1.0), hour(-1, 2.0), hour(3, 1.0), hour(6, 4.0)];
+        // the -1 was reported before the latest
+        assert_eq!(lowest_temp(&weather, now(), 12), Some(1.0));
+
+        let weather = [hour(-1, -0.5), hour(3, 1.0)];
+        assert_eq!(lowest_temp(&weather, now(), 12), Some(-0.5));
+    }
+
+    #[test]
+    fn ignores_a_stale_last_report() {
+        let weather = [hour(-LAST_REPORTED_HOURS - 1, -5.0), hour(2, 3.0)];
+        assert_eq!(lowest_temp(&weather, now(), 12), Some(3.0));
+        assert_eq!(lowest_temp(&weather[..1], now(), 12), None);
+    }
+
+    #[test]
+    fn only_counts_the_forecast_horizon() {
+        let weather = [hour(-1, 5.0), hour(4, 2.0), hour(5, -3.0)];
+        assert_eq!(lowest_temp(&weather, now(), 4), Some(2.0));
+        assert_eq!(lowest_temp(&weather, now(), 5), Some(-3.0));
+        assert_eq!(lowest_temp(&[], now(), 12), None);
+    }
+
+    #[test]
+    fn lifts_once_warmer_by_the_hysteresis() {
+        assert_eq!(switch_to(false, 0.9, 1.0), Some(true));
+        assert_eq!(switch_to(false, 1.0, 1.0), None);
+
+        assert_eq!(switch_to(true, 1.5, 1.0), None);
+        assert_eq!(switch_to(true, 1.0 + FREEZE_HYSTERESIS_C, 1.0), Some(false));
+        assert_eq!(switch_to(true, 0.0, 1.0), None);
+    }
+}
@@ -0,0 +1,78 @@
+use crate::auth::to_hex;
+use crate::types::DeviceId;
+
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde::Deserialize;
+use shared::{ControllerHandshake, verify_identify_proof};
+
+const CHALLENGE_BYTES: usize = 16;
+
+/// Who a new connection says it is.
+#[derive(Debug)]
+pub enum Handshake {
+    User { token: String },
+    Controller { device_id: DeviceId, proof: Proof },
+}
+
+/// How a controller showed it holds its secret on one connection.
+#[derive(Debug, Clone, Default)]
+pub struct Proof {
+    pub challenge: String,
+    /// The controller's nonce, which the server signs with from then on.
+    pub nonce: u64,
+    pub proof: String,
+}
+
+impl Proof {
+    /// Whether the controller made this proof with `secret`. An empty secret
+    /// never matches, or any device could claim to hold it.
+    pub fn verifies(&self, device_id: DeviceId, secret: &str) -> bool {
+        !secret.is_empty()
+            && verify_identify_proof(
+                secret.as_bytes(),
+                &device_id.to_string(),
+                &self.challenge,
+                self.nonce,
+                &self.proof,
+            )
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "camelCase")]
+enum UserHandshake {
+    Authenticate { token: String },
+}
+
+/// A fresh random challenge to send a new connection.
+pub fn challenge() -> String {
+    let mut bytes = [0; CHALLENGE_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+/// Works out who connected from the first message. Dashboards authenticate
+/// with a token, controllers identify themselves with their device ID and a
+/// proof over the `challenge` the connection was sent.
+pub fn identify(text: &str, challenge: &str) -> Option<Handshake> {
+    if let Ok(UserHandshake::Authenticate { token }) = serde_json::from_str(text) {
+        return Some(Handshake::User { token });
+    }
+
+    let ControllerHandshake::Identify(payload) = serde_json::from_str(text).ok()?;
+    match payload.device_id.parse() {
+        Ok(device_id) => Some(Handshake::Controller {
+            device_id,
+            proof: Proof {
+                challenge: challenge.to_string(),
+                nonce: payload.nonce,
+                proof: payload.proof.to_string(),
+            },
+        }),
+        Err(e) => {
+            println!("Error parsing controller handshake: {e}");
+            None
+        }
+    }
+}
@@ -0,0 +1,474 @@
+use crate::audit::rotated_path;
+use crate::error::ServerError;
+use crate::flow::FlowAlert;
+use crate::restrictions::RestrictionRule;
+use crate::scheduler_runner::rules::SkipReason;
+use crate::types::{DeviceId, HistoryMutex, ZoneId};
+use crate::weather::balance::EtAdjustment;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+
+pub const HISTORY_FILE_PATH: &str = ".history.jsonl";
+/// The log is rotated once it grows past this.
+pub const MAX_HISTORY_FILE_BYTES: u64 = 2 * 1024 * 1024;
+/// Rotated logs kept besides the current one, `.history.1.jsonl` the newest.
+pub const ROTATED_HISTORY_FILES: usize = 4;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ZoneSource {
+    User,
+    Schedule { schedule: String },
+    FlowAlert,
+    FreezeGuard,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum HistoryEvent {
+    ZoneToggled {
+        zone: ZoneId,
+        activate: bool,
+        source: ZoneSource,
+    },
+    ScheduleStarted {
+        schedule: String,
+    },
+    ScheduleFinished {
+        schedule: String,
+        error: Option<String>,
+    },
+    ScheduleSkipped {
+        schedule: String,
+        reason: SkipReason,
+    },
+    /// The run was stopped early because `rule` forbids watering.
+    ScheduleTruncated {
+        schedule: String,
+        rule: RestrictionRule,
+    },
+    EtAdjusted {
+        schedule: String,
+        adjustment: EtAdjustment,
+    },
+    // entries from before controllers had IDs read as `DeviceId::UNKNOWN`
+    ControllerConnected {
+        #[serde(default)]
+        controller: DeviceId,
+    },
+    ControllerDisconnected {
+        #[serde(default)]
+        controller: DeviceId,
+    },
+    RainSensorChanged {
+        #[serde(default)]
+        controller: DeviceId,
+        active: bool,
+    },
+    FlowAlert {
+        alert: FlowAlert,
+    },
+    FreezeGuardChanged {
+        active: bool,
+        temp: f64,
+    },
+    SoilMoistureReading {
+        zone: ZoneId,
+        percent: f64,
+    },
+    #[serde(rename_all = "camelCase")]
+    MoistureAdjusted {
+        schedule: String,
+        zone: ZoneId,
+        percent: f64,
+        scheduled_secs: u64,
+        adjusted_secs: u64,
+    },
+    ConfigChanged {
+        field: String,
+    },
+}
+
+impl HistoryEvent {
+    pub fn zone(&self) -> Option<ZoneId> {
+        match self {
+            HistoryEvent::ZoneToggled { zone, .. }
+            | HistoryEvent::SoilMoistureReading { zone, .. }
+            | HistoryEvent::MoistureAdjusted { zone, .. } => Some(*zone),
+            HistoryEvent::EtAdjusted { adjustment, .. } => Some(adjustment.zone),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub timestamp: DateTime<Local>,
+    pub event: HistoryEvent,
+}
+
+impl HistoryEntry {
+    fn is_skip(&self) -> bool {
+        matches!(self.event, HistoryEvent::ScheduleSkipped { .. })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HistoryFilter {
+    pub from: Option<DateTime<Local>>,
+    pub to: Option<DateTime<Local>>,
+    pub zones: Vec<ZoneId>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.from.is_none_or(|from| entry.timestamp >= from)
+            && self.to.is_none_or(|to| entry.timestamp < to)
+            && (self.zones.is_empty()
+                || entry
+                    .event
+                    .zone()
+                    .is_some_and(|zone| self.zones.contains(&zone)))
+    }
+}
+
+/// Append-only event log, one JSON entry per line. It's rotated like the
+/// audit log, and everything still on disk is kept in memory so queries
+/// don't have to read it back.
+pub struct History {
+    path: String,
+    next_id: u64,
+    /// Oldest first.
+    entries: Vec<HistoryEntry>,
+    /// How many of `entries` are in each file, the current one first.
+    file_entries: VecDeque<usize>,
+    last_skip: Option<HistoryEntry>,
+}
+
+impl History {
+    pub fn open(path: &str) -> Result<Self, ServerError> {
+        let mut entries = vec![];
+        let mut file_entries = VecDeque::new();
+        for n in (0..=ROTATED_HISTORY_FILES).rev() {
+            let file = match n {
+                0 => read_entries(path)?,
+                n => read_entries(&rotated_path(path, n))?,
+            };
+            file_entries.push_front(file.len());
+            entries.extend(file);
+        }
+        let last_id = entries.last().map(|entry| entry.id);
+        let last_skip = entries.iter().rfind(|entry| entry.is_skip()).cloned();
+
+        Ok(Self {
+            path: path.to_string(),
+            next_id: last_id.map_or(0, |id| id + 1),
+            entries,
+            file_entries,
+            last_skip,
+        })
+    }
+
+    pub fn record(&mut self, event: HistoryEvent) -> Result<HistoryEntry, ServerError> {
+        let entry = HistoryEntry {
+            id: self.next_id,
+            timestamp: Local::now(),
+            event,
+        };
+
+        if fs::metadata(&self.path).is_ok_and(|metadata| metadata.len() >= MAX_HISTORY_FILE_BYTES) {
+            self.rotate()?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|_| ServerError::FailedToCreateFile(self.path.clone()))?;
+        writeln!(file, "{}", serde_json::to_string(&entry).unwrap())
+            .map_err(|_| ServerError::FailedToWriteToFile(self.path.clone()))?;
+
+        self.next_id += 1;
+        if entry.is_skip() {
+            self.last_skip = Some(entry.clone());
+        }
+        self.entries.push(entry.clone());
+        if let Some(current) = self.file_entries.front_mut() {
+            *current += 1;
+        }
+        Ok(entry)
+    }
+
+    /// The most recent `ScheduleSkipped` entry.
+    pub fn last_skip(&self) -> Option<&HistoryEntry> {
+        self.last_skip.as_ref()
+    }
+
+    /// Every matching entry, oldest first.
+    pub fn entries(&self, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>, ServerError> {
+        Ok(self
+            .entries
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .cloned()
+            .collect())
+    }
+
+    /// Matching entries, newest first, skipping `offset` and returning at
+    /// most `limit`, along with the total number of matches.
+    pub fn query(
+        &self,
+        filter: &HistoryFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<HistoryEntry>, usize), ServerError> {
+        let matching = self
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| filter.matches(entry));
+        let total = matching.clone().count();
+
+        let page = matching.skip(offset).take(limit).cloned().collect();
+        Ok((page, total))
+    }
+
+    // shifts every log down one, dropping the oldest along with its entries
+    fn rotate(&mut self) -> Result<(), ServerError> {
+        for n in (1..ROTATED_HISTORY_FILES).rev() {
+            let from = rotated_path(&self.path, n);
+            if fs::metadata(&from).is_ok() {
+                let to = rotated_path(&self.path, n + 1);
+                fs::rename(&from, &to).map_err(|_| ServerError::FailedToWriteToFile(to))?;
+            }
+        }
+        let to = rotated_path(&self.path, 1);
+        fs::rename(&self.path, &to).map_err(|_| ServerError::FailedToWriteToFile(to))?;
+
+        self.file_entries.push_front(0);
+        if self.file_entries.len() > ROTATED_HISTORY_FILES + 1
+            && let Some(dropped) = self.file_entries.pop_back()
+        {
+            self.entries.drain(..dropped);
+        }
+        Ok(())
+    }
+}
+
+fn read_entries(path: &str) -> Result<Vec<HistoryEntry>, ServerError> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) => match e.kind() {
+            std::io::ErrorKind::NotFound => return Ok(vec![]),
+            _ => return Err(ServerError::FailedToReadFile(path.to_string())),
+        },
+    };
+
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|_| ServerError::FailedToReadFile(path.to_string()))?;
+        // a line torn by a crash mid-write is dropped rather than failing the log
+        if let Ok(entry) = serde_json::from_str(&line) {
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Records an event, logging rather than failing if the log can't be written.
+pub async fn record(history: &HistoryMutex, event: HistoryEvent) {
+    if let Err(e) = history.lock().await.record(event) {
+        println!("Failed to record history: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Zone;
+    use chrono::TimeZone;
+    use std::path::{Path, PathBuf};
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+    const ZONE_2: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone2,
+    };
+
+    /// A log path of its own for each test, with any files from a previous
+    /// run cleared away.
+    fn log_path(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("history-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(".history.jsonl")
+    }
+
+    fn toggled(id: u64, hour: u32, zone: ZoneId) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            timestamp: Local.with_ymd_and_hms(2025, 6, 2, hour, 0, 0).unwrap(),
+            event: HistoryEvent::ZoneToggled {
+                zone,
+                activate: true,
+                source: ZoneSource::User,
+            },
+        }
+    }
+
+    fn write(path: &str, entries: &[HistoryEntry]) {
+        let lines: Vec<String> = entries
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap())
+            .collect();
+        fs::write(path, lines.join("\n") + "\n").unwrap();
+    }
+
+    fn ids(entries: &[HistoryEntry]) -> Vec<u64> {
+        entries.iter().map(|entry| entry.id).collect()
+    }
+
+    #[test]
+    fn filters_by_time_and_zone() {
+        let path = log_path("filters");
+        let path = path.to_str().unwrap();
+        write(
+            path,
+            &[
+                toggled(0, 6, ZONE_1),
+                toggled(1, 7, ZONE_2),
+                toggled(2, 8, ZONE_1),
+                HistoryEntry {
+                    id: 3,
+                    timestamp: Local.with_ymd_and_hms(2025, 6, 2, 9, 0, 0).unwrap(),
+                    event: HistoryEvent::ConfigChanged {
+                        field: "schedules".to_string(),
+                    },
+                },
+            ],
+        );
+        let history = History::open(path).unwrap();
+
+        let all = history.entries(&HistoryFilter::default()).unwrap();
+        assert_eq!(ids(&all), vec![0, 1, 2, 3]);
+
+        // from is inclusive, to isn't
+        let filter = HistoryFilter {
+            from: Some(Local.with_ymd_and_hms(2025, 6, 2, 7, 0, 0).unwrap()),
+            to: Some(Local.with_ymd_and_hms(2025, 6, 2, 9, 0, 0).unwrap()),
+            ..HistoryFilter::default()
+        };
+        assert_eq!(ids(&history.entries(&filter).unwrap()), vec![1, 2]);
+
+        // entries without a zone are left out once zones are asked for
+        let filter = HistoryFilter {
+            zones: vec![ZONE_1],
+            ..HistoryFilter::default()
+        };
+        assert_eq!(ids(&history.entries(&filter).unwrap()), vec![0, 2]);
+        let _ = fs::remove_dir_all(Path::new(path).parent().unwrap());
+    }
+
+    #[test]
+    fn pages_newest_first() {
+        let path = log_path("paging");
+        let path = path.to_str().unwrap();
+        let entries: Vec<HistoryEntry> = (0..5)
+            .map(|id| toggled(id, id as u32, if id % 2 == 0 { ZONE_1 } else { ZONE_2 }))
+            .collect();
+        write(path, &entries);
+        let history = History::open(path).unwrap();
+        let everything = HistoryFilter::default();
+
+        let (page, total) = history.query(&everything, 0, 2).unwrap();
+        assert_eq!((ids(&page), total), (vec![4, 3], 5));
+        let (page, total) = history.query(&everything, 4, 2).unwrap();
+        assert_eq!((ids(&page), total), (vec![0], 5));
+        let (page, total) = history.query(&everything, 5, 2).unwrap();
+        assert_eq!((ids(&page), total), (vec![], 5));
+
+        // the total counts matches, not entries
+        let zone_1 = HistoryFilter {
+            zones: vec![ZONE_1],
+            ..HistoryFilter::default()
+        };
+        let (page, total) = history.query(&zone_1, 1, 10).unwrap();
+        assert_eq!((ids(&page), total), (vec![2, 0], 3));
+        let _ = fs::remove_dir_all(Path::new(path).parent().unwrap());
+    }
+
+    #[test]
+    fn carries_on_from_the_last_id() {
+        let path = log_path("ids");
+        let path = path.to_str().unwrap();
+        write(path, &[toggled(0, 6, ZONE_1), toggled(1, 7, ZONE_1)]);
+        let mut history = History::open(path).unwrap();
+
+        let entry = history
+            .record(HistoryEvent::ScheduleStarted {
+                schedule: "a".to_string(),
+            })
+            .unwrap();
+        assert_eq!(entry.id, 2);
+        // kept in memory and on disk
+        assert_eq!(
+            history.query(&HistoryFilter::default(), 0, 1).unwrap().0[0].id,
+            2
+        );
+        assert_eq!(History::open(path).unwrap().next_id, 3);
+        let _ = fs::remove_dir_all(Path::new(path).parent().unwrap());
+    }
+
+    #[test]
+    fn rotates_and_forgets_the_oldest_log() {
+        let path = log_path("rotation");
+        let path = path.to_str().unwrap();
+        for n in 1..=ROTATED_HISTORY_FILES {
+            let id = (ROTATED_HISTORY_FILES - n) as u64;
+            write(&rotated_path(path, n), &[toggled(id, 6, ZONE_1)]);
+        }
+        // a full current log, made of a line too torn to read
+        fs::write(path, "x".repeat(MAX_HISTORY_FILE_BYTES as usize)).unwrap();
+
+        let mut history = History::open(path).unwrap();
+        assert_eq!(
+            ids(&history.entries(&HistoryFilter::default()).unwrap()),
+            vec![0, 1, 2, 3]
+        );
+
+        history
+            .record(HistoryEvent::ScheduleStarted {
+                schedule: "a".to_string(),
+            })
+            .unwrap();
+
+        // the oldest log went, and the full one took its place as the newest
+        assert_eq!(
+            ids(&history.entries(&HistoryFilter::default()).unwrap()),
+            vec![1, 2, 3, 4]
+        );
+        assert!(fs::metadata(path).unwrap().len() < 1024);
+        assert_eq!(
+            fs::metadata(rotated_path(path, 1)).unwrap().len(),
+            MAX_HISTORY_FILE_BYTES
+        );
+        assert_eq!(
+            ids(&History::open(path)
+                .unwrap()
+                .entries(&HistoryFilter::default())
+                .unwrap()),
+            vec![1, 2, 3, 4]
+        );
+        let _ = fs::remove_dir_all(Path::new(path).parent().unwrap());
+    }
+}
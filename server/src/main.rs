@@ -1,82 +1,310 @@
+mod audit;
+mod auth;
+mod budget;
+mod cli;
 mod config;
+mod controllers;
 mod error;
+mod flow;
+mod freeze;
+mod handshake;
+mod history;
 mod message;
+mod pairing;
+mod restrictions;
 mod scheduler_runner;
+mod sensors;
+mod tls;
 mod types;
+mod usage;
+mod weather;
+mod zone_state;
 
+use chrono::Local;
+use futures_util::stream::SplitSink;
 use futures_util::{SinkExt, StreamExt};
-use shared::ControllerMessage;
+use shared::{ChallengePayload, ControllerMessage, ServerHandshake, Signer};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Mutex;
 use tokio::sync::mpsc::unbounded_channel;
-use tokio_tungstenite::accept_async;
+use tokio_native_tls::TlsAcceptor;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, accept_async};
 
+use crate::audit::{AUDIT_FILE_PATH, Audit};
+use crate::auth::{Principal, authenticate};
+use crate::cli::{Command, USAGE};
 use crate::config::Config;
+use crate::controllers::controller_states;
+use crate::freeze::freeze_guard_task;
+use crate::handshake::{Handshake, challenge, identify};
+use crate::history::{HISTORY_FILE_PATH, History, HistoryEvent};
 use crate::message::server::ServerResponse;
 use crate::message::server::controller_heartbeat::ControllerHeartbeatPayload;
+use crate::message::server::pairing_request::PairingRequestPayload;
 use crate::message::user::UserMessage;
 use crate::message::{
-    handle_controller_message, handle_server_message, handle_user_message, send_to_client,
+    handle_controller_message, handle_server_message, handle_user_message, send_to_session,
 };
+use crate::pairing::{Admission, Pairing};
 use crate::scheduler_runner::ScheduleRunner;
-use crate::types::{ClientMap, ClientType, ConfigMutex, ControllerTimestamp, ScheduleRunnerMutex};
+use crate::sensors::Sensors;
+use crate::types::{
+    AuditMutex, Client, ClientMap, ClientType, ConfigMutex, ControllerTimestamps, DeviceId,
+    HistoryMutex, PairingMutex, ScheduleRunnerMutex, SensorsMutex, SessionId, ZoneStateMutex,
+};
+use crate::zone_state::ZoneState;
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
 
 #[tokio::main]
 async fn main() {
-    let listener = TcpListener::bind("0.0.0.0:9001").await.unwrap();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match cli::parse(&args) {
+        Ok(Command::Serve(options)) => options,
+        Ok(Command::MintToken { name, role }) => std::process::exit(cli::mint(&name, role)),
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
     let clients: ClientMap = types::ClientMap::default();
-    let controller_timestamp: ControllerTimestamp = Arc::new(Mutex::new(None));
+    let controller_timestamps: ControllerTimestamps = ControllerTimestamps::default();
     let config: ConfigMutex = Arc::new(Mutex::new(Config::load().unwrap()));
+    let tls = match options.tls(&*config.lock().await) {
+        Ok(tls) => tls,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    let listener = TcpListener::bind(("0.0.0.0", options.port()))
+        .await
+        .unwrap();
+    // a bad certificate fails at startup rather than on the first connection
+    let tls_listener = match tls {
+        Some(tls) => Some((
+            TcpListener::bind(("0.0.0.0", tls.port)).await.unwrap(),
+            tls.acceptor().unwrap(),
+        )),
+        None => None,
+    };
+    let zone_state: ZoneStateMutex = Arc::new(Mutex::new(ZoneState::new(&*config.lock().await)));
+    let history: HistoryMutex = Arc::new(Mutex::new(History::open(HISTORY_FILE_PATH).unwrap()));
+    let sensors: SensorsMutex = Arc::new(Mutex::new(Sensors::default()));
+    let pairing: PairingMutex = Arc::new(Mutex::new(Pairing::default()));
+    let audit: AuditMutex = Arc::new(Mutex::new(Audit::open(AUDIT_FILE_PATH).unwrap()));
     let schedule_runner: ScheduleRunnerMutex = Arc::new(Mutex::new(ScheduleRunner::new(
         config.lock().await.clone(),
         &clients,
+        &zone_state,
+        &history,
+        &sensors,
     )));
 
+    tokio::spawn(freeze_guard_task(
+        clients.clone(),
+        config.clone(),
+        zone_state.clone(),
+        history.clone(),
+    ));
+
     // Spawn heartbeat task
     let heartbeat_clients = clients.clone();
-    let heartbeat_timestamp = controller_timestamp.clone();
+    let heartbeat_config = config.clone();
+    let heartbeat_timestamps = controller_timestamps.clone();
+    let heartbeat_sensors = sensors.clone();
     tokio::spawn(async move {
-        heartbeat_task(heartbeat_clients, heartbeat_timestamp).await;
+        heartbeat_task(
+            heartbeat_clients,
+            heartbeat_config,
+            heartbeat_timestamps,
+            heartbeat_sensors,
+        )
+        .await;
     });
 
-    while let Ok((stream, _)) = listener.accept().await {
+    if let Some((tls_listener, tls_acceptor)) = tls_listener {
+        println!(
+            "Listening for wss:// on {}",
+            tls_listener.local_addr().unwrap()
+        );
+        tokio::spawn(accept_loop(
+            tls_listener,
+            Some(tls_acceptor),
+            clients.clone(),
+            controller_timestamps.clone(),
+            config.clone(),
+            schedule_runner.clone(),
+            zone_state.clone(),
+            history.clone(),
+            sensors.clone(),
+            pairing.clone(),
+            audit.clone(),
+        ));
+    }
+
+    println!("Listening for ws:// on {}", listener.local_addr().unwrap());
+    accept_loop(
+        listener,
+        None,
+        clients,
+        controller_timestamps,
+        config,
+        schedule_runner,
+        zone_state,
+        history,
+        sensors,
+        pairing,
+        audit,
+    )
+    .await;
+}
+
+/// Serves every connection to `listener`, over TLS when `tls` is set.
+#[allow(clippy::too_many_arguments)]
+async fn accept_loop(
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
+    clients: ClientMap,
+    controller_timestamps: ControllerTimestamps,
+    config: ConfigMutex,
+    schedule_runner: ScheduleRunnerMutex,
+    zone_state: ZoneStateMutex,
+    history: HistoryMutex,
+    sensors: SensorsMutex,
+    pairing: PairingMutex,
+    audit: AuditMutex,
+) {
+    while let Ok((stream, address)) = listener.accept().await {
         let clients = clients.clone();
-        let controller_timestamp = controller_timestamp.clone();
+        let controller_timestamps = controller_timestamps.clone();
         let config = config.clone();
         let schedule_runner = schedule_runner.clone();
+        let zone_state = zone_state.clone();
+        let history = history.clone();
+        let sensors = sensors.clone();
+        let pairing = pairing.clone();
+        let audit = audit.clone();
+        let tls = tls.clone();
 
         tokio::spawn(async move {
+            let stream = match tls {
+                Some(tls) => match tls.accept(stream).await {
+                    Ok(stream) => MaybeTlsStream::NativeTls(stream),
+                    Err(e) => {
+                        println!("TLS handshake with {address} failed: {e}");
+                        return;
+                    }
+                },
+                None => MaybeTlsStream::Plain(stream),
+            };
             let ws_stream = match accept_async(stream).await {
                 Ok(ws) => ws,
                 Err(_) => return,
             };
             let (mut write, mut read) = ws_stream.split();
 
+            // controllers answer this to show they hold their secret
+            let challenge = challenge();
+            let greeting = ServerHandshake::Challenge(ChallengePayload {
+                challenge: &challenge,
+            });
+            if write
+                .send(Message::Text(serde_json::to_string(&greeting).unwrap()))
+                .await
+                .is_err()
+            {
+                return;
+            }
+
             // get client type
             let Some(Ok(msg)) = read.next().await else {
                 return;
             };
 
             // parse client type
-            let client_type = match msg.to_text().unwrap().to_string().as_str() {
-                "user" => ClientType::User,
-                "controller" => ClientType::Controller,
-                _ => return,
+            let Some(handshake) = msg
+                .to_text()
+                .ok()
+                .and_then(|text| identify(text, &challenge))
+            else {
+                println!("Refused a connection without a valid handshake");
+                close(&mut write, "Invalid handshake").await;
+                return;
+            };
+            let (client_type, device_id, principal, signer) = match handshake {
+                Handshake::User { token } => {
+                    let principal = {
+                        let mut config_guard = config.lock().await;
+                        if let Err(e) = config_guard.reload_tokens() {
+                            println!("Failed to reload tokens: {e}");
+                        }
+                        authenticate(&config_guard, &token)
+                    };
+                    let Some(principal) = principal else {
+                        println!("Refused a user from {address} with an invalid token");
+                        close(&mut write, "Invalid token").await;
+                        return;
+                    };
+                    println!("Authenticated user {} ({})", principal.name, principal.role);
+                    (ClientType::User, None, Some(principal), None)
+                }
+                Handshake::Controller { device_id, proof } => {
+                    // config before pairing, the order approvals take them in
+                    let config_guard = config.lock().await;
+                    let admission =
+                        pairing
+                            .lock()
+                            .await
+                            .admit(&config_guard, device_id, &proof, address);
+                    let signer = config_guard
+                        .controller(device_id)
+                        .filter(|_| admission == Admission::Approved)
+                        .map(|controller| Signer::new(controller.secret.as_bytes(), proof.nonce));
+                    drop(config_guard);
+                    let Some(signer) = signer else {
+                        refuse_controller(&clients, &pairing, &mut write, device_id, admission)
+                            .await;
+                        return;
+                    };
+                    (ClientType::Controller, Some(device_id), None, Some(signer))
+                }
             };
 
             // create and store channel for client
+            let session = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
             let (tx, mut rx) = unbounded_channel();
             {
                 let mut clients = clients.lock().await;
-                if clients.contains_key(&client_type) {
-                    clients.remove(&client_type);
+                // users can have several sessions open, but a controller that
+                // reconnects replaces its old session
+                if device_id.is_some() {
+                    clients.retain(|_, client| client.device_id != device_id);
                 }
-                clients.insert(client_type, tx);
+                clients.insert(
+                    session,
+                    Client {
+                        client_type,
+                        device_id,
+                        signer,
+                        sender: tx,
+                    },
+                );
             }
 
-            println!("Connected: {client_type}");
+            println!("Connected: {client_type} (session {session})");
+            if let Some(controller) = device_id {
+                println!("Controller {controller} identified");
+                history::record(&history, HistoryEvent::ControllerConnected { controller }).await;
+            }
 
             // spawn task in charge of sending messages
             let write_task = tokio::spawn(async move {
@@ -94,48 +322,113 @@ async fn main() {
 
                     handle_incoming_message(
                         &clients,
-                        &controller_timestamp,
+                        session,
+                        &controller_timestamps,
                         client_type,
+                        device_id,
+                        principal.as_ref(),
                         text,
                         &config,
                         &schedule_runner,
+                        &zone_state,
+                        &history,
+                        &sensors,
+                        &pairing,
+                        &audit,
+                        address,
                     )
                     .await;
                 }
             }
 
             // handle disconnect
-            println!("Disconnected: {client_type}");
+            println!("Disconnected: {client_type} (session {session})");
+            if let Some(controller) = device_id {
+                history::record(
+                    &history,
+                    HistoryEvent::ControllerDisconnected { controller },
+                )
+                .await;
+            }
             {
                 let mut clients = clients.lock().await;
-                clients.remove(&client_type);
+                clients.remove(&session);
             }
             write_task.abort();
         });
     }
 }
 
-async fn heartbeat_task(clients: ClientMap, controller_timestamp: ControllerTimestamp) {
+/// Turns away a controller that isn't approved. Users hear about devices
+/// the first time they ask to be paired, a few a minute at most.
+async fn refuse_controller(
+    clients: &ClientMap,
+    pairing: &PairingMutex,
+    write: &mut SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
+    device_id: DeviceId,
+    admission: Admission,
+) {
+    println!("Refused controller {device_id}: {admission}");
+
+    if admission == Admission::Pending
+        && let Some(controller) = pairing.lock().await.announce(device_id, Local::now())
+    {
+        handle_server_message(
+            clients,
+            ClientType::User,
+            ServerResponse::PairingRequest(PairingRequestPayload { controller }),
+        )
+        .await;
+    }
+
+    close(write, &format!("Controller {admission}")).await;
+}
+
+/// Ends a connection that failed its handshake, with a policy violation
+/// close code so clients know not to retry as is.
+async fn close(
+    write: &mut SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
+    reason: &str,
+) {
+    let _ = write
+        .send(Message::Close(Some(CloseFrame {
+            code: CloseCode::Policy,
+            reason: reason.to_string().into(),
+        })))
+        .await;
+}
+
+async fn heartbeat_task(
+    clients: ClientMap,
+    config: ConfigMutex,
+    controller_timestamps: ControllerTimestamps,
+    sensors: SensorsMutex,
+) {
     let mut interval = tokio::time::interval(Duration::from_secs(5)); // Check every 5 seconds
 
     loop {
         interval.tick().await;
 
-        let is_controller_connected = {
-            let timestamp_guard = controller_timestamp.lock().await;
-            if let Some(last_message) = *timestamp_guard {
-                last_message.elapsed() < Duration::from_secs(15)
-            } else {
-                false
-            }
-        };
-
-        if clients.lock().await.contains_key(&ClientType::User) {
+        let has_users = clients
+            .lock()
+            .await
+            .values()
+            .any(|client| client.client_type == ClientType::User);
+        if has_users {
+            let controllers = controller_states(
+                &*config.lock().await,
+                &controller_timestamps,
+                &*sensors.lock().await,
+            )
+            .await;
             handle_server_message(
                 &clients,
                 ClientType::User,
                 ServerResponse::ControllerHeartbeat(ControllerHeartbeatPayload {
-                    is_controller_connected,
+                    is_controller_connected: controllers
+                        .iter()
+                        .any(|controller| controller.is_connected),
+                    controllers,
                 }),
             )
             .await
@@ -143,23 +436,36 @@ async fn heartbeat_task(clients: ClientMap, controller_timestamp: ControllerTime
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_incoming_message(
     clients: &ClientMap,
-    controller_timestamp: &ControllerTimestamp,
+    session: SessionId,
+    controller_timestamps: &ControllerTimestamps,
     client_type: ClientType,
+    device_id: Option<DeviceId>,
+    principal: Option<&Principal>,
     text: &str,
     config: &ConfigMutex,
     schedule_runner: &ScheduleRunnerMutex,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    sensors: &SensorsMutex,
+    pairing: &PairingMutex,
+    audit: &AuditMutex,
+    address: SocketAddr,
 ) {
     match client_type {
         ClientType::User => {
+            let Some(principal) = principal else {
+                return;
+            };
             let parsed_msg: UserMessage = match serde_json::from_str(text) {
                 Ok(msg) => msg,
                 Err(e) => {
                     println!("Error parsing message: {e}");
-                    send_to_client(
-                        &clients,
-                        &ClientType::Controller,
+                    send_to_session(
+                        clients,
+                        session,
                         &format!("Error parsing user message: {e}"),
                     )
                     .await;
@@ -168,19 +474,30 @@ pub async fn handle_incoming_message(
             };
 
             handle_user_message(
-                &clients,
-                &controller_timestamp,
-                &config,
-                &schedule_runner,
+                clients,
+                session,
+                principal,
+                address,
+                controller_timestamps,
+                config,
+                schedule_runner,
+                zone_state,
+                history,
+                sensors,
+                pairing,
+                audit,
                 parsed_msg,
             )
             .await;
         }
         ClientType::Controller => {
-            {
-                let mut timestamp_guard = controller_timestamp.lock().await;
-                *timestamp_guard = Some(Instant::now());
-            }
+            let Some(device_id) = device_id else {
+                return;
+            };
+            controller_timestamps
+                .lock()
+                .await
+                .insert(device_id, Instant::now());
 
             let parsed_msg: ControllerMessage = match serde_json::from_str(text) {
                 Ok(msg) => msg,
@@ -190,7 +507,10 @@ pub async fn handle_incoming_message(
                 }
             };
 
-            handle_controller_message(clients, parsed_msg).await;
+            handle_controller_message(
+                clients, device_id, config, zone_state, history, sensors, parsed_msg,
+            )
+            .await;
         }
     }
 }
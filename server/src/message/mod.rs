@@ -3,80 +3,318 @@ pub mod user;
 
 use tokio_tungstenite::tungstenite::Message;
 
+use crate::audit::{self, Actor, AuditAction, config_diff};
+use crate::auth::Principal;
+use crate::budget::derived_durations;
+use crate::config::validate::FieldError;
+use crate::config::{Config, MAX_SEASONAL_ADJUSTMENT_PERCENT};
+use crate::controllers::{ControllerConfig, controller_states};
+use crate::error::ServerError;
+use crate::flow::{FlowAlert, configured_flows};
+use crate::history::{self, HistoryEvent, HistoryFilter, ZoneSource};
 use crate::message::server::ServerResponse;
+use crate::message::server::flow_alert::FlowAlertPayload;
+use crate::message::user::approve_controller::ApproveControllerResponse;
+use crate::message::user::conflict::ConflictResponse;
+use crate::message::user::create_schedule::CreateScheduleResponse;
+use crate::message::user::delete_schedule::DeleteScheduleResponse;
+use crate::message::user::duplicate_schedule::DuplicateScheduleResponse;
+use crate::message::user::forbidden::ForbiddenResponse;
+use crate::message::user::get_audit_log::{
+    DEFAULT_AUDIT_LOG_LIMIT, GetAuditLogResponse, MAX_AUDIT_LOG_LIMIT,
+};
 use crate::message::user::get_config::GetConfigResponse;
+use crate::message::user::get_history::{
+    DEFAULT_HISTORY_LIMIT, GetHistoryResponse, MAX_HISTORY_LIMIT,
+};
+use crate::message::user::get_pending_controllers::GetPendingControllersResponse;
+use crate::message::user::get_upcoming_runs::{GetUpcomingRunsResponse, MAX_UPCOMING_RUN_DAYS};
+use crate::message::user::get_usage_report::GetUsageReportResponse;
+use crate::message::user::reject_controller::RejectControllerResponse;
+use crate::message::user::reorder_schedules::ReorderSchedulesResponse;
+use crate::message::user::report_soil_moisture::ReportSoilMoistureResponse;
+use crate::message::user::set_rain_delay::SetRainDelayResponse;
+use crate::message::user::set_restriction_stage::SetRestrictionStageResponse;
 use crate::message::user::set_schedule::SetScheduleResponse;
-use crate::message::user::status::StatusResponse;
+use crate::message::user::set_seasonal_adjustment::SetSeasonalAdjustmentResponse;
+use crate::message::user::status::{LastSkip, StatusResponse};
 use crate::message::user::toggle_zone::ToggleZoneResponse;
+use crate::message::user::update_schedule::UpdateScheduleResponse;
 use crate::message::user::{UserMessage, UserMessageResponse};
-use crate::types::{ClientMap, ClientType, ConfigMutex, ControllerTimestamp, ScheduleRunnerMutex};
+use crate::restrictions::schedule_violations;
+use crate::scheduler_runner::conflicts::{ScheduleConflict, find_conflicts};
+use crate::scheduler_runner::projection::upcoming_runs;
+use crate::sensors::{FlowReading, record_soil_moisture};
+use crate::types::{
+    AuditMutex, ClientMap, ClientType, ConfigMutex, ControllerTimestamps, DeviceId, HistoryMutex,
+    PairingMutex, Schedule, ScheduleRunnerMutex, Schedules, SensorsMutex, SessionId, Zone, ZoneId,
+    ZoneStateMutex,
+};
+use crate::usage::{to_csv, usage_report};
+use crate::zone_state::toggle_zone;
 
-use shared::{ControllerMessage, ServerMessage, ToggleZonePayload};
+use chrono::Local;
+use shared::{ControllerMessage, ServerMessage};
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use uuid::Uuid;
 
+/// Sends `message` to every session of `client_type`. Returns whether any of
+/// them got it.
 pub async fn send_to_client(clients: &ClientMap, client_type: &ClientType, message: &str) -> bool {
     let clients = clients.lock().await;
-    if let Some(sender) = clients.get(client_type) {
-        sender.send(Message::Text(message.to_string())).is_ok()
+    let mut sent = false;
+    for client in clients
+        .values()
+        .filter(|client| client.client_type == *client_type)
+    {
+        sent |= client
+            .sender
+            .send(Message::Text(message.to_string()))
+            .is_ok();
+    }
+    sent
+}
+
+pub async fn send_to_session(clients: &ClientMap, session: SessionId, message: &str) -> bool {
+    let clients = clients.lock().await;
+    if let Some(client) = clients.get(&session) {
+        client
+            .sender
+            .send(Message::Text(message.to_string()))
+            .is_ok()
+    } else {
+        false
+    }
+}
+
+/// Signs `message` for the controller's session and sends it.
+pub async fn send_to_controller(
+    clients: &ClientMap,
+    device_id: DeviceId,
+    message: &ServerMessage,
+) -> bool {
+    let mut clients = clients.lock().await;
+    if let Some(client) = clients
+        .values_mut()
+        .find(|client| client.device_id == Some(device_id))
+        && let Some(signer) = client.signer.as_mut()
+    {
+        client
+            .sender
+            .send(Message::Text(signer.sign(message)))
+            .is_ok()
     } else {
         false
     }
 }
 
-pub async fn send_to_controller(clients: &ClientMap, message: &str) -> bool {
-    send_to_client(clients, &ClientType::Controller, message).await
+/// Validates and saves `schedules` in place of the saved ones, restarting
+/// the scheduler tasks of only the schedules that changed.
+#[allow(clippy::too_many_arguments)]
+async fn save_schedules(
+    config: &mut Config,
+    schedules: Schedules,
+    actor: &Actor,
+    clients: &ClientMap,
+    schedule_runner: &ScheduleRunnerMutex,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    sensors: &SensorsMutex,
+    audit: &AuditMutex,
+) -> Result<Vec<ScheduleConflict>, Vec<FieldError>> {
+    config.validate_schedules(&schedules)?;
+
+    let mut new_config = config.clone();
+    new_config.set_schedules(schedules);
+    new_config
+        .save()
+        .map_err(|e| vec![FieldError::new("schedules", e.to_string())])?;
+
+    let changes = config_diff(config, &new_config);
+    schedule_runner.lock().await.update_schedules(
+        config,
+        &new_config,
+        clients,
+        zone_state,
+        history,
+        sensors,
+    );
+    *config = new_config;
+    history::record(
+        history,
+        HistoryEvent::ConfigChanged {
+            field: "schedules".to_string(),
+        },
+    )
+    .await;
+    audit::record(
+        audit,
+        actor,
+        AuditAction::ConfigChanged {
+            field: "schedules".to_string(),
+            changes,
+        },
+    )
+    .await;
+
+    Ok(find_conflicts(config))
 }
 
-pub async fn send_to_user(clients: &ClientMap, message: &str) -> bool {
-    send_to_client(clients, &ClientType::User, message).await
+fn unknown_schedule(id: Uuid) -> FieldError {
+    FieldError::new("id", format!("No schedule has id {id}"))
 }
 
+/// Refuses a change made against an older config than the saved one, so it
+/// can't overwrite edits the user hasn't seen.
+async fn send_conflict(
+    clients: &ClientMap,
+    session: SessionId,
+    request: &str,
+    revision: u64,
+    current_revision: u64,
+) {
+    println!("Refused {request}: based on revision {revision}, config is at {current_revision}");
+    let response = ConflictResponse {
+        request: request.to_string(),
+        revision,
+        current_revision,
+        error: ServerError::RevisionConflict {
+            revision,
+            current: current_revision,
+        }
+        .to_string(),
+    };
+    send_to_session(
+        clients,
+        session,
+        &serde_json::to_string(&UserMessageResponse::ConflictResponse(response)).unwrap(),
+    )
+    .await;
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_user_message(
     clients: &ClientMap,
-    controller_timestamp: &ControllerTimestamp,
+    session: SessionId,
+    principal: &Principal,
+    address: SocketAddr,
+    controller_timestamps: &ControllerTimestamps,
     config: &ConfigMutex,
     schedule_runner: &ScheduleRunnerMutex,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    sensors: &SensorsMutex,
+    pairing: &PairingMutex,
+    audit: &AuditMutex,
     msg: UserMessage,
 ) {
     println!("User Message: {msg:?}");
 
+    let required_role = msg.required_role();
+    if !principal.role.allows(required_role) {
+        println!(
+            "Refused {} from {}: needs {required_role}",
+            msg.name(),
+            principal.name
+        );
+        let response = ForbiddenResponse {
+            request: msg.name(),
+            role: principal.role,
+            required_role,
+            error: ServerError::Forbidden {
+                role: principal.role,
+                required: required_role,
+            }
+            .to_string(),
+        };
+        send_to_session(
+            clients,
+            session,
+            &serde_json::to_string(&UserMessageResponse::ForbiddenResponse(response)).unwrap(),
+        )
+        .await;
+        return;
+    }
+
+    let actor = Actor {
+        principal: principal.name.clone(),
+        session,
+        address: address.to_string(),
+    };
+
     match msg {
         UserMessage::ToggleZone(payload) => {
-            send_to_controller(
+            let result = toggle_zone(
                 clients,
-                &serde_json::to_string(&ServerMessage::ToggleZone(ToggleZonePayload {
-                    zone: payload.zone,
-                    activate: payload.activate,
-                }))
-                .unwrap(),
+                zone_state,
+                history,
+                payload.zone,
+                payload.activate,
+                ZoneSource::User,
             )
             .await;
-
-            send_to_user(
-                clients,
-                &serde_json::to_string(&UserMessageResponse::ToggleZoneResponse(
+            let response = match result {
+                Ok(()) => {
+                    audit::record(
+                        audit,
+                        &actor,
+                        AuditAction::ZoneToggled {
+                            zone: payload.zone,
+                            activate: payload.activate,
+                        },
+                    )
+                    .await;
                     ToggleZoneResponse {
                         success: true,
                         error: None,
-                    },
-                ))
-                .unwrap(),
+                    }
+                }
+                Err(e) => ToggleZoneResponse {
+                    success: false,
+                    error: Some(e.to_string()),
+                },
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::ToggleZoneResponse(response)).unwrap(),
             )
             .await;
         }
         UserMessage::Status(_payload) => {
-            let is_controller_connected = {
-                let timestamp_guard = controller_timestamp.lock().await;
-                if let Some(last_message) = *timestamp_guard {
-                    last_message.elapsed() < std::time::Duration::from_secs(15)
-                } else {
-                    false
-                }
+            let (controllers, rain_sensor_active) = {
+                let config_guard = config.lock().await;
+                let sensors_guard = sensors.lock().await;
+                (
+                    controller_states(&config_guard, controller_timestamps, &sensors_guard).await,
+                    sensors_guard.rain_sensor_active(),
+                )
             };
 
-            send_to_user(
+            let last_skip = history.lock().await.last_skip().and_then(|entry| {
+                let HistoryEvent::ScheduleSkipped { schedule, reason } = &entry.event else {
+                    return None;
+                };
+                Some(LastSkip {
+                    schedule: schedule.clone(),
+                    timestamp: entry.timestamp,
+                    reason: reason.clone(),
+                })
+            });
+
+            send_to_session(
                 clients,
+                session,
                 &serde_json::to_string(&UserMessageResponse::StatusResponse(StatusResponse {
-                    is_controller_connected,
+                    is_controller_connected: controllers
+                        .iter()
+                        .any(|controller| controller.is_connected),
+                    rain_sensor_active,
+                    controllers,
+                    freeze_guard: zone_state.lock().await.freeze_guard().cloned(),
+                    last_skip,
                 }))
                 .unwrap(),
             )
@@ -85,50 +323,976 @@ pub async fn handle_user_message(
         UserMessage::KeepAlive(_payload) => {}
         UserMessage::SetSchedule(payload) => {
             let mut config_guard = config.lock().await;
-            config_guard.set_schedules(payload.schedules);
-            let response = match config_guard.save() {
-                Ok(_) => SetScheduleResponse {
-                    success: {
-                        let mut schedule_runner_guard = schedule_runner.lock().await;
-                        schedule_runner_guard.update(config_guard.clone(), clients);
-                        true
-                    },
-                    error: None,
+            if payload.revision != config_guard.revision {
+                send_conflict(
+                    clients,
+                    session,
+                    "setSchedule",
+                    payload.revision,
+                    config_guard.revision,
+                )
+                .await;
+                return;
+            }
+            let response = match save_schedules(
+                &mut config_guard,
+                payload.schedules,
+                &actor,
+                clients,
+                schedule_runner,
+                zone_state,
+                history,
+                sensors,
+                audit,
+            )
+            .await
+            {
+                Ok(conflicts) => SetScheduleResponse {
+                    success: true,
+                    errors: vec![],
+                    conflicts,
+                    revision: config_guard.revision,
                 },
-                Err(e) => SetScheduleResponse {
+                Err(errors) => SetScheduleResponse {
                     success: false,
-                    error: Some(e.to_string()),
+                    errors,
+                    conflicts: vec![],
+                    revision: config_guard.revision,
                 },
             };
 
-            send_to_user(
+            send_to_session(
                 clients,
+                session,
                 &serde_json::to_string(&UserMessageResponse::SetScheduleResponse(response))
                     .unwrap(),
             )
             .await;
         }
+        UserMessage::CreateSchedule(payload) => {
+            let mut config_guard = config.lock().await;
+            if payload.revision != config_guard.revision {
+                send_conflict(
+                    clients,
+                    session,
+                    "createSchedule",
+                    payload.revision,
+                    config_guard.revision,
+                )
+                .await;
+                return;
+            }
+            let id = Uuid::new_v4();
+            let mut schedules = config_guard.schedules.clone();
+            schedules.push(Schedule {
+                id,
+                ..payload.schedule
+            });
+
+            let response = match save_schedules(
+                &mut config_guard,
+                schedules,
+                &actor,
+                clients,
+                schedule_runner,
+                zone_state,
+                history,
+                sensors,
+                audit,
+            )
+            .await
+            {
+                Ok(conflicts) => CreateScheduleResponse {
+                    success: true,
+                    id: Some(id),
+                    errors: vec![],
+                    conflicts,
+                    revision: config_guard.revision,
+                },
+                Err(errors) => CreateScheduleResponse {
+                    success: false,
+                    id: None,
+                    errors,
+                    conflicts: vec![],
+                    revision: config_guard.revision,
+                },
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::CreateScheduleResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::UpdateSchedule(payload) => {
+            let mut config_guard = config.lock().await;
+            if payload.revision != config_guard.revision {
+                send_conflict(
+                    clients,
+                    session,
+                    "updateSchedule",
+                    payload.revision,
+                    config_guard.revision,
+                )
+                .await;
+                return;
+            }
+            let id = payload.schedule.id;
+            let mut schedules = config_guard.schedules.clone();
+            let result = match schedules.iter_mut().find(|schedule| schedule.id == id) {
+                Some(schedule) => {
+                    *schedule = payload.schedule;
+                    save_schedules(
+                        &mut config_guard,
+                        schedules,
+                        &actor,
+                        clients,
+                        schedule_runner,
+                        zone_state,
+                        history,
+                        sensors,
+                        audit,
+                    )
+                    .await
+                }
+                None => Err(vec![unknown_schedule(id)]),
+            };
+
+            let response = match result {
+                Ok(conflicts) => UpdateScheduleResponse {
+                    success: true,
+                    errors: vec![],
+                    conflicts,
+                    revision: config_guard.revision,
+                },
+                Err(errors) => UpdateScheduleResponse {
+                    success: false,
+                    errors,
+                    conflicts: vec![],
+                    revision: config_guard.revision,
+                },
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::UpdateScheduleResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::DeleteSchedule(payload) => {
+            let mut config_guard = config.lock().await;
+            if payload.revision != config_guard.revision {
+                send_conflict(
+                    clients,
+                    session,
+                    "deleteSchedule",
+                    payload.revision,
+                    config_guard.revision,
+                )
+                .await;
+                return;
+            }
+            let mut schedules = config_guard.schedules.clone();
+            let result = match schedules
+                .iter()
+                .position(|schedule| schedule.id == payload.id)
+            {
+                Some(index) => {
+                    schedules.remove(index);
+                    save_schedules(
+                        &mut config_guard,
+                        schedules,
+                        &actor,
+                        clients,
+                        schedule_runner,
+                        zone_state,
+                        history,
+                        sensors,
+                        audit,
+                    )
+                    .await
+                }
+                None => Err(vec![unknown_schedule(payload.id)]),
+            };
+
+            let response = DeleteScheduleResponse {
+                success: result.is_ok(),
+                errors: result.err().unwrap_or_default(),
+                revision: config_guard.revision,
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::DeleteScheduleResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::DuplicateSchedule(payload) => {
+            let mut config_guard = config.lock().await;
+            if payload.revision != config_guard.revision {
+                send_conflict(
+                    clients,
+                    session,
+                    "duplicateSchedule",
+                    payload.revision,
+                    config_guard.revision,
+                )
+                .await;
+                return;
+            }
+            let id = Uuid::new_v4();
+            let mut schedules = config_guard.schedules.clone();
+            let result = match schedules
+                .iter()
+                .position(|schedule| schedule.id == payload.id)
+            {
+                Some(index) => {
+                    let original = &schedules[index];
+                    let copy = Schedule {
+                        id,
+                        name: payload
+                            .name
+                            .unwrap_or_else(|| format!("{} (copy)", original.name)),
+                        ..original.clone()
+                    };
+                    schedules.insert(index + 1, copy);
+                    save_schedules(
+                        &mut config_guard,
+                        schedules,
+                        &actor,
+                        clients,
+                        schedule_runner,
+                        zone_state,
+                        history,
+                        sensors,
+                        audit,
+                    )
+                    .await
+                }
+                None => Err(vec![unknown_schedule(payload.id)]),
+            };
+
+            let response = match result {
+                Ok(conflicts) => DuplicateScheduleResponse {
+                    success: true,
+                    id: Some(id),
+                    errors: vec![],
+                    conflicts,
+                    revision: config_guard.revision,
+                },
+                Err(errors) => DuplicateScheduleResponse {
+                    success: false,
+                    id: None,
+                    errors,
+                    conflicts: vec![],
+                    revision: config_guard.revision,
+                },
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::DuplicateScheduleResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::ReorderSchedules(payload) => {
+            let mut config_guard = config.lock().await;
+            if payload.revision != config_guard.revision {
+                send_conflict(
+                    clients,
+                    session,
+                    "reorderSchedules",
+                    payload.revision,
+                    config_guard.revision,
+                )
+                .await;
+                return;
+            }
+            // repeated ids are caught by validation
+            let reordered: Option<Schedules> = payload
+                .ids
+                .iter()
+                .map(|&id| config_guard.schedule(id).cloned())
+                .collect();
+            let result = match reordered {
+                Some(schedules) if schedules.len() == config_guard.schedules.len() => {
+                    save_schedules(
+                        &mut config_guard,
+                        schedules,
+                        &actor,
+                        clients,
+                        schedule_runner,
+                        zone_state,
+                        history,
+                        sensors,
+                        audit,
+                    )
+                    .await
+                }
+                _ => Err(vec![FieldError::new(
+                    "ids",
+                    "Every schedule's id has to be given once",
+                )]),
+            };
+
+            let response = ReorderSchedulesResponse {
+                success: result.is_ok(),
+                errors: result.err().unwrap_or_default(),
+                revision: config_guard.revision,
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::ReorderSchedulesResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
         UserMessage::GetConfig(_payload) => {
             let config_guard = config.lock().await;
             let config = config_guard.clone();
             let response = GetConfigResponse {
+                revision: config.revision,
+                derived_durations: derived_durations(&config),
                 schedules: config.schedules,
                 stagger_on: config.stagger_on,
                 stagger_zones: config.stagger_zones,
+                restrictions: config.restrictions,
+                seasonal_adjustment_percent: config.seasonal_adjustment_percent,
+                rain_delay_until: config.rain_delay_until,
             };
 
-            send_to_user(
+            send_to_session(
                 clients,
+                session,
                 &serde_json::to_string(&UserMessageResponse::GetConfigResponse(response)).unwrap(),
             )
             .await;
         }
+        UserMessage::GetUpcomingRuns(payload) => {
+            let response = if payload.days == 0 || payload.days > MAX_UPCOMING_RUN_DAYS {
+                GetUpcomingRunsResponse {
+                    zones: Default::default(),
+                    skipped: vec![],
+                    error: Some(format!(
+                        "Days must be between 1 and {MAX_UPCOMING_RUN_DAYS}"
+                    )),
+                }
+            } else {
+                let upcoming = upcoming_runs(&*config.lock().await, payload.from, payload.days);
+                GetUpcomingRunsResponse {
+                    zones: upcoming.zones,
+                    skipped: upcoming.skipped,
+                    error: None,
+                }
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::GetUpcomingRunsResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::GetHistory(payload) => {
+            let filter = HistoryFilter {
+                from: payload.from,
+                to: payload.to,
+                zones: payload.zones,
+            };
+            let limit = payload
+                .limit
+                .unwrap_or(DEFAULT_HISTORY_LIMIT)
+                .min(MAX_HISTORY_LIMIT);
+
+            let response = match history.lock().await.query(&filter, payload.offset, limit) {
+                Ok((entries, total)) => GetHistoryResponse {
+                    entries,
+                    total,
+                    error: None,
+                },
+                Err(e) => GetHistoryResponse {
+                    entries: vec![],
+                    total: 0,
+                    error: Some(e.to_string()),
+                },
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::GetHistoryResponse(response)).unwrap(),
+            )
+            .await;
+        }
+        UserMessage::GetUsageReport(payload) => {
+            let filter = HistoryFilter {
+                to: Some(payload.to),
+                ..Default::default()
+            };
+            let entries = history.lock().await.entries(&filter);
+
+            let response = match entries {
+                _ if payload.from >= payload.to => GetUsageReportResponse {
+                    report: None,
+                    csv: None,
+                    error: Some("From must be before to".to_string()),
+                },
+                Ok(entries) => {
+                    let report =
+                        usage_report(&*config.lock().await, &entries, payload.from, payload.to);
+                    GetUsageReportResponse {
+                        csv: payload.include_csv.then(|| to_csv(&report)),
+                        report: Some(report),
+                        error: None,
+                    }
+                }
+                Err(e) => GetUsageReportResponse {
+                    report: None,
+                    csv: None,
+                    error: Some(e.to_string()),
+                },
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::GetUsageReportResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::ReportSoilMoisture(payload) => {
+            let result =
+                record_soil_moisture(sensors, history, payload.zone, payload.percent).await;
+            let response = match result {
+                Ok(()) => {
+                    audit::record(
+                        audit,
+                        &actor,
+                        AuditAction::SoilMoistureReported {
+                            zone: payload.zone,
+                            percent: payload.percent,
+                        },
+                    )
+                    .await;
+                    ReportSoilMoistureResponse {
+                        success: true,
+                        error: None,
+                    }
+                }
+                Err(e) => ReportSoilMoistureResponse {
+                    success: false,
+                    error: Some(e.to_string()),
+                },
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::ReportSoilMoistureResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::SetRestrictionStage(payload) => {
+            let mut config_guard = config.lock().await;
+            if payload.revision != config_guard.revision {
+                send_conflict(
+                    clients,
+                    session,
+                    "setRestrictionStage",
+                    payload.revision,
+                    config_guard.revision,
+                )
+                .await;
+                return;
+            }
+            let response = if !config_guard.restrictions.has_stage(payload.stage) {
+                SetRestrictionStageResponse {
+                    success: false,
+                    error: Some(format!("Stage {} is not configured", payload.stage)),
+                    violations: vec![],
+                    revision: config_guard.revision,
+                }
+            } else {
+                let mut new_config = config_guard.clone();
+                new_config.restrictions.active_stage = payload.stage;
+                match new_config.save() {
+                    Ok(_) => {
+                        let changes = config_diff(&config_guard, &new_config);
+                        *config_guard = new_config;
+                        history::record(
+                            history,
+                            HistoryEvent::ConfigChanged {
+                                field: "restrictions".to_string(),
+                            },
+                        )
+                        .await;
+                        audit::record(
+                            audit,
+                            &actor,
+                            AuditAction::ConfigChanged {
+                                field: "restrictions".to_string(),
+                                changes,
+                            },
+                        )
+                        .await;
+                        schedule_runner.lock().await.refresh(config_guard.clone());
+                        SetRestrictionStageResponse {
+                            success: true,
+                            error: None,
+                            violations: schedule_violations(&config_guard),
+                            revision: config_guard.revision,
+                        }
+                    }
+                    Err(e) => SetRestrictionStageResponse {
+                        success: false,
+                        error: Some(e.to_string()),
+                        violations: vec![],
+                        revision: config_guard.revision,
+                    },
+                }
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::SetRestrictionStageResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::SetRainDelay(payload) => {
+            let mut config_guard = config.lock().await;
+            if payload.revision != config_guard.revision {
+                send_conflict(
+                    clients,
+                    session,
+                    "setRainDelay",
+                    payload.revision,
+                    config_guard.revision,
+                )
+                .await;
+                return;
+            }
+
+            let mut new_config = config_guard.clone();
+            new_config.rain_delay_until = payload.until;
+            let response = match new_config.save() {
+                Ok(_) => {
+                    let changes = config_diff(&config_guard, &new_config);
+                    *config_guard = new_config;
+                    history::record(
+                        history,
+                        HistoryEvent::ConfigChanged {
+                            field: "rainDelayUntil".to_string(),
+                        },
+                    )
+                    .await;
+                    audit::record(
+                        audit,
+                        &actor,
+                        AuditAction::ConfigChanged {
+                            field: "rainDelayUntil".to_string(),
+                            changes,
+                        },
+                    )
+                    .await;
+                    schedule_runner.lock().await.refresh(config_guard.clone());
+                    SetRainDelayResponse {
+                        success: true,
+                        error: None,
+                        revision: config_guard.revision,
+                    }
+                }
+                Err(e) => SetRainDelayResponse {
+                    success: false,
+                    error: Some(e.to_string()),
+                    revision: config_guard.revision,
+                },
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::SetRainDelayResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::SetSeasonalAdjustment(payload) => {
+            let mut config_guard = config.lock().await;
+            if payload.revision != config_guard.revision {
+                send_conflict(
+                    clients,
+                    session,
+                    "setSeasonalAdjustment",
+                    payload.revision,
+                    config_guard.revision,
+                )
+                .await;
+                return;
+            }
+
+            let response =
+                if payload.percent == 0 || payload.percent > MAX_SEASONAL_ADJUSTMENT_PERCENT {
+                    SetSeasonalAdjustmentResponse {
+                        success: false,
+                        error: Some(format!(
+                            "Percent must be between 1 and {MAX_SEASONAL_ADJUSTMENT_PERCENT}"
+                        )),
+                        revision: config_guard.revision,
+                    }
+                } else {
+                    let mut new_config = config_guard.clone();
+                    new_config.seasonal_adjustment_percent = payload.percent;
+                    match new_config.save() {
+                        Ok(_) => {
+                            let changes = config_diff(&config_guard, &new_config);
+                            *config_guard = new_config;
+                            history::record(
+                                history,
+                                HistoryEvent::ConfigChanged {
+                                    field: "seasonalAdjustmentPercent".to_string(),
+                                },
+                            )
+                            .await;
+                            audit::record(
+                                audit,
+                                &actor,
+                                AuditAction::ConfigChanged {
+                                    field: "seasonalAdjustmentPercent".to_string(),
+                                    changes,
+                                },
+                            )
+                            .await;
+                            schedule_runner.lock().await.refresh(config_guard.clone());
+                            SetSeasonalAdjustmentResponse {
+                                success: true,
+                                error: None,
+                                revision: config_guard.revision,
+                            }
+                        }
+                        Err(e) => SetSeasonalAdjustmentResponse {
+                            success: false,
+                            error: Some(e.to_string()),
+                            revision: config_guard.revision,
+                        },
+                    }
+                };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::SetSeasonalAdjustmentResponse(
+                    response,
+                ))
+                .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::GetPendingControllers(_payload) => {
+            let response = GetPendingControllersResponse {
+                controllers: pairing.lock().await.pending(),
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::GetPendingControllersResponse(
+                    response,
+                ))
+                .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::ApproveController(payload) => {
+            let mut config_guard = config.lock().await;
+            if payload.revision != config_guard.revision {
+                send_conflict(
+                    clients,
+                    session,
+                    "approveController",
+                    payload.revision,
+                    config_guard.revision,
+                )
+                .await;
+                return;
+            }
+            let mut pairing_guard = pairing.lock().await;
+            let response = match pairing_guard.approve(payload.device_id) {
+                None => ApproveControllerResponse {
+                    success: false,
+                    error: Some(format!(
+                        "Controller {} is not awaiting approval",
+                        payload.device_id
+                    )),
+                    revision: config_guard.revision,
+                },
+                Some(pending) if !pending.proof.verifies(pending.device_id, &payload.secret) => {
+                    let error = format!(
+                        "The secret doesn't match the one controller {} identified with",
+                        pending.device_id
+                    );
+                    pairing_guard.restore(pending);
+                    ApproveControllerResponse {
+                        success: false,
+                        error: Some(error),
+                        revision: config_guard.revision,
+                    }
+                }
+                Some(pending) => {
+                    let mut new_config = config_guard.clone();
+                    new_config.controllers.push(ControllerConfig {
+                        device_id: pending.device_id,
+                        name: payload.name,
+                        secret: payload.secret,
+                    });
+                    // an upgraded install's zones belong to its first controller
+                    let adopted = new_config.adopt_legacy_zones();
+                    match new_config.save() {
+                        Ok(_) => {
+                            let changes = config_diff(&config_guard, &new_config);
+                            if adopted {
+                                schedule_runner.lock().await.update_schedules(
+                                    &config_guard,
+                                    &new_config,
+                                    clients,
+                                    zone_state,
+                                    history,
+                                    sensors,
+                                );
+                            }
+                            *config_guard = new_config;
+                            history::record(
+                                history,
+                                HistoryEvent::ConfigChanged {
+                                    field: "controllers".to_string(),
+                                },
+                            )
+                            .await;
+                            audit::record(
+                                audit,
+                                &actor,
+                                AuditAction::ConfigChanged {
+                                    field: "controllers".to_string(),
+                                    changes,
+                                },
+                            )
+                            .await;
+                            println!("Controller {} approved", pending.device_id);
+                            ApproveControllerResponse {
+                                success: true,
+                                error: None,
+                                revision: config_guard.revision,
+                            }
+                        }
+                        Err(e) => {
+                            // leave it pending so the approval can be retried
+                            pairing_guard.restore(pending);
+                            ApproveControllerResponse {
+                                success: false,
+                                error: Some(e.to_string()),
+                                revision: config_guard.revision,
+                            }
+                        }
+                    }
+                }
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::ApproveControllerResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::RejectController(payload) => {
+            let response = if config.lock().await.controller(payload.device_id).is_some() {
+                RejectControllerResponse {
+                    success: false,
+                    error: Some(format!(
+                        "Controller {} is already approved",
+                        payload.device_id
+                    )),
+                }
+            } else {
+                pairing.lock().await.reject(payload.device_id);
+                println!("Controller {} rejected", payload.device_id);
+                audit::record(
+                    audit,
+                    &actor,
+                    AuditAction::ControllerRejected {
+                        controller: payload.device_id,
+                    },
+                )
+                .await;
+                RejectControllerResponse {
+                    success: true,
+                    error: None,
+                }
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::RejectControllerResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
+        UserMessage::GetAuditLog(payload) => {
+            let limit = payload
+                .limit
+                .unwrap_or(DEFAULT_AUDIT_LOG_LIMIT)
+                .min(MAX_AUDIT_LOG_LIMIT);
+
+            let response = match audit.lock().await.query(payload.offset, limit) {
+                Ok((entries, total)) => GetAuditLogResponse {
+                    entries,
+                    total,
+                    error: None,
+                },
+                Err(e) => GetAuditLogResponse {
+                    entries: vec![],
+                    total: 0,
+                    error: Some(e.to_string()),
+                },
+            };
+
+            send_to_session(
+                clients,
+                session,
+                &serde_json::to_string(&UserMessageResponse::GetAuditLogResponse(response))
+                    .unwrap(),
+            )
+            .await;
+        }
     }
 }
 
-pub async fn handle_controller_message(_clients: &ClientMap, msg: ControllerMessage) {
+pub async fn handle_controller_message(
+    clients: &ClientMap,
+    device_id: DeviceId,
+    config: &ConfigMutex,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    sensors: &SensorsMutex,
+    msg: ControllerMessage,
+) {
     match msg {
         ControllerMessage::KeepAlive(_payload) => {}
+        ControllerMessage::RainSensor(payload) => {
+            let previous = sensors
+                .lock()
+                .await
+                .rain_sensors
+                .insert(device_id, payload.active);
+
+            if previous != Some(payload.active) {
+                println!(
+                    "Rain sensor on {device_id} {}",
+                    if payload.active { "active" } else { "clear" }
+                );
+                history::record(
+                    history,
+                    HistoryEvent::RainSensorChanged {
+                        controller: device_id,
+                        active: payload.active,
+                    },
+                )
+                .await;
+            }
+        }
+        ControllerMessage::SoilMoisture(payload) => {
+            let result = match Zone::try_from(payload.zone) {
+                Ok(zone) => {
+                    let zone = ZoneId::new(device_id, zone);
+                    record_soil_moisture(sensors, history, zone, payload.percent as f64).await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                println!("Ignored soil moisture reading: {e}");
+            }
+        }
+        ControllerMessage::Flow(payload) => {
+            let open_zones: BTreeSet<ZoneId> = zone_state
+                .lock()
+                .await
+                .open_zones()
+                .iter()
+                .filter(|zone| zone.controller == device_id)
+                .copied()
+                .collect();
+            let configured = configured_flows(&*config.lock().await);
+            let flow = payload.liters_per_minute as f64;
+
+            let alert = {
+                let mut sensors_guard = sensors.lock().await;
+                sensors_guard.flow.insert(
+                    device_id,
+                    FlowReading {
+                        liters_per_minute: flow,
+                        total_liters: payload.total_liters as f64,
+                    },
+                );
+                sensors_guard
+                    .flow_monitors
+                    .entry(device_id)
+                    .or_default()
+                    .update(flow, &open_zones, Local::now(), &configured)
+            };
+            let Some(alert) = alert else {
+                return;
+            };
+
+            println!("{alert}");
+            history::record(
+                history,
+                HistoryEvent::FlowAlert {
+                    alert: alert.clone(),
+                },
+            )
+            .await;
+            handle_server_message(
+                clients,
+                ClientType::User,
+                ServerResponse::FlowAlert(FlowAlertPayload {
+                    alert: alert.clone(),
+                }),
+            )
+            .await;
+
+            if let FlowAlert::HighFlow { zones, .. } = alert {
+                for zone in zones {
+                    if let Err(e) = toggle_zone(
+                        clients,
+                        zone_state,
+                        history,
+                        zone,
+                        false,
+                        ZoneSource::FlowAlert,
+                    )
+                    .await
+                    {
+                        println!("Failed to close {zone} after flow alert: {e}");
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -139,3 +1303,124 @@ pub async fn handle_server_message(
 ) {
     send_to_client(clients, &recipient, &serde_json::to_string(&msg).unwrap()).await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::Audit;
+    use crate::auth::Role;
+    use crate::history::History;
+    use crate::message::user::set_schedule::SetSchedulePayload;
+    use crate::pairing::Pairing;
+    use crate::scheduler_runner::ScheduleRunner;
+    use crate::sensors::Sensors;
+    use crate::types::Client;
+    use crate::zone_state::ZoneState;
+
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+
+    fn schedule(name: &str, duration_minutes: u32) -> Schedule {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "days": ["monday"],
+            "activePeriods": [{ "zone": ZONE_1, "durationMinutes": duration_minutes }],
+            "startTimeMinutes": 360,
+            "isActive": true,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn invalid_schedules_leave_the_config_unchanged() {
+        let before = Config {
+            revision: 3,
+            schedules: vec![schedule("lawn", 10)],
+            ..Config::default()
+        };
+        let config: ConfigMutex = Arc::new(Mutex::new(before.clone()));
+
+        let (sender, mut receiver) = unbounded_channel();
+        let clients = ClientMap::default();
+        clients.lock().await.insert(
+            1,
+            Client {
+                client_type: ClientType::User,
+                device_id: None,
+                signer: None,
+                sender,
+            },
+        );
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let history_path = dir.join(format!("set-schedule-test-history-{id}.jsonl"));
+        let audit_path = dir.join(format!("set-schedule-test-audit-{id}.jsonl"));
+        let history: HistoryMutex = Arc::new(Mutex::new(
+            History::open(history_path.to_str().unwrap()).unwrap(),
+        ));
+        let audit: AuditMutex = Arc::new(Mutex::new(
+            Audit::open(audit_path.to_str().unwrap()).unwrap(),
+        ));
+        let zone_state: ZoneStateMutex = Arc::new(Mutex::new(ZoneState::new(&before)));
+        let sensors: SensorsMutex = Arc::new(Mutex::new(Sensors::default()));
+        let schedule_runner: ScheduleRunnerMutex = Arc::new(Mutex::new(ScheduleRunner::new(
+            Config::default(),
+            &clients,
+            &zone_state,
+            &history,
+            &sensors,
+        )));
+
+        // the second period has no duration
+        let message = UserMessage::SetSchedule(SetSchedulePayload {
+            revision: 3,
+            schedules: vec![schedule("lawn", 20), schedule("beds", 0)],
+        });
+        handle_user_message(
+            &clients,
+            1,
+            &Principal {
+                name: "alice".to_string(),
+                role: Role::Admin,
+            },
+            "127.0.0.1:9001".parse().unwrap(),
+            &ControllerTimestamps::default(),
+            &config,
+            &schedule_runner,
+            &zone_state,
+            &history,
+            &sensors,
+            &PairingMutex::new(Mutex::new(Pairing::default())),
+            &audit,
+            message,
+        )
+        .await;
+
+        let response: serde_json::Value =
+            serde_json::from_str(&receiver.recv().await.unwrap().into_text().unwrap()).unwrap();
+        assert_eq!(response["type"], "setScheduleResponse");
+        assert_eq!(response["payload"]["success"], false);
+        assert_eq!(response["payload"]["revision"], 3);
+        assert_eq!(
+            response["payload"]["errors"][0]["field"],
+            format!(
+                "schedules[1].activePeriods[{}].durationMinutes",
+                ZONE_1.key()
+            )
+        );
+
+        let after = config.lock().await;
+        assert_eq!(after.revision, before.revision);
+        assert_eq!(after.schedules, before.schedules);
+        assert!(audit.lock().await.query(0, 10).unwrap().0.is_empty());
+
+        let _ = std::fs::remove_file(history_path);
+        let _ = std::fs::remove_file(audit_path);
+    }
+}
@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
 
+use crate::controllers::ControllerState;
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ControllerHeartbeatPayload {
+    /// Whether any controller is connected.
     pub is_controller_connected: bool,
+    pub controllers: Vec<ControllerState>,
 }
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+use crate::flow::FlowAlert;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowAlertPayload {
+    pub alert: FlowAlert,
+}
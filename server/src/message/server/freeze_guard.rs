@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FreezeGuardPayload {
+    pub active: bool,
+    /// In °C.
+    pub temp: f64,
+}
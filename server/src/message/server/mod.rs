@@ -1,10 +1,19 @@
 pub mod controller_heartbeat;
+pub mod flow_alert;
+pub mod freeze_guard;
+pub mod pairing_request;
 
 use crate::message::server::controller_heartbeat::ControllerHeartbeatPayload;
+use crate::message::server::flow_alert::FlowAlertPayload;
+use crate::message::server::freeze_guard::FreezeGuardPayload;
+use crate::message::server::pairing_request::PairingRequestPayload;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type", content = "payload", rename_all = "camelCase")]
 pub enum ServerResponse {
     ControllerHeartbeat(ControllerHeartbeatPayload),
+    FlowAlert(FlowAlertPayload),
+    FreezeGuard(FreezeGuardPayload),
+    PairingRequest(PairingRequestPayload),
 }
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+use crate::pairing::PendingController;
+
+/// Sent when a controller nobody has approved yet tries to connect.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PairingRequestPayload {
+    pub controller: PendingController,
+}
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Debug};
+
+use crate::audit::REDACTED;
+use crate::types::DeviceId;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApproveControllerPayload {
+    pub device_id: DeviceId,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The secret flashed into the controller, read off the device. It has
+    /// to match the proof the controller identified with.
+    pub secret: String,
+    /// The config revision the approval was made from.
+    pub revision: u64,
+}
+
+// user messages are logged, the secret mustn't be
+impl Debug for ApproveControllerPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApproveControllerPayload")
+            .field("device_id", &self.device_id)
+            .field("name", &self.name)
+            .field("secret", &REDACTED)
+            .field("revision", &self.revision)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApproveControllerResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    /// The config revision after the request.
+    pub revision: u64,
+}
@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+/// Sent instead of the usual response when a change was based on an older
+/// config than the saved one.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConflictResponse {
+    /// The type of the refused message, like `setSchedule`.
+    pub request: String,
+    /// The revision the request was based on.
+    pub revision: u64,
+    pub current_revision: u64,
+    pub error: String,
+}
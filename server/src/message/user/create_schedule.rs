@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::validate::FieldError;
+use crate::scheduler_runner::conflicts::ScheduleConflict;
+use crate::types::Schedule;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateSchedulePayload {
+    /// Added after the others. Its id is picked by the server, so can be
+    /// left out.
+    pub schedule: Schedule,
+    /// The config revision the schedule was made from.
+    pub revision: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateScheduleResponse {
+    pub success: bool,
+    /// The new schedule's id.
+    pub id: Option<Uuid>,
+    pub errors: Vec<FieldError>,
+    pub conflicts: Vec<ScheduleConflict>,
+    /// The config revision after the request.
+    pub revision: u64,
+}
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::validate::FieldError;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteSchedulePayload {
+    pub id: Uuid,
+    /// The config revision the schedule was deleted from.
+    pub revision: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteScheduleResponse {
+    pub success: bool,
+    pub errors: Vec<FieldError>,
+    /// The config revision after the request.
+    pub revision: u64,
+}
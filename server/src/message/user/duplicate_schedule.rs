@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::validate::FieldError;
+use crate::scheduler_runner::conflicts::ScheduleConflict;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateSchedulePayload {
+    /// The schedule to copy. The copy goes right after it.
+    pub id: Uuid,
+    /// The copy's name, by default the original's with " (copy)" added.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The config revision the schedule was copied from.
+    pub revision: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateScheduleResponse {
+    pub success: bool,
+    /// The copy's id.
+    pub id: Option<Uuid>,
+    pub errors: Vec<FieldError>,
+    pub conflicts: Vec<ScheduleConflict>,
+    /// The config revision after the request.
+    pub revision: u64,
+}
@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Role;
+
+/// Sent instead of the usual response when the session's role can't make
+/// the request.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ForbiddenResponse {
+    /// The type of the refused message, like `setSchedule`.
+    pub request: String,
+    pub role: Role,
+    pub required_role: Role,
+    pub error: String,
+}
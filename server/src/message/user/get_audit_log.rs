@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+use crate::audit::AuditEntry;
+
+pub const DEFAULT_AUDIT_LOG_LIMIT: usize = 100;
+pub const MAX_AUDIT_LOG_LIMIT: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAuditLogPayload {
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAuditLogResponse {
+    /// Newest first.
+    pub entries: Vec<AuditEntry>,
+    pub total: usize,
+    pub error: Option<String>,
+}
@@ -1,5 +1,8 @@
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 
+use crate::budget::DerivedDuration;
+use crate::restrictions::Restrictions;
 use crate::types::Schedules;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -9,7 +12,15 @@ pub struct GetConfigPayload {}
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct GetConfigResponse {
+    /// Pass this back with changes to the config.
+    pub revision: u64,
     pub schedules: Schedules,
     pub stagger_on: bool,
     pub stagger_zones: bool,
+    /// Durations worked out from zone water budgets, which replace the
+    /// scheduled minutes of those periods.
+    pub derived_durations: Vec<DerivedDuration>,
+    pub restrictions: Restrictions,
+    pub seasonal_adjustment_percent: u32,
+    pub rain_delay_until: Option<DateTime<Local>>,
 }
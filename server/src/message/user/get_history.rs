@@ -0,0 +1,28 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::history::HistoryEntry;
+use crate::types::ZoneId;
+
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+pub const MAX_HISTORY_LIMIT: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetHistoryPayload {
+    pub from: Option<DateTime<Local>>,
+    pub to: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub zones: Vec<ZoneId>,
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetHistoryResponse {
+    pub entries: Vec<HistoryEntry>,
+    pub total: usize,
+    pub error: Option<String>,
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+use crate::pairing::PendingController;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPendingControllersPayload {}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPendingControllersResponse {
+    pub controllers: Vec<PendingController>,
+}
@@ -0,0 +1,23 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::scheduler_runner::projection::{SkippedRun, ZoneRun};
+use crate::types::ZoneId;
+
+pub const MAX_UPCOMING_RUN_DAYS: u32 = 31;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetUpcomingRunsPayload {
+    pub from: DateTime<Local>,
+    pub days: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetUpcomingRunsResponse {
+    pub zones: BTreeMap<ZoneId, Vec<ZoneRun>>,
+    pub skipped: Vec<SkippedRun>,
+    pub error: Option<String>,
+}
@@ -0,0 +1,22 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::usage::UsageReport;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetUsageReportPayload {
+    pub from: DateTime<Local>,
+    pub to: DateTime<Local>,
+    /// Also return the runs as CSV, one row per run.
+    #[serde(default)]
+    pub include_csv: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetUsageReportResponse {
+    pub report: Option<UsageReport>,
+    pub csv: Option<String>,
+    pub error: Option<String>,
+}
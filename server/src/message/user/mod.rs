@@ -1,12 +1,62 @@
+pub mod approve_controller;
+pub mod conflict;
+pub mod create_schedule;
+pub mod delete_schedule;
+pub mod duplicate_schedule;
+pub mod forbidden;
+pub mod get_audit_log;
 pub mod get_config;
+pub mod get_history;
+pub mod get_pending_controllers;
+pub mod get_upcoming_runs;
+pub mod get_usage_report;
+pub mod reject_controller;
+pub mod reorder_schedules;
+pub mod report_soil_moisture;
+pub mod set_rain_delay;
+pub mod set_restriction_stage;
 pub mod set_schedule;
+pub mod set_seasonal_adjustment;
 pub mod status;
 pub mod toggle_zone;
+pub mod update_schedule;
 
+use crate::message::user::approve_controller::{
+    ApproveControllerPayload, ApproveControllerResponse,
+};
+use crate::message::user::conflict::ConflictResponse;
+use crate::message::user::create_schedule::{CreateSchedulePayload, CreateScheduleResponse};
+use crate::message::user::delete_schedule::{DeleteSchedulePayload, DeleteScheduleResponse};
+use crate::message::user::duplicate_schedule::{
+    DuplicateSchedulePayload, DuplicateScheduleResponse,
+};
+use crate::message::user::forbidden::ForbiddenResponse;
+use crate::message::user::get_audit_log::{GetAuditLogPayload, GetAuditLogResponse};
 use crate::message::user::get_config::{GetConfigPayload, GetConfigResponse};
+use crate::message::user::get_history::{GetHistoryPayload, GetHistoryResponse};
+use crate::message::user::get_pending_controllers::{
+    GetPendingControllersPayload, GetPendingControllersResponse,
+};
+use crate::message::user::get_upcoming_runs::{GetUpcomingRunsPayload, GetUpcomingRunsResponse};
+use crate::message::user::get_usage_report::{GetUsageReportPayload, GetUsageReportResponse};
+use crate::message::user::reject_controller::{RejectControllerPayload, RejectControllerResponse};
+use crate::message::user::reorder_schedules::{ReorderSchedulesPayload, ReorderSchedulesResponse};
+use crate::message::user::report_soil_moisture::{
+    ReportSoilMoisturePayload, ReportSoilMoistureResponse,
+};
+use crate::message::user::set_rain_delay::{SetRainDelayPayload, SetRainDelayResponse};
+use crate::message::user::set_restriction_stage::{
+    SetRestrictionStagePayload, SetRestrictionStageResponse,
+};
 use crate::message::user::set_schedule::{SetSchedulePayload, SetScheduleResponse};
+use crate::message::user::set_seasonal_adjustment::{
+    SetSeasonalAdjustmentPayload, SetSeasonalAdjustmentResponse,
+};
 use crate::message::user::status::{StatusPayload, StatusResponse};
 use crate::message::user::toggle_zone::{ToggleZonePayload, ToggleZoneResponse};
+use crate::message::user::update_schedule::{UpdateSchedulePayload, UpdateScheduleResponse};
+
+use crate::auth::Role;
 
 use serde::{Deserialize, Serialize};
 
@@ -26,14 +76,85 @@ pub enum UserMessage {
     KeepAlive(KeepAlivePayload),
     SetSchedule(SetSchedulePayload),
     GetConfig(GetConfigPayload),
+    GetUpcomingRuns(GetUpcomingRunsPayload),
+    GetHistory(GetHistoryPayload),
+    GetUsageReport(GetUsageReportPayload),
+    ReportSoilMoisture(ReportSoilMoisturePayload),
+    SetRestrictionStage(SetRestrictionStagePayload),
+    GetPendingControllers(GetPendingControllersPayload),
+    ApproveController(ApproveControllerPayload),
+    RejectController(RejectControllerPayload),
+    GetAuditLog(GetAuditLogPayload),
+    CreateSchedule(CreateSchedulePayload),
+    UpdateSchedule(UpdateSchedulePayload),
+    DeleteSchedule(DeleteSchedulePayload),
+    DuplicateSchedule(DuplicateSchedulePayload),
+    ReorderSchedules(ReorderSchedulesPayload),
+    SetRainDelay(SetRainDelayPayload),
+    SetSeasonalAdjustment(SetSeasonalAdjustmentPayload),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type", content = "payload", rename_all = "camelCase")]
+#[allow(clippy::enum_variant_names)]
 pub enum UserMessageResponse {
     ToggleZoneResponse(ToggleZoneResponse),
     StatusResponse(StatusResponse),
     KeepAliveResponse(KeepAliveResponse),
     SetScheduleResponse(SetScheduleResponse),
     GetConfigResponse(GetConfigResponse),
+    GetUpcomingRunsResponse(GetUpcomingRunsResponse),
+    GetHistoryResponse(GetHistoryResponse),
+    GetUsageReportResponse(GetUsageReportResponse),
+    ReportSoilMoistureResponse(ReportSoilMoistureResponse),
+    SetRestrictionStageResponse(SetRestrictionStageResponse),
+    GetPendingControllersResponse(GetPendingControllersResponse),
+    ApproveControllerResponse(ApproveControllerResponse),
+    RejectControllerResponse(RejectControllerResponse),
+    ForbiddenResponse(ForbiddenResponse),
+    GetAuditLogResponse(GetAuditLogResponse),
+    ConflictResponse(ConflictResponse),
+    CreateScheduleResponse(CreateScheduleResponse),
+    UpdateScheduleResponse(UpdateScheduleResponse),
+    DeleteScheduleResponse(DeleteScheduleResponse),
+    DuplicateScheduleResponse(DuplicateScheduleResponse),
+    ReorderSchedulesResponse(ReorderSchedulesResponse),
+    SetRainDelayResponse(SetRainDelayResponse),
+    SetSeasonalAdjustmentResponse(SetSeasonalAdjustmentResponse),
+}
+
+impl UserMessage {
+    /// The least a session's role has to be to send this message.
+    pub fn required_role(&self) -> Role {
+        match self {
+            UserMessage::Status(_)
+            | UserMessage::KeepAlive(_)
+            | UserMessage::GetConfig(_)
+            | UserMessage::GetUpcomingRuns(_)
+            | UserMessage::GetHistory(_)
+            | UserMessage::GetUsageReport(_) => Role::Viewer,
+            UserMessage::ToggleZone(_) | UserMessage::ReportSoilMoisture(_) => Role::Operator,
+            UserMessage::SetSchedule(_)
+            | UserMessage::CreateSchedule(_)
+            | UserMessage::UpdateSchedule(_)
+            | UserMessage::DeleteSchedule(_)
+            | UserMessage::DuplicateSchedule(_)
+            | UserMessage::ReorderSchedules(_)
+            | UserMessage::SetRestrictionStage(_)
+            | UserMessage::SetRainDelay(_)
+            | UserMessage::SetSeasonalAdjustment(_)
+            | UserMessage::GetPendingControllers(_)
+            | UserMessage::ApproveController(_)
+            | UserMessage::RejectController(_)
+            | UserMessage::GetAuditLog(_) => Role::Admin,
+        }
+    }
+
+    /// The message's `type`, like `setSchedule`.
+    pub fn name(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|value| value["type"].as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
 }
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::DeviceId;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectControllerPayload {
+    /// Refused until the server restarts, pending or not.
+    pub device_id: DeviceId,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectControllerResponse {
+    pub success: bool,
+    pub error: Option<String>,
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::validate::FieldError;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReorderSchedulesPayload {
+    /// Every schedule's id, in the new order.
+    pub ids: Vec<Uuid>,
+    /// The config revision the order was picked from.
+    pub revision: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReorderSchedulesResponse {
+    pub success: bool,
+    pub errors: Vec<FieldError>,
+    /// The config revision after the request.
+    pub revision: u64,
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::ZoneId;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportSoilMoisturePayload {
+    pub zone: ZoneId,
+    pub percent: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportSoilMoistureResponse {
+    pub success: bool,
+    pub error: Option<String>,
+}
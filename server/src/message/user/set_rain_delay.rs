@@ -0,0 +1,20 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SetRainDelayPayload {
+    /// Automatic runs starting before this are skipped. None lifts the delay.
+    pub until: Option<DateTime<Local>>,
+    /// The config revision the delay was picked from.
+    pub revision: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SetRainDelayResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    /// The config revision after the request.
+    pub revision: u64,
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::validate::FieldError;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SetRestrictionStagePayload {
+    /// One of the configured stages, or 0 to lift the stage rules.
+    pub stage: u8,
+    /// The config revision the stage was picked from.
+    pub revision: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SetRestrictionStageResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    /// Saved schedules that break the new stage. These don't fail the
+    /// switch, the runs are blocked or cut short instead.
+    pub violations: Vec<FieldError>,
+    /// The config revision after the request.
+    pub revision: u64,
+}
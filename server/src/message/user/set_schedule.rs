@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
 
+use crate::config::validate::FieldError;
+use crate::scheduler_runner::conflicts::ScheduleConflict;
 use crate::types::Schedules;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SetSchedulePayload {
+    /// The config revision the schedules were edited from.
+    pub revision: u64,
     pub schedules: Schedules,
 }
 
@@ -12,5 +16,10 @@ pub struct SetSchedulePayload {
 #[serde(rename_all = "camelCase")]
 pub struct SetScheduleResponse {
     pub success: bool,
-    pub error: Option<String>,
+    pub errors: Vec<FieldError>,
+    /// Saved programs whose run windows overlap. These don't fail the save,
+    /// `Config::overlap_policy` decides what happens when they meet.
+    pub conflicts: Vec<ScheduleConflict>,
+    /// The config revision after the request.
+    pub revision: u64,
 }
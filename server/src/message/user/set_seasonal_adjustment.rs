@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SetSeasonalAdjustmentPayload {
+    /// Between 1 and `MAX_SEASONAL_ADJUSTMENT_PERCENT`, 100 runs schedules
+    /// as written.
+    pub percent: u32,
+    /// The config revision the adjustment was picked from.
+    pub revision: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SetSeasonalAdjustmentResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    /// The config revision after the request.
+    pub revision: u64,
+}
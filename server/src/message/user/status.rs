@@ -1,11 +1,30 @@
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 
+use crate::controllers::ControllerState;
+use crate::freeze::FreezeGuard;
+use crate::scheduler_runner::rules::SkipReason;
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct StatusPayload {}
 
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LastSkip {
+    pub schedule: String,
+    pub timestamp: DateTime<Local>,
+    pub reason: SkipReason,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct StatusResponse {
+    /// Whether any controller is connected.
     pub is_controller_connected: bool,
+    /// Whether any controller's rain sensor is wet.
+    pub rain_sensor_active: bool,
+    pub controllers: Vec<ControllerState>,
+    pub freeze_guard: Option<FreezeGuard>,
+    pub last_skip: Option<LastSkip>,
 }
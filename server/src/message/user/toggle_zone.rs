@@ -1,9 +1,11 @@
 use serde::{Deserialize, Serialize};
 
+use crate::types::ZoneId;
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ToggleZonePayload {
-    pub zone: u8,
+    pub zone: ZoneId,
     pub activate: bool,
 }
 
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::validate::FieldError;
+use crate::scheduler_runner::conflicts::ScheduleConflict;
+use crate::types::Schedule;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateSchedulePayload {
+    /// Replaces the saved schedule with the same id.
+    pub schedule: Schedule,
+    /// The config revision the schedule was edited from.
+    pub revision: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateScheduleResponse {
+    pub success: bool,
+    pub errors: Vec<FieldError>,
+    pub conflicts: Vec<ScheduleConflict>,
+    /// The config revision after the request.
+    pub revision: u64,
+}
@@ -0,0 +1,264 @@
+use crate::config::Config;
+use crate::handshake::Proof;
+use crate::types::DeviceId;
+
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Display;
+use std::net::SocketAddr;
+
+/// How many controllers can wait on an admin at once. Past this, the one
+/// heard from least recently is dropped, so that a device cycling through
+/// made up MACs can't grow the list without bound.
+pub const MAX_PENDING_CONTROLLERS: usize = 16;
+
+/// How many pairing requests are sent to users a minute. Devices past this
+/// still show up in the pending list.
+pub const MAX_PAIRING_REQUESTS_PER_MINUTE: usize = 5;
+
+/// A controller that has identified itself but isn't approved yet.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingController {
+    pub device_id: DeviceId,
+    pub address: String,
+    pub first_seen: DateTime<Local>,
+    pub last_seen: DateTime<Local>,
+    /// What the controller last identified with. An admin approving it has
+    /// to enter a secret this proof was made with.
+    #[serde(skip)]
+    pub proof: Proof,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Approved,
+    Pending,
+    Rejected,
+    WrongSecret,
+}
+
+impl Display for Admission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Admission::Approved => write!(f, "approved"),
+            Admission::Pending => write!(f, "awaiting approval"),
+            Admission::Rejected => write!(f, "rejected"),
+            Admission::WrongSecret => write!(f, "wrong secret"),
+        }
+    }
+}
+
+/// Controllers waiting on an admin. Only approvals are saved, so pending and
+/// rejected devices are forgotten on restart.
+#[derive(Debug, Default)]
+pub struct Pairing {
+    pending: BTreeMap<DeviceId, PendingController>,
+    rejected: BTreeSet<DeviceId>,
+    /// When the pairing requests of the last minute were sent.
+    announced: VecDeque<DateTime<Local>>,
+}
+
+impl Pairing {
+    /// Whether a controller can connect. Unknown devices are added to the
+    /// pending list, along with the proof they presented.
+    pub fn admit(
+        &mut self,
+        config: &Config,
+        device_id: DeviceId,
+        proof: &Proof,
+        address: SocketAddr,
+    ) -> Admission {
+        if let Some(controller) = config.controller(device_id) {
+            return if proof.verifies(device_id, &controller.secret) {
+                Admission::Approved
+            } else {
+                Admission::WrongSecret
+            };
+        }
+        if self.rejected.contains(&device_id) {
+            return Admission::Rejected;
+        }
+
+        let now = Local::now();
+        if !self.pending.contains_key(&device_id)
+            && self.pending.len() >= MAX_PENDING_CONTROLLERS
+            && let Some(stalest) = self
+                .pending
+                .values()
+                .min_by_key(|pending| pending.last_seen)
+                .map(|pending| pending.device_id)
+        {
+            println!("Too many controllers awaiting approval, dropped {stalest}");
+            self.pending.remove(&stalest);
+        }
+        let pending = self
+            .pending
+            .entry(device_id)
+            .or_insert_with(|| PendingController {
+                device_id,
+                address: address.to_string(),
+                first_seen: now,
+                last_seen: now,
+                proof: proof.clone(),
+            });
+        pending.address = address.to_string();
+        pending.last_seen = now;
+        pending.proof = proof.clone();
+        Admission::Pending
+    }
+
+    /// The pairing request to send users for a controller seen for the first
+    /// time, unless too many have been sent in the last minute.
+    pub fn announce(
+        &mut self,
+        device_id: DeviceId,
+        now: DateTime<Local>,
+    ) -> Option<PendingController> {
+        let controller = self
+            .pending
+            .get(&device_id)
+            .filter(|controller| controller.first_seen == controller.last_seen)?;
+
+        while self
+            .announced
+            .front()
+            .is_some_and(|at| now - *at >= TimeDelta::minutes(1))
+        {
+            self.announced.pop_front();
+        }
+        if self.announced.len() >= MAX_PAIRING_REQUESTS_PER_MINUTE {
+            return None;
+        }
+        self.announced.push_back(now);
+        Some(controller.clone())
+    }
+
+    pub fn pending(&self) -> Vec<PendingController> {
+        self.pending.values().cloned().collect()
+    }
+
+    pub fn approve(&mut self, device_id: DeviceId) -> Option<PendingController> {
+        self.pending.remove(&device_id)
+    }
+
+    /// Puts back a controller whose approval couldn't be saved.
+    pub fn restore(&mut self, controller: PendingController) {
+        self.pending.insert(controller.device_id, controller);
+    }
+
+    pub fn reject(&mut self, device_id: DeviceId) {
+        self.rejected.insert(device_id);
+        self.pending.remove(&device_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::ControllerConfig;
+    use shared::identify_proof;
+
+    const DEVICE: DeviceId = DeviceId([0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+    const CHALLENGE: &str = "0123456789abcdef0123456789abcdef";
+
+    fn proof(secret: &str) -> Proof {
+        Proof {
+            challenge: CHALLENGE.to_string(),
+            nonce: 7,
+            proof: identify_proof(secret.as_bytes(), &DEVICE.to_string(), CHALLENGE, 7),
+        }
+    }
+
+    fn address() -> SocketAddr {
+        "192.0.2.1:5000".parse().unwrap()
+    }
+
+    fn device(n: u8) -> DeviceId {
+        DeviceId([0x02, 0, 0, 0, 0, n])
+    }
+
+    #[test]
+    fn admits_approved_controllers_by_proof() {
+        let mut config = Config::default();
+        config.controllers.push(ControllerConfig {
+            device_id: DEVICE,
+            name: None,
+            secret: "hunter2".to_string(),
+        });
+        let mut pairing = Pairing::default();
+
+        let admission = pairing.admit(&config, DEVICE, &proof("hunter2"), address());
+        assert_eq!(admission, Admission::Approved);
+        let admission = pairing.admit(&config, DEVICE, &proof("guessed"), address());
+        assert_eq!(admission, Admission::WrongSecret);
+    }
+
+    #[test]
+    fn keeps_the_proof_of_unknown_controllers() {
+        let config = Config::default();
+        let mut pairing = Pairing::default();
+
+        let admission = pairing.admit(&config, DEVICE, &proof("hunter2"), address());
+        assert_eq!(admission, Admission::Pending);
+
+        let pending = pairing.approve(DEVICE).unwrap();
+        assert!(pending.proof.verifies(DEVICE, "hunter2"));
+        assert!(!pending.proof.verifies(DEVICE, "guessed"));
+        assert!(!pending.proof.verifies(DEVICE, ""));
+    }
+
+    #[test]
+    fn drops_the_stalest_pending_controller() {
+        let config = Config::default();
+        let mut pairing = Pairing::default();
+
+        for n in 0..MAX_PENDING_CONTROLLERS as u8 {
+            pairing.admit(&config, device(n), &proof("hunter2"), address());
+        }
+        // heard from again, so no longer the stalest
+        pairing.admit(&config, device(0), &proof("hunter2"), address());
+        pairing.admit(&config, DEVICE, &proof("hunter2"), address());
+
+        let pending: Vec<DeviceId> = pairing
+            .pending()
+            .iter()
+            .map(|pending| pending.device_id)
+            .collect();
+        assert_eq!(pending.len(), MAX_PENDING_CONTROLLERS);
+        assert!(pending.contains(&device(0)));
+        assert!(!pending.contains(&device(1)));
+        assert!(pending.contains(&DEVICE));
+    }
+
+    #[test]
+    fn limits_pairing_requests() {
+        let config = Config::default();
+        let mut pairing = Pairing::default();
+        let now = Local::now();
+
+        for n in 0..=MAX_PAIRING_REQUESTS_PER_MINUTE as u8 {
+            pairing.admit(&config, device(n), &proof("hunter2"), address());
+        }
+        for n in 0..MAX_PAIRING_REQUESTS_PER_MINUTE as u8 {
+            assert!(pairing.announce(device(n), now).is_some());
+        }
+        let last = device(MAX_PAIRING_REQUESTS_PER_MINUTE as u8);
+        assert!(pairing.announce(last, now).is_none());
+        assert!(
+            pairing
+                .announce(last, now + TimeDelta::minutes(1))
+                .is_some()
+        );
+
+        // only the first time a controller is seen
+        pairing.admit(&config, DEVICE, &proof("hunter2"), address());
+        pairing.admit(&config, DEVICE, &proof("hunter2"), address());
+        assert!(
+            pairing
+                .announce(DEVICE, now + TimeDelta::minutes(2))
+                .is_none()
+        );
+    }
+}
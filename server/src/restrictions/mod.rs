@@ -0,0 +1,428 @@
+use crate::config::Config;
+use crate::config::validate::FieldError;
+use crate::history::{HistoryEvent, HistoryFilter};
+use crate::scheduler_runner::conflicts::program_windows;
+use crate::types::HistoryMutex;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeDelta, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::Display;
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// Local watering restrictions, like a municipal drought ordinance.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Restrictions {
+    /// The street number of the property, for parity rules.
+    #[serde(default)]
+    pub address_number: Option<u32>,
+    /// Rules that always apply.
+    #[serde(default)]
+    pub rules: Vec<RestrictionRule>,
+    /// Extra rules for each drought stage.
+    #[serde(default)]
+    pub stages: Vec<RestrictionStage>,
+    /// The stage in force, 0 for none.
+    #[serde(default)]
+    pub active_stage: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RestrictionStage {
+    pub stage: u8,
+    pub rules: Vec<RestrictionRule>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum RestrictionRule {
+    /// No watering from `start_minutes` until `end_minutes`, which can wrap
+    /// past midnight.
+    #[serde(rename_all = "camelCase")]
+    ForbiddenWindow {
+        start_minutes: u32,
+        end_minutes: u32,
+    },
+    /// Odd addresses water on odd days of the month, even ones on even days.
+    AddressParity,
+    /// Watering on at most `days` days a week.
+    MaxDaysPerWeek { days: u8 },
+}
+
+impl Display for RestrictionRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let time = |minutes: &u32| format!("{:02}:{:02}", minutes / 60, minutes % 60);
+        match self {
+            RestrictionRule::ForbiddenWindow {
+                start_minutes,
+                end_minutes,
+            } => write!(
+                f,
+                "no watering {}-{}",
+                time(start_minutes),
+                time(end_minutes)
+            ),
+            RestrictionRule::AddressParity => write!(f, "odd/even days by address"),
+            RestrictionRule::MaxDaysPerWeek { days } => write!(f, "at most {days} days a week"),
+        }
+    }
+}
+
+/// A run cut short so that it ends when a forbidden window starts.
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    pub secs: u64,
+    pub rule: RestrictionRule,
+}
+
+impl Restrictions {
+    pub fn has_stage(&self, stage: u8) -> bool {
+        stage == 0 || self.stages.iter().any(|s| s.stage == stage)
+    }
+
+    /// The base rules along with those of the active stage.
+    pub fn active_rules(&self) -> impl Iterator<Item = &RestrictionRule> {
+        self.rules.iter().chain(
+            self.stages
+                .iter()
+                .filter(|stage| stage.stage == self.active_stage)
+                .flat_map(|stage| stage.rules.iter()),
+        )
+    }
+}
+
+/// The forbidden window as minute ranges around a day, covering the wrap
+/// from the day before and into the day after.
+fn forbidden_ranges(start_minutes: u32, end_minutes: u32) -> Vec<(i64, i64)> {
+    let start = start_minutes as i64;
+    let mut end = end_minutes as i64;
+    if end <= start {
+        end += MINUTES_PER_DAY;
+    }
+
+    [-1, 0, 1]
+        .iter()
+        .map(|day| (start + day * MINUTES_PER_DAY, end + day * MINUTES_PER_DAY))
+        .collect()
+}
+
+fn is_forbidden_minute(start_minutes: u32, end_minutes: u32, minute: i64) -> bool {
+    forbidden_ranges(start_minutes, end_minutes)
+        .iter()
+        .any(|(start, end)| (*start..*end).contains(&minute))
+}
+
+/// Every way the active schedules of `config` break its active restrictions.
+/// Parity can't be broken by a weekly schedule, so it's only enforced when
+/// runs start.
+pub fn schedule_violations(config: &Config) -> Vec<FieldError> {
+    let restrictions = &config.restrictions;
+    let mut errors = vec![];
+    let mut watering_days = HashSet::new();
+
+    for (index, schedule) in config.schedules.iter().enumerate() {
+        if !schedule.is_active {
+            continue;
+        }
+        let field = |name: &str| format!("schedules[{index}].{name}");
+        let windows = program_windows(config, schedule);
+        let days_before = watering_days.len();
+        watering_days.extend(schedule.days.iter().copied());
+
+        for rule in restrictions.active_rules() {
+            match rule {
+                RestrictionRule::ForbiddenWindow {
+                    start_minutes,
+                    end_minutes,
+                } => {
+                    let Some(window) = windows.first() else {
+                        continue;
+                    };
+                    let crosses = forbidden_ranges(*start_minutes, *end_minutes).iter().any(
+                        |(start, end)| {
+                            (window.start_minutes as i64) < *end
+                                && *start < window.end_minutes as i64
+                        },
+                    );
+                    if crosses {
+                        errors.push(FieldError::new(
+                            field("startTimeMinutes"),
+                            format!(
+                                "Runs {:02}:{:02}-{:02}:{:02}, breaking {rule}",
+                                window.start_minutes / 60,
+                                window.start_minutes % 60,
+                                (window.end_minutes / 60) % 24,
+                                window.end_minutes % 60,
+                            ),
+                        ));
+                    }
+                }
+                RestrictionRule::MaxDaysPerWeek { days } => {
+                    // only on the schedule that takes the total over
+                    if watering_days.len() > *days as usize && days_before <= *days as usize {
+                        errors.push(FieldError::new(
+                            field("days"),
+                            format!(
+                                "Schedules water on {} days a week, breaking {rule}",
+                                watering_days.len()
+                            ),
+                        ));
+                    }
+                }
+                RestrictionRule::AddressParity => {}
+            }
+        }
+    }
+
+    errors
+}
+
+/// The rule stopping a run from starting at `at`, going by the clock and
+/// calendar alone.
+pub fn blocking_rule(config: &Config, at: DateTime<Local>) -> Option<RestrictionRule> {
+    let restrictions = &config.restrictions;
+    let minute = (at.hour() * 60 + at.minute()) as i64;
+
+    restrictions
+        .active_rules()
+        .find(|rule| match rule {
+            RestrictionRule::ForbiddenWindow {
+                start_minutes,
+                end_minutes,
+            } => is_forbidden_minute(*start_minutes, *end_minutes, minute),
+            RestrictionRule::AddressParity => restrictions
+                .address_number
+                .is_some_and(|number| number % 2 != at.day() % 2),
+            RestrictionRule::MaxDaysPerWeek { .. } => false,
+        })
+        .cloned()
+}
+
+/// The weekly limit a run on the day of `at` would break, going by the
+/// days this week that already had a run.
+pub async fn weekly_limit(
+    config: &Config,
+    history: &HistoryMutex,
+    at: DateTime<Local>,
+) -> Option<RestrictionRule> {
+    let limit = config
+        .restrictions
+        .active_rules()
+        .filter_map(|rule| match rule {
+            RestrictionRule::MaxDaysPerWeek { days } => Some((*days, rule)),
+            _ => None,
+        })
+        .min_by_key(|(days, _)| *days)?;
+
+    let today = at.date_naive();
+    let monday = today - TimeDelta::days(today.weekday().num_days_from_monday() as i64);
+    let from = monday
+        .and_hms_opt(0, 0, 0)
+        .and_then(|from| from.and_local_timezone(Local).earliest())?;
+    let filter = HistoryFilter {
+        from: Some(from),
+        ..Default::default()
+    };
+    let entries = match history.lock().await.entries(&filter) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Failed to read history, running without a weekly limit: {e}");
+            return None;
+        }
+    };
+
+    let watered: HashSet<NaiveDate> = entries
+        .iter()
+        .filter(|entry| matches!(entry.event, HistoryEvent::ScheduleStarted { .. }))
+        .map(|entry| entry.timestamp.date_naive())
+        .filter(|day| *day != today)
+        .collect();
+    (watered.len() >= limit.0 as usize).then(|| limit.1.clone())
+}
+
+/// When a run starting at `at` has to stop for the next forbidden window,
+/// if one starts within a day.
+pub fn deadline(config: &Config, at: DateTime<Local>) -> Option<Deadline> {
+    let minute = (at.hour() * 60 + at.minute()) as i64;
+    let secs_into_minute = at.second() as i64;
+
+    config
+        .restrictions
+        .active_rules()
+        .filter_map(|rule| {
+            let RestrictionRule::ForbiddenWindow {
+                start_minutes,
+                end_minutes,
+            } = rule
+            else {
+                return None;
+            };
+            let start = forbidden_ranges(*start_minutes, *end_minutes)
+                .iter()
+                .map(|(start, _)| *start)
+                .find(|start| *start > minute)?;
+            Some(Deadline {
+                secs: ((start - minute) * 60 - secs_into_minute).max(0) as u64,
+                rule: rule.clone(),
+            })
+        })
+        .min_by_key(|deadline| deadline.secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DeviceId, Schedule, Zone, ZoneId};
+
+    use chrono::TimeZone;
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+    // 22:00 to 06:00
+    const OVERNIGHT: RestrictionRule = RestrictionRule::ForbiddenWindow {
+        start_minutes: 22 * 60,
+        end_minutes: 6 * 60,
+    };
+
+    fn at(day: u32, hour: u32, minute: u32, second: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2025, 6, day, hour, minute, second)
+            .unwrap()
+    }
+
+    fn config(rules: Vec<RestrictionRule>) -> Config {
+        Config {
+            restrictions: Restrictions {
+                address_number: Some(12),
+                rules,
+                ..Restrictions::default()
+            },
+            ..Config::default()
+        }
+    }
+
+    fn schedule(days: &[&str], start_time_minutes: u32, is_active: bool) -> Schedule {
+        serde_json::from_value(serde_json::json!({
+            "name": "lawn",
+            "days": days,
+            "activePeriods": [{ "zone": ZONE_1, "durationMinutes": 20 }],
+            "startTimeMinutes": start_time_minutes,
+            "isActive": is_active,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn forbidden_ranges_cover_the_days_around() {
+        assert_eq!(
+            forbidden_ranges(600, 960),
+            [(-840, -480), (600, 960), (2040, 2400)]
+        );
+        // wrapping past midnight, into the day after
+        assert_eq!(
+            forbidden_ranges(1320, 360),
+            [(-120, 360), (1320, 1800), (2760, 3240)]
+        );
+
+        assert!(is_forbidden_minute(1320, 360, 23 * 60));
+        assert!(is_forbidden_minute(1320, 360, 5 * 60 + 59));
+        assert!(!is_forbidden_minute(1320, 360, 6 * 60));
+        assert!(!is_forbidden_minute(1320, 360, 21 * 60 + 59));
+    }
+
+    #[test]
+    fn blocks_runs_in_forbidden_windows() {
+        let config = config(vec![OVERNIGHT]);
+        assert_eq!(blocking_rule(&config, at(2, 23, 30, 0)), Some(OVERNIGHT));
+        assert_eq!(blocking_rule(&config, at(2, 5, 59, 59)), Some(OVERNIGHT));
+        assert_eq!(blocking_rule(&config, at(2, 6, 0, 0)), None);
+    }
+
+    #[test]
+    fn blocks_runs_on_the_other_parity() {
+        let mut config = config(vec![RestrictionRule::AddressParity]);
+        // 12 waters on even days
+        assert_eq!(blocking_rule(&config, at(2, 6, 0, 0)), None);
+        assert_eq!(
+            blocking_rule(&config, at(3, 6, 0, 0)),
+            Some(RestrictionRule::AddressParity)
+        );
+
+        config.restrictions.address_number = None;
+        assert_eq!(blocking_rule(&config, at(3, 6, 0, 0)), None);
+    }
+
+    #[test]
+    fn only_the_active_stage_applies() {
+        let mut config = config(vec![]);
+        config.restrictions.stages = vec![RestrictionStage {
+            stage: 2,
+            rules: vec![OVERNIGHT],
+        }];
+        assert_eq!(blocking_rule(&config, at(2, 23, 0, 0)), None);
+
+        config.restrictions.active_stage = 2;
+        assert_eq!(blocking_rule(&config, at(2, 23, 0, 0)), Some(OVERNIGHT));
+    }
+
+    #[test]
+    fn runs_stop_for_the_next_window() {
+        let evening = RestrictionRule::ForbiddenWindow {
+            start_minutes: 18 * 60,
+            end_minutes: 19 * 60,
+        };
+        let config = config(vec![OVERNIGHT, evening.clone()]);
+
+        let next = deadline(&config, at(2, 17, 0, 0)).unwrap();
+        assert_eq!((next.secs, next.rule), (3600, evening));
+        let next = deadline(&config, at(2, 21, 0, 0)).unwrap();
+        assert_eq!((next.secs, next.rule), (3600, OVERNIGHT));
+        // the window starts before the current minute is out
+        let next = deadline(&config, at(2, 21, 59, 30)).unwrap();
+        assert_eq!(next.secs, 30);
+        // inside a window, the next one is tomorrow's
+        let next = deadline(&config, at(2, 23, 0, 0)).unwrap();
+        assert_eq!(next.secs, 19 * 60 * 60);
+
+        assert!(deadline(&Config::default(), at(2, 21, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn flags_schedules_in_forbidden_windows() {
+        let mut config = config(vec![OVERNIGHT]);
+        config.schedules = vec![
+            schedule(&["monday"], 6 * 60, true),
+            // runs until 22:10
+            schedule(&["tuesday"], 21 * 60 + 50, true),
+            schedule(&["wednesday"], 23 * 60, false),
+        ];
+
+        let errors = schedule_violations(&config);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "schedules[1].startTimeMinutes");
+    }
+
+    #[test]
+    fn flags_the_schedule_going_over_the_weekly_limit() {
+        let mut config = config(vec![RestrictionRule::MaxDaysPerWeek { days: 4 }]);
+        config.schedules = vec![
+            schedule(&["monday", "tuesday", "wednesday"], 360, true),
+            schedule(&["sunday"], 360, false),
+            schedule(&["monday", "thursday"], 420, true),
+            schedule(&["friday"], 360, true),
+            schedule(&["saturday"], 360, true),
+        ];
+
+        let errors = schedule_violations(&config);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "schedules[3].days");
+
+        config.restrictions.rules = vec![RestrictionRule::MaxDaysPerWeek { days: 6 }];
+        assert!(schedule_violations(&config).is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::config::Config;
+use crate::scheduler_runner::rules;
+use crate::types::{Day, Schedule};
+
+use serde::{Deserialize, Serialize};
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const MINUTES_PER_WEEK: u32 = 7 * MINUTES_PER_DAY;
+const DAYS: [Day; 7] = [
+    Day::Monday,
+    Day::Tuesday,
+    Day::Wednesday,
+    Day::Thursday,
+    Day::Friday,
+    Day::Saturday,
+    Day::Sunday,
+];
+
+/// When a program runs on a given day. `end_minutes` goes past 1440 for
+/// programs that run over midnight.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgramWindow {
+    pub schedule: String,
+    pub day: Day,
+    pub start_minutes: u32,
+    pub end_minutes: u32,
+}
+
+impl ProgramWindow {
+    fn week_minutes(&self) -> (u32, u32) {
+        let day_start = self.day as u32 * MINUTES_PER_DAY;
+        (day_start + self.start_minutes, day_start + self.end_minutes)
+    }
+
+    fn overlaps(&self, other: &ProgramWindow) -> bool {
+        let (start, end) = self.week_minutes();
+        let (other_start, other_end) = other.week_minutes();
+
+        // windows can run past the end of sunday into monday
+        [(0, 0), (MINUTES_PER_WEEK, 0), (0, MINUTES_PER_WEEK)]
+            .iter()
+            .any(|(shift, other_shift)| {
+                start + shift < other_end + other_shift && other_start + other_shift < end + shift
+            })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleConflict {
+    pub first: ProgramWindow,
+    pub second: ProgramWindow,
+}
+
+/// The weekly windows of `schedule`, one per watering day.
+pub fn program_windows(config: &Config, schedule: &Schedule) -> Vec<ProgramWindow> {
+    let duration_secs = rules::program(config, schedule)
+        .last()
+        .map(|event| event.offset_secs)
+        .unwrap_or(0);
+    if duration_secs == 0 {
+        return vec![];
+    }
+    let duration_minutes = duration_secs.div_ceil(60) as u32;
+
+    DAYS.iter()
+        .filter(|day| schedule.days.contains(day))
+        .map(|day| ProgramWindow {
+            schedule: schedule.name.clone(),
+            day: *day,
+            start_minutes: schedule.start_time_minutes,
+            end_minutes: schedule.start_time_minutes + duration_minutes,
+        })
+        .collect()
+}
+
+/// Every pair of active programs whose windows overlap.
+pub fn find_conflicts(config: &Config) -> Vec<ScheduleConflict> {
+    let windows: Vec<Vec<ProgramWindow>> = config
+        .schedules
+        .iter()
+        .filter(|schedule| schedule.is_active)
+        .map(|schedule| program_windows(config, schedule))
+        .collect();
+
+    let mut conflicts = vec![];
+    for (index, first_windows) in windows.iter().enumerate() {
+        for second_windows in windows.iter().skip(index + 1) {
+            for first in first_windows.iter() {
+                for second in second_windows
+                    .iter()
+                    .filter(|second| first.overlaps(second))
+                {
+                    conflicts.push(ScheduleConflict {
+                        first: first.clone(),
+                        second: second.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DeviceId, Zone, ZoneId};
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+
+    fn window(day: Day, start_minutes: u32, end_minutes: u32) -> ProgramWindow {
+        ProgramWindow {
+            schedule: String::new(),
+            day,
+            start_minutes,
+            end_minutes,
+        }
+    }
+
+    fn schedule(name: &str, days: &[&str], start_time_minutes: u32, minutes: u32) -> Schedule {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "days": days,
+            "activePeriods": [{ "zone": ZONE_1, "durationMinutes": minutes }],
+            "startTimeMinutes": start_time_minutes,
+            "isActive": true,
+        }))
+        .unwrap()
+    }
+
+    fn conflicts(schedules: Vec<Schedule>) -> Vec<(String, Day, String)> {
+        let config = Config {
+            schedules,
+            ..Config::default()
+        };
+        find_conflicts(&config)
+            .into_iter()
+            .map(|conflict| {
+                (
+                    conflict.first.schedule,
+                    conflict.first.day,
+                    conflict.second.schedule,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn overlapping_windows_on_the_same_day() {
+        let first = window(Day::Wednesday, 360, 390);
+        assert!(first.overlaps(&window(Day::Wednesday, 375, 400)));
+        assert!(window(Day::Wednesday, 375, 400).overlaps(&first));
+        // back to back is fine
+        assert!(!first.overlaps(&window(Day::Wednesday, 390, 400)));
+        assert!(!first.overlaps(&window(Day::Thursday, 360, 390)));
+    }
+
+    #[test]
+    fn windows_overlap_across_midnight() {
+        // 23:30 to 00:30
+        let late = window(Day::Monday, 1410, 1470);
+        assert!(late.overlaps(&window(Day::Tuesday, 0, 20)));
+        assert!(window(Day::Tuesday, 0, 20).overlaps(&late));
+        assert!(!late.overlaps(&window(Day::Tuesday, 30, 60)));
+
+        // and from sunday into monday
+        let sunday = window(Day::Sunday, 1410, 1470);
+        assert!(sunday.overlaps(&window(Day::Monday, 10, 20)));
+        assert!(window(Day::Monday, 10, 20).overlaps(&sunday));
+        assert!(!sunday.overlaps(&window(Day::Monday, 30, 60)));
+    }
+
+    #[test]
+    fn finds_conflicts_on_shared_days() {
+        let mut inactive = schedule("c", &["wednesday"], 360, 30);
+        inactive.is_active = false;
+        let conflicts = conflicts(vec![
+            schedule("a", &["monday", "wednesday"], 360, 30),
+            schedule("b", &["wednesday", "friday"], 375, 10),
+            inactive,
+        ]);
+        assert_eq!(
+            conflicts,
+            [("a".to_string(), Day::Wednesday, "b".to_string())]
+        );
+    }
+
+    #[test]
+    fn finds_conflicts_across_midnight() {
+        let conflicts = conflicts(vec![
+            schedule("late", &["sunday"], 1430, 20),
+            schedule("early", &["monday"], 0, 10),
+        ]);
+        assert_eq!(
+            conflicts,
+            [("late".to_string(), Day::Sunday, "early".to_string())]
+        );
+    }
+}
@@ -1,35 +1,362 @@
+pub mod conflicts;
+pub mod program_lock;
+pub mod projection;
+pub mod rules;
 pub mod runner;
 pub mod spawner;
+pub mod timeline;
 
 use crate::config::Config;
-use crate::scheduler_runner::spawner as schedule_spawner;
-use crate::types::ClientMap;
+use crate::scheduler_runner::program_lock::ProgramLock;
+use crate::scheduler_runner::spawner::{self as schedule_spawner, SharedConfig, Task};
+use crate::types::{ClientMap, HistoryMutex, Schedule, SensorsMutex, ZoneStateMutex};
 
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::thread;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
 
 pub struct ScheduleRunner {
-    handles: Vec<(Arc<AtomicBool>, thread::JoinHandle<()>)>,
+    /// By schedule id.
+    tasks: HashMap<Uuid, Task>,
+    /// What the tasks start their runs with.
+    config: SharedConfig,
+    program_lock: Arc<ProgramLock>,
 }
 
 impl ScheduleRunner {
-    pub fn new(config: Config, clients: &ClientMap) -> Self {
-        let handles =
-            schedule_spawner::spawn(config.schedules.clone(), config.stagger_zones, clients);
+    pub fn new(
+        config: Config,
+        clients: &ClientMap,
+        zone_state: &ZoneStateMutex,
+        history: &HistoryMutex,
+        sensors: &SensorsMutex,
+    ) -> Self {
+        let program_lock = Arc::new(ProgramLock::default());
+        let config = Arc::new(RwLock::new(config));
+        let tasks = schedule_spawner::spawn(
+            &config,
+            clients,
+            zone_state,
+            history,
+            sensors,
+            &program_lock,
+        );
 
-        Self { handles }
+        Self {
+            tasks,
+            config,
+            program_lock,
+        }
+    }
+
+    /// Hands the tasks a config whose schedules are unchanged. Runs that
+    /// start from now on use it, while the ones in progress carry on as they
+    /// were planned.
+    pub fn refresh(&mut self, config: Config) {
+        *self.config.write().unwrap() = config;
     }
 
-    pub fn update(&mut self, config: Config, clients: &ClientMap) -> Self {
-        for (running, handle) in self.handles.drain(..) {
-            running.store(false, Ordering::Relaxed);
-            handle.join().unwrap();
+    /// Restarts the tasks of schedules that changed between `before` and
+    /// `after`, cancelling their runs in progress, and refreshes the rest.
+    pub fn update_schedules(
+        &mut self,
+        before: &Config,
+        after: &Config,
+        clients: &ClientMap,
+        zone_state: &ZoneStateMutex,
+        history: &HistoryMutex,
+        sensors: &SensorsMutex,
+    ) {
+        self.refresh(after.clone());
+        for id in changed_schedules(before, after) {
+            if let Some(task) = self.tasks.remove(&id) {
+                stop(task);
+            }
+            if let Some(schedule) = after.schedules.iter().find(|schedule| schedule.id == id) {
+                let task = schedule_spawner::spawn_one(
+                    &self.config,
+                    schedule,
+                    clients,
+                    zone_state,
+                    history,
+                    sensors,
+                    &self.program_lock,
+                );
+                self.tasks.insert(id, task);
+            }
         }
+    }
+}
+
+/// Tells the task to stop without waiting for it. A run in progress closes
+/// its zones within a moment, and joining here would block the async caller.
+fn stop((running, _handle): Task) {
+    running.store(false, Ordering::Relaxed);
+}
+
+/// Schedules added, removed or edited. Besides its own edits a schedule
+/// changes when its water budget does, as zone budgets are split across
+/// every schedule watering the zone.
+fn changed_schedules(before: &Config, after: &Config) -> BTreeSet<Uuid> {
+    // periods are equal when their zones are, so compare them as JSON to
+    // catch changed durations too
+    fn find(config: &Config, id: Uuid) -> Option<(&Schedule, Value)> {
+        let schedule = config.schedule(id)?;
+        let periods = serde_json::to_value(rules::periods(config, schedule)).unwrap();
+        Some((schedule, periods))
+    }
+
+    before
+        .schedules
+        .iter()
+        .chain(after.schedules.iter())
+        .map(|schedule| schedule.id)
+        .filter(|&id| find(before, id) != find(after, id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ZoneConfig;
+    use crate::history::History;
+    use crate::sensors::Sensors;
+    use crate::types::{Client, ClientType, DeviceId, Zone, ZoneId};
+    use crate::zone_state::ZoneState;
+
+    use chrono::{Datelike, Local, Timelike};
+    use shared::Signer;
+    use std::time::{Duration, Instant};
+    use tokio::sync::Mutex;
+    use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+    use tokio::time::timeout;
+    use tokio_tungstenite::tungstenite::Message;
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+
+    fn schedule(name: &str, duration_minutes: u32) -> Schedule {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "days": ["monday"],
+            "activePeriods": [{ "zone": ZONE_1, "durationMinutes": duration_minutes }],
+            "startTimeMinutes": 360,
+            "isActive": true,
+        }))
+        .unwrap()
+    }
+
+    fn config(schedules: Vec<Schedule>) -> Config {
+        Config {
+            schedules,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn only_edited_schedules_change() {
+        let before = config(vec![schedule("a", 10), schedule("b", 10)]);
+        let (a, b) = (before.schedules[0].id, before.schedules[1].id);
+
+        let mut reordered = before.clone();
+        reordered.schedules.reverse();
+        assert!(changed_schedules(&before, &reordered).is_empty());
+
+        let mut longer = before.clone();
+        longer.schedules[1] = Schedule {
+            id: b,
+            ..schedule("b", 20)
+        };
+        assert_eq!(changed_schedules(&before, &longer), BTreeSet::from([b]));
+
+        let mut added = before.clone();
+        added.schedules.remove(0);
+        let c = schedule("c", 10);
+        let c_id = c.id;
+        added.schedules.push(c);
+        assert_eq!(
+            changed_schedules(&before, &added),
+            BTreeSet::from([a, c_id])
+        );
+    }
+
+    #[test]
+    fn schedules_sharing_a_budget_change_together() {
+        let mut before = config(vec![schedule("a", 10)]);
+        before.zones.insert(
+            ZONE_1,
+            ZoneConfig {
+                enabled: true,
+                flow_rate: None,
+                weekly_depth_target: Some(20.0),
+                precipitation_rate: Some(10.0),
+                crop_coefficient: None,
+            },
+        );
+        let a = before.schedules[0].id;
+
+        // the zone's target is now split across two runs a week
+        let mut after = before.clone();
+        let b = schedule("b", 10);
+        let b_id = b.id;
+        after.schedules.push(b);
+        assert_eq!(
+            changed_schedules(&before, &after),
+            BTreeSet::from([a, b_id])
+        );
+    }
+
+    async fn next_toggle(receiver: &mut UnboundedReceiver<Message>) -> String {
+        let message = timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .expect("no toggle within 5s")
+            .unwrap();
+        message.into_text().unwrap()
+    }
+
+    struct Running {
+        schedule: Schedule,
+        receiver: UnboundedReceiver<Message>,
+        clients: ClientMap,
+        zone_state: ZoneStateMutex,
+        history: HistoryMutex,
+        history_path: std::path::PathBuf,
+        sensors: SensorsMutex,
+        runner: ScheduleRunner,
+    }
+
+    /// A runner with one schedule due this minute, once it has opened its
+    /// zone.
+    async fn start_running(test: &str) -> Running {
+        // runs only start in their start minute, so don't begin at the end of one
+        if Local::now().second() >= 55 {
+            tokio::time::sleep(Duration::from_secs(6)).await;
+        }
+        let now = Local::now();
+        let mut schedule = schedule("a", 10);
+        schedule.days = [now.weekday().into()].into();
+        schedule.start_time_minutes = now.hour() * 60 + now.minute();
+        let before = config(vec![schedule.clone()]);
+
+        let (sender, mut receiver) = unbounded_channel();
+        let clients = ClientMap::default();
+        clients.lock().await.insert(
+            1,
+            Client {
+                client_type: ClientType::Controller,
+                device_id: Some(DeviceId::UNKNOWN),
+                signer: Some(Signer::new(b"secret", 0)),
+                sender,
+            },
+        );
+        let zone_state: ZoneStateMutex = Arc::new(Mutex::new(ZoneState::new(&before)));
+        let history_path = std::env::temp_dir().join(format!(
+            "schedule-runner-{test}-{}.jsonl",
+            std::process::id()
+        ));
+        let history: HistoryMutex = Arc::new(Mutex::new(
+            History::open(history_path.to_str().unwrap()).unwrap(),
+        ));
+        let sensors: SensorsMutex = Arc::new(Mutex::new(Sensors::default()));
+
+        let runner = ScheduleRunner::new(before, &clients, &zone_state, &history, &sensors);
+        assert!(
+            next_toggle(&mut receiver)
+                .await
+                .contains(r#""activate":true"#)
+        );
+
+        Running {
+            schedule,
+            receiver,
+            clients,
+            zone_state,
+            history,
+            history_path,
+            sensors,
+            runner,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn editing_a_running_schedule_cancels_its_run() {
+        let Running {
+            schedule: running,
+            mut receiver,
+            clients,
+            zone_state,
+            history,
+            history_path,
+            sensors,
+            mut runner,
+        } = start_running("edit").await;
+        let before = config(vec![running.clone()]);
+
+        // moved out of the current minute, so the new task doesn't start it again
+        let after = config(vec![Schedule {
+            start_time_minutes: (running.start_time_minutes + 60) % (24 * 60),
+            ..running
+        }]);
+        let started = Instant::now();
+        runner.update_schedules(&before, &after, &clients, &zone_state, &history, &sensors);
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        assert!(
+            next_toggle(&mut receiver)
+                .await
+                .contains(r#""activate":false"#)
+        );
+        assert!(zone_state.lock().await.open_zones().is_empty());
+
+        runner.update_schedules(
+            &after,
+            &config(vec![]),
+            &clients,
+            &zone_state,
+            &history,
+            &sensors,
+        );
+        let _ = std::fs::remove_file(history_path);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn refreshing_the_config_leaves_a_run_going() {
+        let Running {
+            schedule,
+            mut receiver,
+            clients,
+            zone_state,
+            history,
+            history_path,
+            sensors,
+            mut runner,
+        } = start_running("refresh").await;
+
+        let refreshed = Config {
+            seasonal_adjustment_percent: 50,
+            ..config(vec![schedule.clone()])
+        };
+        runner.refresh(refreshed.clone());
 
-        let handles =
-            schedule_spawner::spawn(config.schedules.clone(), config.stagger_zones, clients);
+        assert!(
+            timeout(Duration::from_secs(2), receiver.recv())
+                .await
+                .is_err()
+        );
+        assert_eq!(zone_state.lock().await.open_zones().len(), 1);
 
-        Self { handles }
+        runner.update_schedules(
+            &refreshed,
+            &config(vec![]),
+            &clients,
+            &zone_state,
+            &history,
+            &sensors,
+        );
+        let _ = std::fs::remove_file(history_path);
     }
 }
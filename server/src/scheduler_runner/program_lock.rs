@@ -0,0 +1,59 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+const WAIT_POLL_MILLIS: u64 = 1000;
+
+/// Lets one program run at a time for the queue and skip overlap policies.
+#[derive(Default)]
+pub struct ProgramLock {
+    running: Mutex<Option<String>>,
+    released: Condvar,
+}
+
+pub struct ProgramGuard<'a> {
+    lock: &'a ProgramLock,
+}
+
+impl ProgramLock {
+    /// Takes the lock, or returns the name of the program holding it.
+    pub fn try_acquire(&self, schedule: &str) -> Result<ProgramGuard<'_>, String> {
+        let mut running = self.running.lock().unwrap();
+        match running.as_ref() {
+            Some(other) => Err(other.clone()),
+            None => {
+                *running = Some(schedule.to_string());
+                Ok(ProgramGuard { lock: self })
+            }
+        }
+    }
+
+    /// Waits for the lock, giving up once `keep_waiting` is cleared.
+    pub fn acquire(&self, schedule: &str, keep_waiting: &AtomicBool) -> Option<ProgramGuard<'_>> {
+        let mut running = self.running.lock().unwrap();
+        while running.is_some() {
+            if !keep_waiting.load(Ordering::Relaxed) {
+                return None;
+            }
+            running = self
+                .released
+                .wait_timeout(running, Duration::from_millis(WAIT_POLL_MILLIS))
+                .unwrap()
+                .0;
+        }
+
+        *running = Some(schedule.to_string());
+        Some(ProgramGuard { lock: self })
+    }
+
+    pub fn running(&self) -> Option<String> {
+        self.running.lock().unwrap().clone()
+    }
+}
+
+impl Drop for ProgramGuard<'_> {
+    fn drop(&mut self) {
+        *self.lock.running.lock().unwrap() = None;
+        self.lock.released.notify_all();
+    }
+}
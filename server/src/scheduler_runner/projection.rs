@@ -0,0 +1,288 @@
+use crate::config::Config;
+use crate::scheduler_runner::rules::{self, SkipReason};
+use crate::types::{OverlapPolicy, Schedule, ZoneId};
+
+use chrono::{DateTime, Local, NaiveTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ZoneRun {
+    pub schedule: String,
+    pub open: DateTime<Local>,
+    pub close: DateTime<Local>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedRun {
+    pub schedule: String,
+    pub start: DateTime<Local>,
+    pub reason: SkipReason,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UpcomingRuns {
+    pub zones: BTreeMap<ZoneId, Vec<ZoneRun>>,
+    pub skipped: Vec<SkippedRun>,
+}
+
+/// Every run that would start in the `days` after `from`, laid out per zone
+/// the same way the spawner would run it.
+pub fn upcoming_runs(config: &Config, from: DateTime<Local>, days: u32) -> UpcomingRuns {
+    let until = from + TimeDelta::days(days as i64);
+    let mut upcoming = UpcomingRuns::default();
+
+    let mut due: Vec<(DateTime<Local>, &Schedule)> = vec![];
+    let mut date = from.date_naive();
+    while date <= until.date_naive() {
+        for schedule in config.schedules.iter() {
+            let Some(start_time) =
+                NaiveTime::from_num_seconds_from_midnight_opt(schedule.start_time_minutes * 60, 0)
+            else {
+                continue;
+            };
+            let Some(start) = date
+                .and_time(start_time)
+                .and_local_timezone(Local)
+                .earliest()
+            else {
+                continue;
+            };
+
+            if start >= from && start < until && rules::is_due(schedule, start) {
+                due.push((start, schedule));
+            }
+        }
+
+        date = date.succ_opt().unwrap();
+    }
+    due.sort_by_key(|(start, _)| *start);
+
+    // the program holding the lock under the queue and skip policies
+    let mut running: Option<(&str, DateTime<Local>)> = None;
+    for (due_at, schedule) in due {
+        let mut start = due_at;
+        if let Some((other, finish)) = running
+            && finish > due_at
+        {
+            match config.overlap_policy {
+                OverlapPolicy::Parallel => {}
+                OverlapPolicy::Queue => start = finish,
+                OverlapPolicy::Skip => {
+                    upcoming.skipped.push(SkippedRun {
+                        schedule: schedule.name.clone(),
+                        start,
+                        reason: SkipReason::Overlap {
+                            schedule: other.to_string(),
+                        },
+                    });
+                    continue;
+                }
+            }
+        }
+
+        if let Some(reason) = rules::skip_reason(config, start) {
+            upcoming.skipped.push(SkippedRun {
+                schedule: schedule.name.clone(),
+                start,
+                reason,
+            });
+            continue;
+        }
+
+        let mut opened_at = BTreeMap::new();
+        let mut finish = start;
+        for event in rules::program(config, schedule) {
+            let at = start + TimeDelta::seconds(event.offset_secs as i64);
+            finish = at;
+            if event.activate {
+                opened_at.insert(event.zone, at);
+            } else if let Some(open) = opened_at.remove(&event.zone) {
+                upcoming.zones.entry(event.zone).or_default().push(ZoneRun {
+                    schedule: schedule.name.clone(),
+                    open,
+                    close: at,
+                });
+            }
+        }
+
+        if config.overlap_policy != OverlapPolicy::Parallel {
+            running = Some((&schedule.name, finish));
+        }
+    }
+
+    for runs in upcoming.zones.values_mut() {
+        runs.sort_by_key(|run| run.open);
+    }
+
+    upcoming
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DeviceId, Zone};
+    use chrono::TimeZone;
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+    const ZONE_2: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone2,
+    };
+
+    fn schedule(name: &str, days: &[&str], start_time_minutes: u32) -> Schedule {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "days": days,
+            "activePeriods": [
+                { "zone": ZONE_1, "durationMinutes": 10 },
+                { "zone": ZONE_2, "durationMinutes": 5 },
+            ],
+            "startTimeMinutes": start_time_minutes,
+            "isActive": true,
+        }))
+        .unwrap()
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        // 2025-06-01 is a sunday
+        Local
+            .with_ymd_and_hms(2025, 6, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn runs(
+        upcoming: &UpcomingRuns,
+        zone: ZoneId,
+    ) -> Vec<(String, DateTime<Local>, DateTime<Local>)> {
+        upcoming.zones.get(&zone).map_or(vec![], |runs| {
+            runs.iter()
+                .map(|run| (run.schedule.clone(), run.open, run.close))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn lays_out_each_zone_over_the_window() {
+        let config = Config {
+            schedules: vec![schedule("a", &["monday", "wednesday"], 360)],
+            ..Config::default()
+        };
+
+        let upcoming = upcoming_runs(&config, at(1, 0, 0), 7);
+        assert_eq!(
+            runs(&upcoming, ZONE_1),
+            vec![
+                ("a".to_string(), at(2, 6, 0), at(2, 6, 10)),
+                ("a".to_string(), at(4, 6, 0), at(4, 6, 10)),
+            ]
+        );
+        assert_eq!(
+            runs(&upcoming, ZONE_2),
+            vec![
+                ("a".to_string(), at(2, 6, 10), at(2, 6, 15)),
+                ("a".to_string(), at(4, 6, 10), at(4, 6, 15)),
+            ]
+        );
+        assert!(upcoming.skipped.is_empty());
+
+        // a run at the very start counts, one at the very end doesn't
+        let upcoming = upcoming_runs(&config, at(2, 6, 0), 2);
+        assert_eq!(
+            runs(&upcoming, ZONE_1),
+            vec![("a".to_string(), at(2, 6, 0), at(2, 6, 10))]
+        );
+        let upcoming = upcoming_runs(&config, at(2, 6, 1), 1);
+        assert!(runs(&upcoming, ZONE_1).is_empty());
+    }
+
+    #[test]
+    fn applies_the_seasonal_adjustment() {
+        let config = Config {
+            schedules: vec![schedule("a", &["monday"], 360)],
+            seasonal_adjustment_percent: 200,
+            ..Config::default()
+        };
+
+        let upcoming = upcoming_runs(&config, at(1, 0, 0), 7);
+        assert_eq!(
+            runs(&upcoming, ZONE_2),
+            vec![("a".to_string(), at(2, 6, 20), at(2, 6, 30))]
+        );
+    }
+
+    #[test]
+    fn skips_runs_during_a_rain_delay() {
+        let until = at(3, 12, 0);
+        let config = Config {
+            schedules: vec![schedule("a", &["monday", "wednesday"], 360)],
+            rain_delay_until: Some(until),
+            ..Config::default()
+        };
+
+        let upcoming = upcoming_runs(&config, at(1, 0, 0), 7);
+        assert_eq!(
+            runs(&upcoming, ZONE_1),
+            vec![("a".to_string(), at(4, 6, 0), at(4, 6, 10))]
+        );
+        assert_eq!(upcoming.skipped.len(), 1);
+        assert_eq!(upcoming.skipped[0].start, at(2, 6, 0));
+        assert_eq!(upcoming.skipped[0].reason, SkipReason::RainDelay { until });
+    }
+
+    #[test]
+    fn overlapping_runs_follow_the_policy() {
+        let schedules = vec![
+            schedule("a", &["monday"], 360),
+            schedule("b", &["monday"], 365),
+        ];
+
+        let config = Config {
+            schedules: schedules.clone(),
+            overlap_policy: OverlapPolicy::Queue,
+            ..Config::default()
+        };
+        let upcoming = upcoming_runs(&config, at(1, 0, 0), 7);
+        assert_eq!(
+            runs(&upcoming, ZONE_1),
+            vec![
+                ("a".to_string(), at(2, 6, 0), at(2, 6, 10)),
+                ("b".to_string(), at(2, 6, 15), at(2, 6, 25)),
+            ]
+        );
+
+        let config = Config {
+            schedules: schedules.clone(),
+            overlap_policy: OverlapPolicy::Skip,
+            ..Config::default()
+        };
+        let upcoming = upcoming_runs(&config, at(1, 0, 0), 7);
+        assert_eq!(runs(&upcoming, ZONE_1).len(), 1);
+        assert_eq!(
+            upcoming.skipped[0].reason,
+            SkipReason::Overlap {
+                schedule: "a".to_string()
+            }
+        );
+
+        let config = Config {
+            schedules,
+            overlap_policy: OverlapPolicy::Parallel,
+            ..Config::default()
+        };
+        let upcoming = upcoming_runs(&config, at(1, 0, 0), 7);
+        assert_eq!(
+            runs(&upcoming, ZONE_1),
+            vec![
+                ("a".to_string(), at(2, 6, 0), at(2, 6, 10)),
+                ("b".to_string(), at(2, 6, 5), at(2, 6, 15)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::budget;
+use crate::config::Config;
+use crate::restrictions::{self, RestrictionRule};
+use crate::scheduler_runner::timeline::{self, ZoneEvent};
+use crate::sensors::Sensors;
+use crate::types::{ActivePeriod, Day, Schedule, SkipRule};
+
+use chrono::{DateTime, Datelike, Local, Timelike};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SkipReason {
+    RainDelay {
+        until: DateTime<Local>,
+    },
+    Overlap {
+        schedule: String,
+    },
+    RainSensor,
+    Freeze {
+        temp: f64,
+    },
+    /// `value` is the weather reading that broke `rule`.
+    Weather {
+        rule: SkipRule,
+        value: f64,
+    },
+    Restricted {
+        rule: RestrictionRule,
+    },
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::RainDelay { until } => write!(f, "Rain delay until {until}"),
+            SkipReason::Overlap { schedule } => write!(f, "Schedule {schedule} was still running"),
+            SkipReason::RainSensor => write!(f, "Rain sensor is active"),
+            SkipReason::Freeze { temp } => write!(f, "Freeze guard is on at {temp:.1}°C"),
+            SkipReason::Weather { rule, value } => write!(f, "Weather: {rule} ({value:.1})"),
+            SkipReason::Restricted { rule } => write!(f, "Restricted: {rule}"),
+        }
+    }
+}
+
+/// Whether `schedule` is due to start in the minute containing `at`.
+pub fn is_due(schedule: &Schedule, at: DateTime<Local>) -> bool {
+    let day: Day = at.weekday().into();
+    schedule.is_active
+        && schedule.days.contains(&day)
+        && at.hour() * 60 + at.minute() == schedule.start_time_minutes
+}
+
+/// Why a run starting at `at` should not water, if anything stops it.
+pub fn skip_reason(config: &Config, at: DateTime<Local>) -> Option<SkipReason> {
+    match config.rain_delay_until {
+        Some(until) if at < until => Some(SkipReason::RainDelay { until }),
+        _ => restrictions::blocking_rule(config, at).map(|rule| SkipReason::Restricted { rule }),
+    }
+}
+
+/// Why `schedule` should not water given the controller's sensors.
+pub fn sensor_skip_reason(schedule: &Schedule, sensors: &Sensors) -> Option<SkipReason> {
+    (schedule.obey_rain_sensor && sensors.rain_sensor_active()).then_some(SkipReason::RainSensor)
+}
+
+/// The periods of one run of `schedule`, with water budgets and the seasonal
+/// adjustment applied, in zone order.
+pub fn periods(config: &Config, schedule: &Schedule) -> Vec<ActivePeriod> {
+    let mut periods: Vec<ActivePeriod> = schedule
+        .active_periods
+        .iter()
+        .map(|period| {
+            let duration_minutes = budget::derived_minutes(config, schedule, period.zone)
+                .unwrap_or(period.duration_minutes);
+            ActivePeriod {
+                duration_minutes: config.adjusted_minutes(duration_minutes),
+                ..*period
+            }
+        })
+        .collect();
+    periods.sort_by_key(|period| period.zone);
+    periods
+}
+
+/// The open/close events for one run of `schedule`.
+pub fn program(config: &Config, schedule: &Schedule) -> Vec<ZoneEvent> {
+    timeline::plan(&periods(config, schedule), config.stagger_secs(schedule))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ZoneConfig;
+    use crate::types::{DeviceId, Zone, ZoneId};
+    use chrono::{TimeDelta, TimeZone};
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+    const ZONE_2: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone2,
+    };
+
+    fn schedule(periods: &[(ZoneId, u32)]) -> Schedule {
+        let periods: Vec<_> = periods
+            .iter()
+            .map(|(zone, minutes)| serde_json::json!({ "zone": zone, "durationMinutes": minutes }))
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "name": "a",
+            "days": ["monday"],
+            "activePeriods": periods,
+            "startTimeMinutes": 360,
+            "isActive": true,
+        }))
+        .unwrap()
+    }
+
+    fn minutes(config: &Config, schedule: &Schedule) -> Vec<(ZoneId, u32)> {
+        periods(config, schedule)
+            .into_iter()
+            .map(|period| (period.zone, period.duration_minutes))
+            .collect()
+    }
+
+    #[test]
+    fn due_in_the_start_minute_of_its_days() {
+        // 2025-06-02 is a monday
+        let mut schedule = schedule(&[(ZONE_1, 10)]);
+        assert!(is_due(
+            &schedule,
+            Local.with_ymd_and_hms(2025, 6, 2, 6, 0, 30).unwrap()
+        ));
+        assert!(!is_due(
+            &schedule,
+            Local.with_ymd_and_hms(2025, 6, 2, 6, 1, 0).unwrap()
+        ));
+        assert!(!is_due(
+            &schedule,
+            Local.with_ymd_and_hms(2025, 6, 3, 6, 0, 0).unwrap()
+        ));
+
+        schedule.is_active = false;
+        assert!(!is_due(
+            &schedule,
+            Local.with_ymd_and_hms(2025, 6, 2, 6, 0, 0).unwrap()
+        ));
+    }
+
+    #[test]
+    fn rain_delay_skips_runs_until_it_ends() {
+        let until = Local.with_ymd_and_hms(2025, 6, 3, 12, 0, 0).unwrap();
+        let config = Config {
+            rain_delay_until: Some(until),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            skip_reason(
+                &config,
+                Local.with_ymd_and_hms(2025, 6, 2, 6, 0, 0).unwrap()
+            ),
+            Some(SkipReason::RainDelay { until })
+        );
+        assert_eq!(skip_reason(&config, until), None);
+        assert_eq!(
+            skip_reason(&Config::default(), until - TimeDelta::days(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn periods_are_scaled_and_sorted_by_zone() {
+        let schedule = schedule(&[(ZONE_2, 5), (ZONE_1, 20)]);
+        let config = Config {
+            seasonal_adjustment_percent: 150,
+            ..Config::default()
+        };
+        assert_eq!(minutes(&config, &schedule), vec![(ZONE_1, 30), (ZONE_2, 8)]);
+
+        assert_eq!(
+            minutes(&Config::default(), &schedule),
+            vec![(ZONE_1, 20), (ZONE_2, 5)]
+        );
+    }
+
+    #[test]
+    fn budgets_replace_the_minutes_before_the_adjustment() {
+        let schedule = schedule(&[(ZONE_1, 20), (ZONE_2, 5)]);
+        let mut config = Config {
+            schedules: vec![schedule.clone()],
+            seasonal_adjustment_percent: 50,
+            ..Config::default()
+        };
+        // 1 inch a week at half an inch an hour is two hours
+        config.zones.insert(
+            ZONE_1,
+            ZoneConfig {
+                enabled: true,
+                flow_rate: None,
+                weekly_depth_target: Some(1.0),
+                precipitation_rate: Some(0.5),
+                crop_coefficient: None,
+            },
+        );
+
+        assert_eq!(minutes(&config, &schedule), vec![(ZONE_1, 60), (ZONE_2, 3)]);
+    }
+}
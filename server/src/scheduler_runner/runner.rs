@@ -1,77 +1,508 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use shared::{ServerMessage, ToggleZonePayload};
+use chrono::Local;
 
 use crate::error::ServerError;
-use crate::message::send_to_controller;
-use crate::types::{ClientMap, Schedule};
+use crate::history::{self, HistoryEvent, ZoneSource};
+use crate::restrictions::Deadline;
+use crate::scheduler_runner::timeline::{self, ZoneEvent};
+use crate::sensors::moisture;
+use crate::types::{
+    ActivePeriod, ClientMap, HistoryMutex, MoistureRule, SensorsMutex, ZoneId, ZoneStateMutex,
+};
+use crate::zone_state::toggle_zone;
 
-const ZONE_STAGGER_DURATION_SECS: u64 = 10;
+const ZONE_QUEUE_POLL_MILLIS: u64 = 1000;
+/// How often a sleeping run checks whether it's been cancelled.
+const CANCEL_POLL_MILLIS: u64 = 250;
 
+/// Runs `periods`, stopping early at `deadline` if one is given, or as soon
+/// as `running` is cleared.
+#[allow(clippy::too_many_arguments)]
 pub(super) async fn run(
-    schedule: Schedule,
-    stagger_zones: bool,
+    schedule: &str,
+    periods: &[ActivePeriod],
+    stagger_secs: u32,
+    deadline: Option<Deadline>,
+    running: &AtomicBool,
     clients: &ClientMap,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    sensors: &SensorsMutex,
 ) -> Result<(), ServerError> {
-    let active_periods_len = schedule.active_periods.len();
-    for (index, period) in schedule.active_periods.iter().enumerate() {
-        let duration_secs: u64 = (period.duration_minutes as u64) * 60;
-        let zone = period.zone;
-
-        // If first zone, turn it on
-        if index == 0 {
-            send_to_controller(
-                clients,
-                &serde_json::to_string(&ServerMessage::ToggleZone(ToggleZonePayload {
-                    activate: true,
-                    zone: zone.into(),
-                }))
-                .unwrap(),
+    let mut open_zones: HashSet<ZoneId> = HashSet::new();
+    let result = run_events(
+        schedule,
+        periods,
+        stagger_secs,
+        deadline,
+        running,
+        &mut open_zones,
+        clients,
+        zone_state,
+        history,
+        sensors,
+    )
+    .await;
+
+    // nothing else would close the valves this run opened
+    if result.is_err() && !open_zones.is_empty() {
+        let _ = close_all(schedule, &mut open_zones, clients, zone_state, history).await;
+    }
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_events(
+    schedule: &str,
+    periods: &[ActivePeriod],
+    stagger_secs: u32,
+    deadline: Option<Deadline>,
+    running: &AtomicBool,
+    open_zones: &mut HashSet<ZoneId>,
+    clients: &ClientMap,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    sensors: &SensorsMutex,
+) -> Result<(), ServerError> {
+    let source = || ZoneSource::Schedule {
+        schedule: schedule.to_string(),
+    };
+    let moisture_rules: BTreeMap<ZoneId, MoistureRule> = periods
+        .iter()
+        .filter_map(|period| Some((period.zone, period.moisture?)))
+        .collect();
+
+    let mut events = timeline::plan(periods, stagger_secs);
+    let mut elapsed_secs: u64 = 0;
+
+    let mut index = 0;
+    while index < events.len() {
+        let event = events[index];
+        index += 1;
+
+        if let Some(deadline) = &deadline
+            && event.offset_secs > deadline.secs
+        {
+            if !sleep_while_running(deadline.secs.saturating_sub(elapsed_secs), running) {
+                return cancel(schedule, open_zones, clients, zone_state, history).await;
+            }
+            println!("Stopped schedule {schedule} early: {}", deadline.rule);
+            close_all(schedule, open_zones, clients, zone_state, history).await?;
+            history::record(
+                history,
+                HistoryEvent::ScheduleTruncated {
+                    schedule: schedule.to_string(),
+                    rule: deadline.rule.clone(),
+                },
             )
             .await;
+            return Ok(());
         }
 
-        // sleep while it runs
-        if stagger_zones {
-            thread::sleep(Duration::from_secs(
-                duration_secs - ZONE_STAGGER_DURATION_SECS,
-            ));
-        } else {
-            thread::sleep(Duration::from_secs(duration_secs));
+        // sleep until the next event is due
+        if !sleep_while_running(event.offset_secs - elapsed_secs, running) {
+            return cancel(schedule, open_zones, clients, zone_state, history).await;
         }
+        elapsed_secs = event.offset_secs;
 
-        // if not last zone, turn on next zone
-        if index < active_periods_len - 1 {
-            let next_zone = schedule.active_periods.iter().nth(index + 1).unwrap().zone;
-
-            send_to_controller(
-                clients,
-                &serde_json::to_string(&ServerMessage::ToggleZone(ToggleZonePayload {
-                    activate: true,
-                    zone: next_zone.into(),
-                }))
-                .unwrap(),
+        if !event.activate {
+            // stays in open_zones until it's closed, so a failure retries it
+            if open_zones.contains(&event.zone) {
+                toggle_zone(clients, zone_state, history, event.zone, false, source()).await?;
+                open_zones.remove(&event.zone);
+            }
+            continue;
+        }
+
+        if let Some(rule) = moisture_rules.get(&event.zone)
+            && !adjust_step(
+                schedule,
+                rule,
+                event,
+                &mut events[index..],
+                history,
+                sensors,
             )
-            .await;
+            .await
+        {
+            continue;
+        }
+
+        match toggle_zone(clients, zone_state, history, event.zone, true, source()).await {
+            Ok(()) => {}
+            // the limits don't allow an overlap, so hand over sequentially
+            Err(e) if e.is_zone_conflict() => {
+                close_all(schedule, open_zones, clients, zone_state, history).await?;
+                let opened = open_when_available(
+                    clients,
+                    zone_state,
+                    history,
+                    event.zone,
+                    source(),
+                    running,
+                )
+                .await?;
+                if !opened {
+                    return cancel(schedule, open_zones, clients, zone_state, history).await;
+                }
+            }
+            Err(e) => return Err(e),
+        }
+        open_zones.insert(event.zone);
+    }
+
+    Ok(())
+}
+
+/// Sleeps for `secs`, waking early if `running` is cleared. Returns whether
+/// the run goes on.
+fn sleep_while_running(secs: u64, running: &AtomicBool) -> bool {
+    let until = Instant::now() + Duration::from_secs(secs);
+    loop {
+        if !running.load(Ordering::Relaxed) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= until {
+            return true;
         }
+        thread::sleep((until - now).min(Duration::from_millis(CANCEL_POLL_MILLIS)));
+    }
+}
+
+/// Closes the zones a cancelled run left open.
+async fn cancel(
+    schedule: &str,
+    open_zones: &mut HashSet<ZoneId>,
+    clients: &ClientMap,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+) -> Result<(), ServerError> {
+    println!("Cancelled schedule {schedule}");
+    close_all(schedule, open_zones, clients, zone_state, history).await
+}
 
-        // if staggering, let them run together for a bit
-        if stagger_zones {
-            thread::sleep(Duration::from_secs(ZONE_STAGGER_DURATION_SECS));
+/// Closes every zone in `open_zones`, going on past any that fail so one
+/// offline controller can't leave the others watering. Returns the first
+/// failure.
+async fn close_all(
+    schedule: &str,
+    open_zones: &mut HashSet<ZoneId>,
+    clients: &ClientMap,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+) -> Result<(), ServerError> {
+    let mut result = Ok(());
+    for zone in open_zones.drain() {
+        let source = ZoneSource::Schedule {
+            schedule: schedule.to_string(),
+        };
+        if let Err(e) = toggle_zone(clients, zone_state, history, zone, false, source).await {
+            println!("Schedule {schedule} failed to close {zone}: {e}");
+            if result.is_ok() {
+                result = Err(e);
+            }
         }
+    }
+    result
+}
+
+/// Shortens the step `open` starts to suit its zone's soil moisture, pulling
+/// the later steps forward by the time saved. Returns whether the step still
+/// runs.
+async fn adjust_step(
+    schedule: &str,
+    rule: &MoistureRule,
+    open: ZoneEvent,
+    remaining: &mut [ZoneEvent],
+    history: &HistoryMutex,
+    sensors: &SensorsMutex,
+) -> bool {
+    let Some(close) = remaining
+        .iter()
+        .find(|event| event.zone == open.zone && !event.activate)
+    else {
+        return true;
+    };
+    let scheduled_secs = close.offset_secs - open.offset_secs;
+
+    let reading = sensors.lock().await.soil_moisture.get(&open.zone).cloned();
+    let adjusted_secs = moisture::step_secs(rule, reading.as_ref(), scheduled_secs, Local::now());
+    let Some(reading) = reading.filter(|_| adjusted_secs < scheduled_secs) else {
+        return true;
+    };
+
+    println!(
+        "Cut {} of schedule {schedule} from {scheduled_secs}s to {adjusted_secs}s at {:.0}% moisture",
+        open.zone, reading.percent,
+    );
+    history::record(
+        history,
+        HistoryEvent::MoistureAdjusted {
+            schedule: schedule.to_string(),
+            zone: open.zone,
+            percent: reading.percent,
+            scheduled_secs,
+            adjusted_secs,
+        },
+    )
+    .await;
+
+    // zones still open from earlier steps keep their close times
+    let later_zones: HashSet<ZoneId> = remaining
+        .iter()
+        .filter(|event| event.activate)
+        .map(|event| event.zone)
+        .chain([open.zone])
+        .collect();
+    let saved_secs = scheduled_secs - adjusted_secs;
+    for event in remaining
+        .iter_mut()
+        .filter(|event| later_zones.contains(&event.zone))
+    {
+        event.offset_secs = event
+            .offset_secs
+            .saturating_sub(saved_secs)
+            .max(open.offset_secs);
+    }
+    remaining.sort_by_key(|event| (event.offset_secs, event.activate));
+
+    adjusted_secs > 0
+}
 
-        // turn off current zone
-        send_to_controller(
-            clients,
-            &serde_json::to_string(&ServerMessage::ToggleZone(ToggleZonePayload {
-                activate: false,
-                zone: zone.into(),
-            }))
-            .unwrap(),
+/// Opens a zone, queueing until the zone limits allow it. Returns whether
+/// it was opened, which it isn't if `running` is cleared while queued.
+async fn open_when_available(
+    clients: &ClientMap,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    zone: ZoneId,
+    source: ZoneSource,
+    running: &AtomicBool,
+) -> Result<bool, ServerError> {
+    let mut is_queued = false;
+    loop {
+        match toggle_zone(clients, zone_state, history, zone, true, source.clone()).await {
+            Err(e) if e.is_zone_conflict() => {
+                if !is_queued {
+                    println!("Queued {zone}: {e}");
+                    is_queued = true;
+                }
+                if !running.load(Ordering::Relaxed) {
+                    return Ok(false);
+                }
+                thread::sleep(Duration::from_millis(ZONE_QUEUE_POLL_MILLIS));
+            }
+            result => return result.map(|()| true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::history::History;
+    use crate::sensors::Sensors;
+    use crate::sensors::moisture::MoistureReading;
+    use crate::types::{Client, ClientType, DeviceId, Zone};
+    use crate::zone_state::ZoneState;
+    use shared::Signer;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+    const ZONE_2: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone2,
+    };
+    const ZONE_3: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone3,
+    };
+
+    fn event(offset_secs: u64, zone: ZoneId, activate: bool) -> ZoneEvent {
+        ZoneEvent {
+            offset_secs,
+            zone,
+            activate,
+        }
+    }
+
+    /// Three ten minute zones staggered by ten seconds, with the second
+    /// zone just opened.
+    fn second_step() -> (ZoneEvent, Vec<ZoneEvent>) {
+        (
+            event(590, ZONE_2, true),
+            vec![
+                event(600, ZONE_1, false),
+                event(1180, ZONE_3, true),
+                event(1190, ZONE_2, false),
+                event(1780, ZONE_3, false),
+            ],
+        )
+    }
+
+    async fn adjust_second_step(
+        name: &str,
+        rule: MoistureRule,
+        reading: Option<MoistureReading>,
+    ) -> (bool, Vec<ZoneEvent>) {
+        let history_path =
+            std::env::temp_dir().join(format!("runner-test-{name}-{}.jsonl", std::process::id()));
+        let history: HistoryMutex = Arc::new(Mutex::new(
+            History::open(history_path.to_str().unwrap()).unwrap(),
+        ));
+        let sensors: SensorsMutex = Arc::new(Mutex::new(Sensors::default()));
+        if let Some(reading) = reading {
+            sensors.lock().await.soil_moisture.insert(ZONE_2, reading);
+        }
+
+        let (open, mut remaining) = second_step();
+        let runs = adjust_step("a", &rule, open, &mut remaining, &history, &sensors).await;
+
+        let _ = std::fs::remove_file(history_path);
+        (runs, remaining)
+    }
+
+    #[tokio::test]
+    async fn a_shortened_step_pulls_later_zones_forward() {
+        let reading = MoistureReading {
+            percent: 30.0,
+            at: Local::now(),
+        };
+        let (runs, remaining) = adjust_second_step(
+            "shortened",
+            MoistureRule::ShortenToward { percent: 40.0 },
+            Some(reading),
         )
         .await;
+
+        // 150s instead of 600s, and the first zone still closes on time
+        assert!(runs);
+        assert_eq!(
+            remaining,
+            [
+                event(600, ZONE_1, false),
+                event(730, ZONE_3, true),
+                event(740, ZONE_2, false),
+                event(1330, ZONE_3, false),
+            ]
+        );
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn a_skipped_step_pulls_later_zones_to_its_start() {
+        let reading = MoistureReading {
+            percent: 50.0,
+            at: Local::now(),
+        };
+        let (runs, remaining) = adjust_second_step(
+            "skipped",
+            MoistureRule::SkipAbove { percent: 40.0 },
+            Some(reading),
+        )
+        .await;
+
+        assert!(!runs);
+        assert_eq!(
+            remaining,
+            [
+                event(590, ZONE_2, false),
+                event(590, ZONE_3, true),
+                event(600, ZONE_1, false),
+                event(1180, ZONE_3, false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_stale_reading_leaves_the_step_alone() {
+        let reading = MoistureReading {
+            percent: 50.0,
+            at: Local::now() - chrono::TimeDelta::hours(7),
+        };
+        let (runs, remaining) = adjust_second_step(
+            "stale",
+            MoistureRule::SkipAbove { percent: 40.0 },
+            Some(reading),
+        )
+        .await;
+
+        assert!(runs);
+        assert_eq!(remaining, second_step().1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_failed_open_closes_the_zones_already_open() {
+        // on a controller that never connected
+        let offline = ZoneId::new("AA:BB:CC:DD:EE:02".parse().unwrap(), Zone::Zone2);
+        let periods = [
+            ActivePeriod {
+                zone: ZONE_1,
+                duration_minutes: 1,
+                moisture: None,
+            },
+            ActivePeriod {
+                zone: offline,
+                duration_minutes: 1,
+                moisture: None,
+            },
+        ];
+
+        let (sender, mut receiver) = unbounded_channel();
+        let clients = ClientMap::default();
+        clients.lock().await.insert(
+            1,
+            Client {
+                client_type: ClientType::Controller,
+                device_id: Some(DeviceId::UNKNOWN),
+                signer: Some(Signer::new(b"secret", 0)),
+                sender,
+            },
+        );
+        let zone_state: ZoneStateMutex = Arc::new(Mutex::new(ZoneState::new(&Config::default())));
+        let history_path =
+            std::env::temp_dir().join(format!("runner-test-{}.jsonl", std::process::id()));
+        let history: HistoryMutex = Arc::new(Mutex::new(
+            History::open(history_path.to_str().unwrap()).unwrap(),
+        ));
+        let sensors: SensorsMutex = Arc::new(Mutex::new(Sensors::default()));
+
+        // the second zone opens a second into the first
+        let result = run(
+            "a",
+            &periods,
+            60,
+            None,
+            &AtomicBool::new(true),
+            &clients,
+            &zone_state,
+            &history,
+            &sensors,
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(ServerError::ControllerNotConnected(device_id)) if device_id == offline.controller
+        ));
+        let toggles: Vec<String> = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|message| message.into_text().unwrap())
+            .collect();
+        assert_eq!(toggles.len(), 2);
+        assert!(toggles[0].contains(r#""activate":true"#));
+        assert!(toggles[1].contains(r#""activate":false"#));
+        assert!(zone_state.lock().await.open_zones().is_empty());
+
+        let _ = std::fs::remove_file(history_path);
+    }
 }
@@ -1,51 +1,228 @@
+use crate::config::Config;
+use crate::history::{self, HistoryEvent};
+use crate::restrictions;
+use crate::scheduler_runner::program_lock::ProgramLock;
+use crate::scheduler_runner::rules::{self, SkipReason};
 use crate::scheduler_runner::runner as schedule_runner;
-use crate::types::{ClientMap, Day, Schedule};
+use crate::types::{
+    ClientMap, HistoryMutex, OverlapPolicy, Schedule, SensorsMutex, ZoneStateMutex,
+};
+use crate::weather;
 
-use chrono::{Datelike, Local, Timelike};
-use std::sync::Arc;
+use chrono::{Local, NaiveDate};
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
 use std::thread;
 use std::time::Duration;
+use tokio::runtime::Handle;
+use uuid::Uuid;
 
 const THREAD_POLL_MILLIS: u64 = 1000;
 
+/// A schedule's thread, and the flag that stops it.
+pub(super) type Task = (Arc<AtomicBool>, thread::JoinHandle<()>);
+/// The config every task reads as its runs start.
+pub(super) type SharedConfig = Arc<RwLock<Config>>;
+
 pub(super) fn spawn(
-    schedules: Vec<Schedule>,
-    stagger_zones: bool,
+    config: &SharedConfig,
     clients: &ClientMap,
-) -> Vec<(Arc<AtomicBool>, thread::JoinHandle<()>)> {
-    schedules
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    sensors: &SensorsMutex,
+    program_lock: &Arc<ProgramLock>,
+) -> HashMap<Uuid, Task> {
+    config
+        .read()
+        .unwrap()
+        .schedules
         .iter()
         .map(|schedule| {
-            let running = Arc::new(AtomicBool::new(true));
-            let thread_running = running.clone();
+            let task = spawn_one(
+                config,
+                schedule,
+                clients,
+                zone_state,
+                history,
+                sensors,
+                program_lock,
+            );
+            (schedule.id, task)
+        })
+        .collect()
+}
 
-            let schedule = schedule.clone();
-            let start_time = schedule.start_time_minutes;
-            let days = schedule.days.clone();
+pub(super) fn spawn_one(
+    config: &SharedConfig,
+    schedule: &Schedule,
+    clients: &ClientMap,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    sensors: &SensorsMutex,
+    program_lock: &Arc<ProgramLock>,
+) -> Task {
+    let running = Arc::new(AtomicBool::new(true));
+    let thread_running = running.clone();
 
-            let clients = clients.clone();
+    let config = config.clone();
+    let schedule = schedule.clone();
 
-            let handle = thread::spawn(move || {
-                loop {
-                    if !thread_running.load(Ordering::Relaxed) {
-                        break;
-                    }
+    let clients = clients.clone();
+    let zone_state = zone_state.clone();
+    let history = history.clone();
+    let sensors = sensors.clone();
+    let program_lock = program_lock.clone();
+    let runtime = Handle::current();
 
-                    let now = Local::now();
-                    let current_day: Day = now.weekday().into();
+    let handle = thread::spawn(move || {
+        // a run can finish within its start minute, so only start once a day
+        let mut last_started: Option<NaiveDate> = None;
 
-                    let current_time_minutes = now.hour() * 60 + now.minute();
+        loop {
+            if !thread_running.load(Ordering::Relaxed) {
+                break;
+            }
 
-                    if days.contains(&current_day) && current_time_minutes == start_time {
-                        let _ = schedule_runner::run(schedule.clone(), stagger_zones, &clients);
-                    }
+            let now = Local::now();
 
-                    thread::sleep(Duration::from_millis(THREAD_POLL_MILLIS));
-                }
-            });
+            if rules::is_due(&schedule, now) && last_started != Some(now.date_naive()) {
+                last_started = Some(now.date_naive());
+                let config = config.read().unwrap().clone();
+                start_run(
+                    &config,
+                    &schedule,
+                    &clients,
+                    &zone_state,
+                    &history,
+                    &sensors,
+                    &program_lock,
+                    &thread_running,
+                    &runtime,
+                );
+            }
 
-            (running, handle)
-        })
-        .collect()
+            thread::sleep(Duration::from_millis(THREAD_POLL_MILLIS));
+        }
+    });
+
+    (running, handle)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_run(
+    config: &Config,
+    schedule: &Schedule,
+    clients: &ClientMap,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    sensors: &SensorsMutex,
+    program_lock: &ProgramLock,
+    running: &AtomicBool,
+    runtime: &Handle,
+) {
+    let skip = |reason: SkipReason| {
+        println!("Skipped schedule {}: {reason}", schedule.name);
+        runtime.block_on(history::record(
+            history,
+            HistoryEvent::ScheduleSkipped {
+                schedule: schedule.name.clone(),
+                reason,
+            },
+        ));
+    };
+
+    let _guard = match config.overlap_policy {
+        OverlapPolicy::Parallel => None,
+        OverlapPolicy::Skip => match program_lock.try_acquire(&schedule.name) {
+            Ok(guard) => Some(guard),
+            Err(other) => {
+                skip(SkipReason::Overlap { schedule: other });
+                return;
+            }
+        },
+        OverlapPolicy::Queue => {
+            if let Some(other) = program_lock.running() {
+                println!("Queued schedule {} behind {other}", schedule.name);
+            }
+            match program_lock.acquire(&schedule.name, running) {
+                Some(guard) => Some(guard),
+                None => return,
+            }
+        }
+    };
+
+    let now = Local::now();
+    let sensor_skip = rules::sensor_skip_reason(schedule, &runtime.block_on(sensors.lock()));
+    let freeze_skip = runtime
+        .block_on(zone_state.lock())
+        .freeze_guard()
+        .map(|guard| SkipReason::Freeze { temp: guard.temp });
+    if let Some(reason) = rules::skip_reason(config, now)
+        .or(freeze_skip)
+        .or(sensor_skip)
+        .or_else(|| weather::skip_reason(config, schedule, now))
+    {
+        skip(reason);
+        return;
+    }
+    if let Some(rule) = runtime.block_on(restrictions::weekly_limit(config, history, now)) {
+        skip(SkipReason::Restricted { rule });
+        return;
+    }
+
+    runtime.block_on(async {
+        history::record(
+            history,
+            HistoryEvent::ScheduleStarted {
+                schedule: schedule.name.clone(),
+            },
+        )
+        .await;
+
+        let mut periods = rules::periods(config, schedule);
+        for adjustment in weather::et_adjust(config, history, &mut periods).await {
+            println!(
+                "Adjusted {} of schedule {} from {} to {} minutes, {:.1}mm short",
+                adjustment.zone,
+                schedule.name,
+                adjustment.scheduled_minutes,
+                adjustment.adjusted_minutes,
+                adjustment.balance.deficit,
+            );
+            history::record(
+                history,
+                HistoryEvent::EtAdjusted {
+                    schedule: schedule.name.clone(),
+                    adjustment,
+                },
+            )
+            .await;
+        }
+
+        let result = schedule_runner::run(
+            &schedule.name,
+            &periods,
+            config.stagger_secs(schedule),
+            restrictions::deadline(config, now),
+            running,
+            clients,
+            zone_state,
+            history,
+            sensors,
+        )
+        .await;
+        if let Err(e) = &result {
+            println!("Schedule {} failed: {e}", schedule.name);
+        }
+
+        history::record(
+            history,
+            HistoryEvent::ScheduleFinished {
+                schedule: schedule.name.clone(),
+                error: result.err().map(|e| e.to_string()),
+            },
+        )
+        .await;
+    });
 }
@@ -0,0 +1,192 @@
+use crate::types::{ActivePeriod, ZoneId};
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct ZoneEvent {
+    pub offset_secs: u64,
+    pub zone: ZoneId,
+    pub activate: bool,
+}
+
+/// Lays a program out as open/close events relative to its start.
+///
+/// Every zone runs for exactly its configured duration. Consecutive zones
+/// overlap by `stagger_secs`, clamped so that an overlap never outlasts
+/// either zone, a zone always opens after the one before it and no more
+/// than two zones are ever open together. Zero minute periods are left out.
+pub fn plan<'a>(
+    periods: impl IntoIterator<Item = &'a ActivePeriod>,
+    stagger_secs: u32,
+) -> Vec<ZoneEvent> {
+    let periods: Vec<&ActivePeriod> = periods
+        .into_iter()
+        .filter(|period| period.duration_minutes > 0)
+        .collect();
+    let mut events = Vec::with_capacity(periods.len() * 2);
+
+    let mut start_secs: u64 = 0;
+    let mut previous_overlap_secs: u64 = 0;
+    for (index, period) in periods.iter().enumerate() {
+        let duration_secs = (period.duration_minutes as u64) * 60;
+
+        events.push(ZoneEvent {
+            offset_secs: start_secs,
+            zone: period.zone,
+            activate: true,
+        });
+        events.push(ZoneEvent {
+            offset_secs: start_secs + duration_secs,
+            zone: period.zone,
+            activate: false,
+        });
+
+        let overlap_secs = match periods.get(index + 1) {
+            Some(next) => (stagger_secs as u64)
+                .min(duration_secs - 1)
+                .min(duration_secs - previous_overlap_secs)
+                .min((next.duration_minutes as u64) * 60),
+            None => 0,
+        };
+
+        start_secs += duration_secs - overlap_secs;
+        previous_overlap_secs = overlap_secs;
+    }
+
+    // closes sort ahead of opens at the same instant so back to back zones
+    // never count against the zone limits together
+    events.sort_by_key(|event| (event.offset_secs, event.activate));
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DeviceId, Zone};
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+    const ZONE_2: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone2,
+    };
+    const ZONE_3: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone3,
+    };
+
+    fn period(zone: ZoneId, duration_minutes: u32) -> ActivePeriod {
+        ActivePeriod {
+            zone,
+            duration_minutes,
+            moisture: None,
+        }
+    }
+
+    fn open(offset_secs: u64, zone: ZoneId) -> ZoneEvent {
+        ZoneEvent {
+            offset_secs,
+            zone,
+            activate: true,
+        }
+    }
+
+    fn close(offset_secs: u64, zone: ZoneId) -> ZoneEvent {
+        ZoneEvent {
+            offset_secs,
+            zone,
+            activate: false,
+        }
+    }
+
+    #[test]
+    fn runs_zones_back_to_back_without_stagger() {
+        let periods = [period(ZONE_1, 5), period(ZONE_2, 10)];
+
+        assert_eq!(
+            plan(&periods, 0),
+            vec![
+                open(0, ZONE_1),
+                close(300, ZONE_1),
+                open(300, ZONE_2),
+                close(900, ZONE_2),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlaps_consecutive_zones_by_stagger() {
+        let periods = [period(ZONE_1, 5), period(ZONE_2, 10)];
+
+        assert_eq!(
+            plan(&periods, 10),
+            vec![
+                open(0, ZONE_1),
+                open(290, ZONE_2),
+                close(300, ZONE_1),
+                close(890, ZONE_2),
+            ]
+        );
+    }
+
+    #[test]
+    fn clamps_stagger_longer_than_a_short_period() {
+        let periods = [period(ZONE_1, 1), period(ZONE_2, 1), period(ZONE_3, 1)];
+
+        // a 90 second overlap would outlast each one minute zone
+        let events = plan(&periods, 90);
+
+        assert_eq!(
+            events,
+            vec![
+                open(0, ZONE_1),
+                open(1, ZONE_2),
+                close(60, ZONE_1),
+                open(60, ZONE_3),
+                close(61, ZONE_2),
+                close(120, ZONE_3),
+            ]
+        );
+    }
+
+    #[test]
+    fn one_minute_zones_never_open_together() {
+        let periods = [period(ZONE_1, 1), period(ZONE_2, 1), period(ZONE_3, 1)];
+
+        for stagger_secs in [30, 59, 60, 61, 120] {
+            let opens: Vec<u64> = plan(&periods, stagger_secs)
+                .into_iter()
+                .filter(|event| event.activate)
+                .map(|event| event.offset_secs)
+                .collect();
+
+            assert_eq!(opens.len(), 3);
+            assert!(
+                opens.windows(2).all(|pair| pair[0] < pair[1]),
+                "{stagger_secs}s stagger opened zones together: {opens:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn never_opens_more_than_two_zones_at_once() {
+        let periods = [period(ZONE_1, 2), period(ZONE_2, 1), period(ZONE_3, 2)];
+
+        let mut open_zones = 0;
+        for event in plan(&periods, 45) {
+            if event.activate {
+                open_zones += 1;
+            } else {
+                open_zones -= 1;
+            }
+            assert!(open_zones <= 2);
+        }
+    }
+
+    #[test]
+    fn skips_zero_minute_periods_without_underflow() {
+        let periods = [period(ZONE_1, 0), period(ZONE_2, 1)];
+
+        assert_eq!(plan(&periods, 10), vec![open(0, ZONE_2), close(60, ZONE_2)]);
+    }
+}
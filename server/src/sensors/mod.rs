@@ -0,0 +1,59 @@
+pub mod moisture;
+
+use crate::error::ServerError;
+use crate::flow::FlowMonitor;
+use crate::history::{self, HistoryEvent};
+use crate::types::{DeviceId, HistoryMutex, SensorsMutex, ZoneId};
+
+use chrono::Local;
+use moisture::MoistureReading;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowReading {
+    pub liters_per_minute: f64,
+    pub total_liters: f64,
+}
+
+/// The latest readings from the controllers' sensors.
+#[derive(Debug, Default)]
+pub struct Sensors {
+    /// Whether each controller's rain sensor is wet. Kept while a controller
+    /// is away, as it reports again when it reconnects.
+    pub rain_sensors: BTreeMap<DeviceId, bool>,
+    pub flow: BTreeMap<DeviceId, FlowReading>,
+    pub flow_monitors: BTreeMap<DeviceId, FlowMonitor>,
+    pub soil_moisture: BTreeMap<ZoneId, MoistureReading>,
+}
+
+impl Sensors {
+    /// Whether any controller's rain sensor is wet.
+    pub fn rain_sensor_active(&self) -> bool {
+        self.rain_sensors.values().any(|active| *active)
+    }
+}
+
+/// Stores a soil moisture reading for `zone`, from the controller or any
+/// other client with a sensor.
+pub async fn record_soil_moisture(
+    sensors: &SensorsMutex,
+    history: &HistoryMutex,
+    zone: ZoneId,
+    percent: f64,
+) -> Result<(), ServerError> {
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(ServerError::InvalidMoistureReading(percent));
+    }
+
+    sensors.lock().await.soil_moisture.insert(
+        zone,
+        MoistureReading {
+            percent,
+            at: Local::now(),
+        },
+    );
+    history::record(history, HistoryEvent::SoilMoistureReading { zone, percent }).await;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use crate::types::MoistureRule;
+
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+/// Readings older than this are too stale to act on.
+const MAX_READING_AGE_HOURS: i64 = 6;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MoistureReading {
+    pub percent: f64,
+    pub at: DateTime<Local>,
+}
+
+/// How long a step planned for `planned_secs` should run under `rule`, given
+/// the latest reading of its zone. Steps run as planned without a recent
+/// reading.
+pub fn step_secs(
+    rule: &MoistureRule,
+    reading: Option<&MoistureReading>,
+    planned_secs: u64,
+    now: DateTime<Local>,
+) -> u64 {
+    let Some(reading) =
+        reading.filter(|reading| now - reading.at <= TimeDelta::hours(MAX_READING_AGE_HOURS))
+    else {
+        return planned_secs;
+    };
+
+    match rule {
+        MoistureRule::SkipAbove { percent } if reading.percent > *percent => 0,
+        MoistureRule::SkipAbove { .. } => planned_secs,
+        MoistureRule::ShortenToward { percent } => {
+            let share = ((percent - reading.percent) / percent).clamp(0.0, 1.0);
+            (planned_secs as f64 * share).round() as u64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn reading(percent: f64, hours_ago: i64) -> MoistureReading {
+        MoistureReading {
+            percent,
+            at: now() - TimeDelta::hours(hours_ago),
+        }
+    }
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2025, 6, 2, 6, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn skips_above_the_threshold() {
+        let rule = MoistureRule::SkipAbove { percent: 40.0 };
+        assert_eq!(step_secs(&rule, Some(&reading(45.0, 1)), 600, now()), 0);
+        assert_eq!(step_secs(&rule, Some(&reading(40.0, 1)), 600, now()), 600);
+        assert_eq!(step_secs(&rule, Some(&reading(10.0, 1)), 600, now()), 600);
+    }
+
+    #[test]
+    fn shortens_toward_the_target() {
+        let rule = MoistureRule::ShortenToward { percent: 40.0 };
+        assert_eq!(step_secs(&rule, Some(&reading(0.0, 1)), 600, now()), 600);
+        assert_eq!(step_secs(&rule, Some(&reading(30.0, 1)), 600, now()), 150);
+        assert_eq!(step_secs(&rule, Some(&reading(40.0, 1)), 600, now()), 0);
+        assert_eq!(step_secs(&rule, Some(&reading(55.0, 1)), 600, now()), 0);
+    }
+
+    #[test]
+    fn runs_as_planned_without_a_recent_reading() {
+        let rule = MoistureRule::SkipAbove { percent: 40.0 };
+        assert_eq!(step_secs(&rule, None, 600, now()), 600);
+        assert_eq!(
+            step_secs(
+                &rule,
+                Some(&reading(90.0, MAX_READING_AGE_HOURS)),
+                600,
+                now()
+            ),
+            0
+        );
+        assert_eq!(
+            step_secs(
+                &rule,
+                Some(&reading(90.0, MAX_READING_AGE_HOURS + 1)),
+                600,
+                now()
+            ),
+            600
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use crate::error::ServerError;
+
+use native_tls::Identity;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use tokio_native_tls::TlsAcceptor;
+
+pub const DEFAULT_TLS_PORT: u16 = 9443;
+
+/// A `wss://` listener, served alongside the plain one.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsConfig {
+    #[serde(default = "default_tls_port")]
+    pub port: u16,
+    /// PEM certificate chain, leaf first.
+    pub cert_path: String,
+    /// PEM PKCS #8 private key for the certificate.
+    pub key_path: String,
+}
+
+fn default_tls_port() -> u16 {
+    DEFAULT_TLS_PORT
+}
+
+impl TlsConfig {
+    pub fn acceptor(&self) -> Result<TlsAcceptor, ServerError> {
+        let read = |path: &str| {
+            fs::read(path).map_err(|_| ServerError::FailedToReadFile(path.to_string()))
+        };
+        let identity = Identity::from_pkcs8(&read(&self.cert_path)?, &read(&self.key_path)?)
+            .map_err(|e| ServerError::InvalidTlsIdentity(e.to_string()))?;
+        let acceptor = native_tls::TlsAcceptor::new(identity)
+            .map_err(|e| ServerError::InvalidTlsIdentity(e.to_string()))?;
+        Ok(acceptor.into())
+    }
+}
@@ -1,19 +1,46 @@
+use crate::audit::Audit;
 use crate::config::Config;
+use crate::error::ServerError;
+use crate::history::History;
+use crate::pairing::Pairing;
 use crate::scheduler_runner::ScheduleRunner;
+use crate::sensors::Sensors;
+use crate::zone_state::ZoneState;
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use shared::Signer;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display};
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio_tungstenite::tungstenite;
+use uuid::Uuid;
 
-pub type ClientMap = Arc<Mutex<HashMap<ClientType, UnboundedSender<tungstenite::Message>>>>;
-pub type ControllerTimestamp = Arc<Mutex<Option<Instant>>>;
+/// Identifies one connection for as long as it stays open.
+pub type SessionId = u64;
+pub type ClientMap = Arc<Mutex<HashMap<SessionId, Client>>>;
+/// When each controller last sent a message.
+pub type ControllerTimestamps = Arc<Mutex<HashMap<DeviceId, Instant>>>;
 pub type ConfigMutex = Arc<Mutex<Config>>;
 pub type ScheduleRunnerMutex = Arc<Mutex<ScheduleRunner>>;
+pub type ZoneStateMutex = Arc<Mutex<ZoneState>>;
+pub type HistoryMutex = Arc<Mutex<History>>;
+pub type SensorsMutex = Arc<Mutex<Sensors>>;
+pub type PairingMutex = Arc<Mutex<Pairing>>;
+pub type AuditMutex = Arc<Mutex<Audit>>;
+
+#[derive(Debug)]
+pub struct Client {
+    pub client_type: ClientType,
+    /// Set for controllers, from the ID they identified with.
+    pub device_id: Option<DeviceId>,
+    /// Set for controllers, signs everything sent to them.
+    pub signer: Option<Signer>,
+    pub sender: UnboundedSender<tungstenite::Message>,
+}
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Hash, Clone, Copy)]
 pub enum ClientType {
@@ -67,17 +94,160 @@ pub enum Zone {
     Zone6 = 5,
 }
 
+// the controller numbers its zones starting from 1
 impl From<Zone> for u8 {
     fn from(zone: Zone) -> Self {
-        zone as u8
+        zone as u8 + 1
+    }
+}
+
+impl TryFrom<u8> for Zone {
+    type Error = ServerError;
+
+    fn try_from(zone: u8) -> Result<Self, Self::Error> {
+        match zone {
+            1 => Ok(Zone::Zone1),
+            2 => Ok(Zone::Zone2),
+            3 => Ok(Zone::Zone3),
+            4 => Ok(Zone::Zone4),
+            5 => Ok(Zone::Zone5),
+            6 => Ok(Zone::Zone6),
+            _ => Err(ServerError::InvalidZone(zone)),
+        }
+    }
+}
+
+impl Display for Zone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Zone {}", u8::from(*self))
+    }
+}
+
+/// Identifies a controller by the MAC address of its Wi-Fi interface.
+#[derive(Debug, Eq, PartialEq, Clone, Hash, Copy, Ord, PartialOrd, Default)]
+pub struct DeviceId(pub [u8; 6]);
+
+impl DeviceId {
+    /// Stands in for the one controller there was before controllers had
+    /// IDs, so older history still reads.
+    pub const UNKNOWN: DeviceId = DeviceId([0; 6]);
+}
+
+impl Display for DeviceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02X}:{b:02X}:{c:02X}:{d:02X}:{e:02X}:{g:02X}")
+    }
+}
+
+impl FromStr for DeviceId {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ServerError::InvalidDeviceId(s.to_string());
+        let mut bytes = [0; 6];
+        let mut parts = s.split(':');
+        for byte in bytes.iter_mut() {
+            let part = parts
+                .next()
+                .filter(|part| part.len() == 2)
+                .ok_or_else(invalid)?;
+            *byte = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(DeviceId(bytes))
+    }
+}
+
+impl Serialize for DeviceId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DeviceId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A zone on one of the controllers, written `AA:BB:CC:DD:EE:FF/zone1`.
+#[derive(Debug, Eq, PartialEq, Clone, Hash, Copy, Ord, PartialOrd)]
+pub struct ZoneId {
+    pub controller: DeviceId,
+    pub zone: Zone,
+}
+
+impl ZoneId {
+    pub fn new(controller: DeviceId, zone: Zone) -> Self {
+        Self { controller, zone }
+    }
+
+    /// The form used in config and messages.
+    pub fn key(&self) -> String {
+        format!("{}/zone{}", self.controller, u8::from(self.zone))
+    }
+}
+
+impl Display for ZoneId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} on {}", self.zone, self.controller)
+    }
+}
+
+impl FromStr for ZoneId {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (controller, zone) = match s.split_once('/') {
+            Some((controller, zone)) => (controller.parse()?, zone),
+            None => (DeviceId::UNKNOWN, s),
+        };
+        let zone = zone
+            .strip_prefix("zone")
+            .and_then(|number| number.parse::<u8>().ok())
+            .ok_or_else(|| ServerError::InvalidZoneId(s.to_string()))
+            .and_then(Zone::try_from)?;
+        Ok(ZoneId { controller, zone })
+    }
+}
+
+impl Serialize for ZoneId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.key())
     }
 }
 
+impl<'de> Deserialize<'de> for ZoneId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// How a step reacts to the soil moisture of its zone, in percent.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum MoistureRule {
+    /// Skip the step if the soil is wetter than `percent`.
+    SkipAbove { percent: f64 },
+    /// Shorten the step in proportion to how close the soil is to
+    /// `percent`, skipping it once the soil gets there.
+    ShortenToward { percent: f64 },
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 #[serde(rename_all = "camelCase")]
 pub struct ActivePeriod {
-    pub zone: Zone,
+    pub zone: ZoneId,
     pub duration_minutes: u32,
+    #[serde(default)]
+    pub moisture: Option<MoistureRule>,
 }
 
 // in service of having unique zone entries within the active_periods set
@@ -95,14 +265,121 @@ impl std::hash::Hash for ActivePeriod {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+/// Weather that stops a schedule from starting. Metric units throughout.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SkipRule {
+    /// At least `mm` of rain fell in the last 24 hours.
+    RecentRain { mm: f64 },
+    /// The chance of rain in the next 12 hours reaches `percent`.
+    RainForecast { percent: f64 },
+    /// The wind is faster than `speed`, in km/h.
+    Wind { speed: f64 },
+    /// The temperature is below 0°C.
+    Freezing,
+}
+
+impl Display for SkipRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipRule::RecentRain { mm } => write!(f, "at least {mm}mm of rain in the last 24h"),
+            SkipRule::RainForecast { percent } => {
+                write!(f, "at least {percent}% chance of rain in the next 12h")
+            }
+            SkipRule::Wind { speed } => write!(f, "wind over {speed}km/h"),
+            SkipRule::Freezing => write!(f, "below freezing"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Schedule {
+    /// Stays the same through renames and reorders. Schedules saved before
+    /// they had one are given one on load.
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
     pub name: String,
     pub days: HashSet<Day>,
     pub active_periods: HashSet<ActivePeriod>,
     pub start_time_minutes: u32,
     pub is_active: bool,
+    #[serde(default)]
+    pub stagger_secs: Option<u32>,
+    #[serde(default)]
+    pub skip_rules: Vec<SkipRule>,
+    /// Whether an active rain sensor stops the schedule from starting.
+    #[serde(default = "default_obey_rain_sensor")]
+    pub obey_rain_sensor: bool,
+}
+
+fn default_obey_rain_sensor() -> bool {
+    true
 }
 
 pub type Schedules = Vec<Schedule>;
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HydraulicGroup {
+    pub name: String,
+    pub zones: HashSet<ZoneId>,
+}
+
+pub type HydraulicGroups = Vec<HydraulicGroup>;
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum VolumeUnit {
+    #[default]
+    Gallons,
+    Liters,
+}
+
+impl VolumeUnit {
+    pub fn to_liters(self, volume: f64) -> f64 {
+        match self {
+            VolumeUnit::Gallons => volume * 3.785_411_784,
+            VolumeUnit::Liters => volume,
+        }
+    }
+}
+
+impl Display for VolumeUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VolumeUnit::Gallons => write!(f, "gallons"),
+            VolumeUnit::Liters => write!(f, "liters"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum DepthUnit {
+    #[default]
+    Inches,
+    Millimeters,
+}
+
+impl DepthUnit {
+    pub fn to_mm(self, depth: f64) -> f64 {
+        match self {
+            DepthUnit::Inches => depth * 25.4,
+            DepthUnit::Millimeters => depth,
+        }
+    }
+}
+
+/// What to do when a program is due while another is still running.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum OverlapPolicy {
+    /// Wait for the running program to finish first.
+    #[default]
+    Queue,
+    /// Skip the later program.
+    Skip,
+    /// Run both, within the zone limits.
+    Parallel,
+}
@@ -0,0 +1,417 @@
+use crate::config::Config;
+use crate::history::{HistoryEntry, HistoryEvent, ZoneSource};
+use crate::scheduler_runner::rules;
+use crate::types::{VolumeUnit, ZoneId};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RunUsage {
+    pub source: ZoneSource,
+    pub open: DateTime<Local>,
+    pub close: DateTime<Local>,
+    pub minutes: f64,
+    pub volume: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PeriodUsage {
+    pub minutes: f64,
+    pub volume: Option<f64>,
+}
+
+impl PeriodUsage {
+    fn add(&mut self, minutes: f64, volume: Option<f64>) {
+        self.minutes += minutes;
+        if let Some(volume) = volume {
+            *self.volume.get_or_insert(0.0) += volume;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ZoneUsage {
+    pub flow_rate: Option<f64>,
+    pub total: PeriodUsage,
+    pub runs: Vec<RunUsage>,
+    pub daily: BTreeMap<NaiveDate, PeriodUsage>,
+    /// Keyed by ISO week, e.g. `2025-W07`.
+    pub weekly: BTreeMap<String, PeriodUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    pub unit: VolumeUnit,
+    pub zones: BTreeMap<ZoneId, ZoneUsage>,
+}
+
+/// When a zone opened by a schedule was due to close, going by the schedule
+/// as it's configured now.
+fn scheduled_end(
+    config: &Config,
+    zone: ZoneId,
+    open: DateTime<Local>,
+    source: &ZoneSource,
+) -> Option<DateTime<Local>> {
+    let ZoneSource::Schedule { schedule } = source else {
+        return None;
+    };
+    let schedule = config
+        .schedules
+        .iter()
+        .find(|candidate| candidate.name == *schedule)?;
+    let period = rules::periods(config, schedule)
+        .into_iter()
+        .find(|period| period.zone == zone)?;
+    Some(open + TimeDelta::minutes(period.duration_minutes.into()))
+}
+
+/// Works out how long each zone ran between `from` and `to` from the zone
+/// history, and how much water that was at the configured flow rates. Runs
+/// count towards the day and week they started in.
+///
+/// A zone that was never closed, say because the server stopped mid-run,
+/// counts until its controller disconnected or its schedule finished, and
+/// no longer than the schedule had it open for.
+pub fn usage_report(
+    config: &Config,
+    entries: &[HistoryEntry],
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+) -> UsageReport {
+    let mut zones: BTreeMap<ZoneId, ZoneUsage> = BTreeMap::new();
+    let mut opened: BTreeMap<ZoneId, (DateTime<Local>, ZoneSource)> = BTreeMap::new();
+
+    let mut add_run = |zone: ZoneId, open: DateTime<Local>, close: DateTime<Local>, source| {
+        let open = open.max(from);
+        let close = close.min(to);
+        if close <= open {
+            return;
+        }
+
+        let flow_rate = config.zones.get(&zone).and_then(|zone| zone.flow_rate);
+        let minutes = (close - open).num_milliseconds() as f64 / 60_000.0;
+        let volume = flow_rate.map(|flow_rate| flow_rate * minutes);
+
+        let usage = zones.entry(zone).or_default();
+        usage.flow_rate = flow_rate;
+        usage.total.add(minutes, volume);
+        usage
+            .daily
+            .entry(open.date_naive())
+            .or_default()
+            .add(minutes, volume);
+        let week = open.iso_week();
+        usage
+            .weekly
+            .entry(format!("{}-W{:02}", week.year(), week.week()))
+            .or_default()
+            .add(minutes, volume);
+
+        usage.runs.push(RunUsage {
+            source,
+            open,
+            close,
+            minutes,
+            volume,
+        });
+    };
+
+    // where a zone the history never closed stopped running, at the latest
+    let unclosed_end = |zone, open, source: &ZoneSource, at: DateTime<Local>| {
+        scheduled_end(config, zone, open, source).map_or(at, |end| end.min(at))
+    };
+
+    for entry in entries.iter().filter(|entry| entry.timestamp < to) {
+        let ended: Vec<ZoneId> = match &entry.event {
+            HistoryEvent::ZoneToggled {
+                zone,
+                activate: true,
+                source,
+            } => {
+                opened
+                    .entry(*zone)
+                    .or_insert((entry.timestamp, source.clone()));
+                continue;
+            }
+            HistoryEvent::ZoneToggled {
+                zone,
+                activate: false,
+                ..
+            } => {
+                if let Some((open, source)) = opened.remove(zone) {
+                    add_run(*zone, open, entry.timestamp, source);
+                }
+                continue;
+            }
+            // nothing can close the zones of a controller that's gone
+            HistoryEvent::ControllerDisconnected { controller } => opened
+                .keys()
+                .filter(|zone| zone.controller == *controller)
+                .copied()
+                .collect(),
+            HistoryEvent::ScheduleFinished { schedule, .. } => opened
+                .iter()
+                .filter(|(_, (_, source))| {
+                    matches!(source, ZoneSource::Schedule { schedule: opened_by } if opened_by == schedule)
+                })
+                .map(|(zone, _)| *zone)
+                .collect(),
+            _ => continue,
+        };
+        for (zone, (open, source)) in ended
+            .into_iter()
+            .filter_map(|zone| opened.remove_entry(&zone))
+        {
+            let close = unclosed_end(zone, open, &source, entry.timestamp);
+            add_run(zone, open, close, source);
+        }
+    }
+
+    // zones that are still open count up to now
+    let now = Local::now();
+    for (zone, (open, source)) in opened {
+        let close = unclosed_end(zone, open, &source, now);
+        add_run(zone, open, close, source);
+    }
+
+    UsageReport {
+        unit: config.volume_unit,
+        zones,
+    }
+}
+
+pub fn to_csv(report: &UsageReport) -> String {
+    let mut csv = String::from("controller,zone,source,open,close,minutes,volume,unit\n");
+    for (zone, usage) in report.zones.iter() {
+        for run in usage.runs.iter() {
+            let source = match &run.source {
+                ZoneSource::User => "user".to_string(),
+                ZoneSource::FlowAlert => "flow alert".to_string(),
+                ZoneSource::FreezeGuard => "freeze guard".to_string(),
+                ZoneSource::Schedule { schedule } => {
+                    format!("\"{}\"", schedule.replace('"', "\"\""))
+                }
+            };
+            let volume = run.volume.map(|v| format!("{v:.2}")).unwrap_or_default();
+            writeln!(
+                csv,
+                "{},{},{},{},{},{:.2},{},{}",
+                zone.controller,
+                u8::from(zone.zone),
+                source,
+                run.open.to_rfc3339(),
+                run.close.to_rfc3339(),
+                run.minutes,
+                volume,
+                report.unit,
+            )
+            .unwrap();
+        }
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ZoneConfig;
+    use crate::types::{DeviceId, Schedule, Zone};
+
+    use chrono::TimeZone;
+
+    const DEVICE: DeviceId = DeviceId([0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF]);
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DEVICE,
+        zone: Zone::Zone1,
+    };
+
+    // a Monday
+    fn at(hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2025, 2, 10, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn entry(timestamp: DateTime<Local>, event: HistoryEvent) -> HistoryEntry {
+        HistoryEntry {
+            id: 0,
+            timestamp,
+            event,
+        }
+    }
+
+    fn toggled(timestamp: DateTime<Local>, activate: bool, source: ZoneSource) -> HistoryEntry {
+        entry(
+            timestamp,
+            HistoryEvent::ZoneToggled {
+                zone: ZONE_1,
+                activate,
+                source,
+            },
+        )
+    }
+
+    fn lawn() -> ZoneSource {
+        ZoneSource::Schedule {
+            schedule: "lawn".to_string(),
+        }
+    }
+
+    fn config() -> Config {
+        let schedule: Schedule = serde_json::from_value(serde_json::json!({
+            "name": "lawn",
+            "days": ["monday"],
+            "activePeriods": [{ "zone": ZONE_1, "durationMinutes": 10 }],
+            "startTimeMinutes": 360,
+            "isActive": true,
+        }))
+        .unwrap();
+        let mut config = Config {
+            schedules: vec![schedule],
+            ..Config::default()
+        };
+        config.zones.insert(
+            ZONE_1,
+            ZoneConfig {
+                enabled: true,
+                flow_rate: Some(2.0),
+                weekly_depth_target: None,
+                precipitation_rate: None,
+                crop_coefficient: None,
+            },
+        );
+        config
+    }
+
+    fn minutes(report: &UsageReport) -> Vec<f64> {
+        report.zones[&ZONE_1]
+            .runs
+            .iter()
+            .map(|run| run.minutes)
+            .collect()
+    }
+
+    #[test]
+    fn totals_runs_by_day_and_week() {
+        let entries = [
+            toggled(at(6, 0), true, lawn()),
+            toggled(at(6, 10), false, lawn()),
+            toggled(at(18, 0), true, ZoneSource::User),
+            toggled(at(18, 5), false, ZoneSource::User),
+        ];
+        let report = usage_report(&config(), &entries, at(0, 0), at(23, 0));
+
+        let usage = &report.zones[&ZONE_1];
+        assert_eq!(usage.total.minutes, 15.0);
+        assert_eq!(usage.total.volume, Some(30.0));
+        assert_eq!(usage.daily[&at(0, 0).date_naive()].minutes, 15.0);
+        assert_eq!(usage.weekly["2025-W07"].volume, Some(30.0));
+        assert_eq!(minutes(&report), [10.0, 5.0]);
+    }
+
+    #[test]
+    fn clamps_runs_to_the_report() {
+        let entries = [
+            toggled(at(5, 50), true, ZoneSource::User),
+            toggled(at(6, 10), false, ZoneSource::User),
+        ];
+        let report = usage_report(&config(), &entries, at(6, 0), at(23, 0));
+        assert_eq!(minutes(&report), [10.0]);
+    }
+
+    #[test]
+    fn unclosed_runs_end_when_their_controller_disconnects() {
+        let entries = [
+            toggled(at(6, 0), true, ZoneSource::User),
+            entry(
+                at(6, 5),
+                HistoryEvent::ControllerDisconnected { controller: DEVICE },
+            ),
+            // a later run isn't stretched back to the first open
+            toggled(at(7, 0), true, ZoneSource::User),
+            toggled(at(7, 1), false, ZoneSource::User),
+        ];
+        let report = usage_report(&config(), &entries, at(0, 0), at(23, 0));
+        assert_eq!(minutes(&report), [5.0, 1.0]);
+    }
+
+    #[test]
+    fn unclosed_runs_end_when_their_schedule_finishes() {
+        let entries = [
+            toggled(at(6, 0), true, lawn()),
+            entry(
+                at(6, 7),
+                HistoryEvent::ScheduleFinished {
+                    schedule: "lawn".to_string(),
+                    error: Some("cancelled".to_string()),
+                },
+            ),
+        ];
+        let report = usage_report(&config(), &entries, at(0, 0), at(23, 0));
+        assert_eq!(minutes(&report), [7.0]);
+    }
+
+    #[test]
+    fn unclosed_runs_stop_at_their_scheduled_end() {
+        let entries = [toggled(at(6, 0), true, lawn())];
+        let report = usage_report(&config(), &entries, at(0, 0), at(23, 0));
+        assert_eq!(minutes(&report), [10.0]);
+
+        // a closed run counts in full, even past what's scheduled now
+        let entries = [
+            toggled(at(6, 0), true, lawn()),
+            toggled(at(6, 12), false, lawn()),
+        ];
+        let report = usage_report(&config(), &entries, at(0, 0), at(23, 0));
+        assert_eq!(minutes(&report), [12.0]);
+    }
+
+    #[test]
+    fn writes_one_csv_row_per_run() {
+        let quoted = ZoneSource::Schedule {
+            schedule: "front \"lawn\", north".to_string(),
+        };
+        let entries = [
+            toggled(at(6, 0), true, quoted.clone()),
+            toggled(at(6, 10), false, quoted),
+            toggled(at(7, 0), true, ZoneSource::User),
+            toggled(at(7, 5), false, ZoneSource::User),
+        ];
+        let config = config();
+        let report = usage_report(&config, &entries, at(0, 0), at(23, 0));
+
+        let csv = to_csv(&report);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("controller,zone,source,open,close,minutes,volume,unit")
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            format!(
+                "AA:BB:CC:DD:EE:FF,1,\"front \"\"lawn\"\", north\",{},{},10.00,20.00,{}",
+                at(6, 0).to_rfc3339(),
+                at(6, 10).to_rfc3339(),
+                config.volume_unit,
+            )
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            format!(
+                "AA:BB:CC:DD:EE:FF,1,user,{},{},5.00,10.00,{}",
+                at(7, 0).to_rfc3339(),
+                at(7, 5).to_rfc3339(),
+                config.volume_unit,
+            )
+        );
+        assert_eq!(lines.next(), None);
+    }
+}
@@ -0,0 +1,392 @@
+use crate::config::Config;
+use crate::history::{HistoryEntry, HistoryEvent};
+use crate::types::{ActivePeriod, ZoneId};
+use crate::usage::UsageReport;
+use crate::weather::{DailyWeather, et};
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How many days back the balance starts from, assuming the soil was full,
+/// when it wasn't worked out more recently.
+pub const BALANCE_DAYS: u64 = 14;
+/// The most water, in mm, a zone's root zone can be short of. Beyond this
+/// the plants are as dry as they get and further ET isn't counted.
+const MAX_DEFICIT_MM: f64 = 50.0;
+/// Zones short of less than this share of a run's depth are skipped.
+const MIN_DEFICIT_RATIO: f64 = 0.2;
+/// The most a run is lengthened to catch up on a deficit.
+const MAX_SCALE: f64 = 1.5;
+
+/// What went in and out of a zone since its balance was last known, in mm.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ZoneBalance {
+    pub et: f64,
+    pub rainfall: f64,
+    pub irrigation: f64,
+    pub deficit: f64,
+}
+
+/// A scheduled run scaled to its zone's balance, with the balance it was
+/// scaled by.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EtAdjustment {
+    pub zone: ZoneId,
+    pub balance: ZoneBalance,
+    /// Depth the scheduled minutes would have applied, in mm.
+    pub scheduled_depth: f64,
+    pub scheduled_minutes: u32,
+    pub adjusted_minutes: u32,
+}
+
+/// A zone's deficit as worked out for a run, before the run watered it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KnownBalance {
+    pub at: DateTime<Local>,
+    pub deficit: f64,
+}
+
+fn precipitation_rate_mm(config: &Config, zone: ZoneId) -> Option<f64> {
+    let rate = config.zones.get(&zone)?.precipitation_rate?;
+    (rate > 0.0).then(|| config.depth_unit.to_mm(rate))
+}
+
+/// The latest balance of each zone recorded in `entries` before `today`.
+/// Today's are left out, as the day's weather may have changed since.
+pub fn known_balances(
+    entries: &[HistoryEntry],
+    today: NaiveDate,
+) -> BTreeMap<ZoneId, KnownBalance> {
+    let mut known = BTreeMap::new();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.timestamp.date_naive() < today)
+    {
+        if let HistoryEvent::EtAdjusted { adjustment, .. } = &entry.event {
+            known.insert(
+                adjustment.zone,
+                KnownBalance {
+                    at: entry.timestamp,
+                    deficit: adjustment.balance.deficit,
+                },
+            );
+        }
+    }
+    known
+}
+
+/// Rolls each zone with a precipitation rate forward to `today`: crop ET
+/// dries the soil out, rain and the zone's own runs wet it. Zones carry on
+/// from their `known` balance, the rest start full on `first_day`. Days
+/// without weather only count the runs.
+pub fn zone_balances(
+    config: &Config,
+    weather: &[DailyWeather],
+    latitude: f64,
+    usage: &UsageReport,
+    known: &BTreeMap<ZoneId, KnownBalance>,
+    first_day: NaiveDate,
+    today: NaiveDate,
+) -> BTreeMap<ZoneId, ZoneBalance> {
+    let mut balances = BTreeMap::new();
+    for (zone, zone_config) in config.zones.iter() {
+        let Some(rate) = precipitation_rate_mm(config, *zone) else {
+            continue;
+        };
+        let crop_coefficient = zone_config.crop_coefficient.unwrap_or(1.0);
+        let zone_usage = usage.zones.get(zone);
+        let daily_usage = zone_usage.map(|usage| &usage.daily);
+
+        let mut balance = ZoneBalance {
+            et: 0.0,
+            rainfall: 0.0,
+            irrigation: 0.0,
+            deficit: 0.0,
+        };
+        let mut from = first_day;
+        if let Some(known) = known
+            .get(zone)
+            .filter(|known| known.at.date_naive() >= first_day)
+        {
+            // that day's weather is already in the deficit, only the runs
+            // after it was worked out aren't
+            let day = known.at.date_naive();
+            let irrigation = zone_usage
+                .into_iter()
+                .flat_map(|usage| usage.runs.iter())
+                .filter(|run| run.open >= known.at && run.open.date_naive() == day)
+                .map(|run| run.minutes / 60.0 * rate)
+                .sum::<f64>();
+            balance.irrigation = irrigation;
+            balance.deficit = (known.deficit - irrigation).clamp(0.0, MAX_DEFICIT_MM);
+            from = day.succ_opt().unwrap_or(day);
+        }
+
+        for day in from.iter_days().take_while(|day| *day <= today) {
+            let (et, rainfall) = weather
+                .iter()
+                .find(|weather| weather.date == day)
+                .map(|weather| {
+                    (
+                        et::hargreaves(weather, latitude) * crop_coefficient,
+                        weather.rainfall,
+                    )
+                })
+                .unwrap_or((0.0, 0.0));
+            let irrigation = daily_usage
+                .and_then(|daily| daily.get(&day))
+                .map_or(0.0, |usage| usage.minutes / 60.0 * rate);
+
+            balance.et += et;
+            balance.rainfall += rainfall;
+            balance.irrigation += irrigation;
+            balance.deficit =
+                (balance.deficit + et - rainfall - irrigation).clamp(0.0, MAX_DEFICIT_MM);
+        }
+
+        balances.insert(*zone, balance);
+    }
+    balances
+}
+
+/// Scales each period with a balance to put back what its zone is short of,
+/// skipping zones that are wet enough already.
+pub fn adjust(
+    config: &Config,
+    balances: &BTreeMap<ZoneId, ZoneBalance>,
+    periods: &mut [ActivePeriod],
+) -> Vec<EtAdjustment> {
+    let mut adjustments = vec![];
+    for period in periods.iter_mut() {
+        let (Some(balance), Some(rate)) = (
+            balances.get(&period.zone),
+            precipitation_rate_mm(config, period.zone),
+        ) else {
+            continue;
+        };
+        let scheduled_depth = period.duration_minutes as f64 / 60.0 * rate;
+        if scheduled_depth <= 0.0 {
+            continue;
+        }
+
+        let ratio = balance.deficit / scheduled_depth;
+        let adjusted_minutes = if ratio < MIN_DEFICIT_RATIO {
+            0
+        } else {
+            (period.duration_minutes as f64 * ratio.min(MAX_SCALE)).round() as u32
+        };
+
+        adjustments.push(EtAdjustment {
+            zone: period.zone,
+            balance: balance.clone(),
+            scheduled_depth,
+            scheduled_minutes: period.duration_minutes,
+            adjusted_minutes,
+        });
+        period.duration_minutes = adjusted_minutes;
+    }
+    adjustments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ZoneConfig;
+    use crate::history::ZoneSource;
+    use crate::types::{DepthUnit, DeviceId, Zone};
+    use crate::usage::usage_report;
+
+    use chrono::{TimeDelta, TimeZone};
+
+    const LATITUDE: f64 = 40.0;
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 7, day).unwrap()
+    }
+
+    fn at(day: u32, hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2025, 7, day, hour, 0, 0).unwrap()
+    }
+
+    // 15 to 31°C at 40°N in early July is about 6.4mm of ET a day
+    fn weather(rainfall: [f64; 3]) -> Vec<DailyWeather> {
+        (1..=3)
+            .zip(rainfall)
+            .map(|(day, rainfall)| DailyWeather {
+                date: date(day),
+                temp_min: 15.0,
+                temp_max: 31.0,
+                humidity: None,
+                rainfall,
+            })
+            .collect()
+    }
+
+    // 12mm an hour, so 2mm in 10 minutes
+    fn config() -> Config {
+        let mut config = Config {
+            depth_unit: DepthUnit::Millimeters,
+            ..Config::default()
+        };
+        config.zones.insert(
+            ZONE_1,
+            ZoneConfig {
+                enabled: true,
+                flow_rate: None,
+                weekly_depth_target: None,
+                precipitation_rate: Some(12.0),
+                crop_coefficient: None,
+            },
+        );
+        config
+    }
+
+    fn toggled(timestamp: DateTime<Local>, activate: bool) -> HistoryEntry {
+        HistoryEntry {
+            id: 0,
+            timestamp,
+            event: HistoryEvent::ZoneToggled {
+                zone: ZONE_1,
+                activate,
+                source: ZoneSource::User,
+            },
+        }
+    }
+
+    fn adjusted(timestamp: DateTime<Local>, deficit: f64) -> HistoryEntry {
+        HistoryEntry {
+            id: 0,
+            timestamp,
+            event: HistoryEvent::EtAdjusted {
+                schedule: "lawn".to_string(),
+                adjustment: EtAdjustment {
+                    zone: ZONE_1,
+                    balance: ZoneBalance {
+                        et: 0.0,
+                        rainfall: 0.0,
+                        irrigation: 0.0,
+                        deficit,
+                    },
+                    scheduled_depth: 2.0,
+                    scheduled_minutes: 10,
+                    adjusted_minutes: 10,
+                },
+            },
+        }
+    }
+
+    // a 10 minute run, watering 2mm
+    fn run(day: u32, hour: u32) -> [HistoryEntry; 2] {
+        let open = at(day, hour);
+        [
+            toggled(open, true),
+            toggled(open + TimeDelta::minutes(10), false),
+        ]
+    }
+
+    fn zone_balance(
+        entries: &[HistoryEntry],
+        rainfall: [f64; 3],
+        first_day: NaiveDate,
+    ) -> ZoneBalance {
+        let config = config();
+        let usage = usage_report(&config, entries, at(1, 0), at(4, 0));
+        let known = known_balances(entries, date(3));
+        zone_balances(
+            &config,
+            &weather(rainfall),
+            LATITUDE,
+            &usage,
+            &known,
+            first_day,
+            date(3),
+        )
+        .remove(&ZONE_1)
+        .unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 0.01, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn follows_et_rain_and_runs() {
+        let balance = zone_balance(&run(3, 6), [0.0, 10.0, 0.0], date(1));
+
+        assert_close(balance.et, 19.121);
+        assert_eq!(balance.rainfall, 10.0);
+        assert_close(balance.irrigation, 2.0);
+        // 6.380, then 6.374 - 10 of rain, then 6.367 - 2 of watering
+        assert_close(balance.deficit, 7.121);
+    }
+
+    #[test]
+    fn stays_between_full_and_the_most_it_can_dry() {
+        let balance = zone_balance(&[], [30.0, 0.0, 0.0], date(1));
+        assert_close(balance.deficit, 6.374 + 6.367);
+
+        let entries = [adjusted(at(2, 6), 48.0)];
+        let balance = zone_balance(&entries, [0.0; 3], date(1));
+        assert_eq!(balance.deficit, MAX_DEFICIT_MM);
+    }
+
+    #[test]
+    fn carries_on_from_the_last_known_balance() {
+        let entries = [
+            run(1, 6),
+            [adjusted(at(1, 18), 10.0), adjusted(at(3, 6), 0.0)],
+            run(1, 19),
+        ]
+        .concat();
+
+        // the morning run and today's balance are ignored, the evening run
+        // after the balance was worked out counts
+        let balance = zone_balance(&entries, [0.0; 3], date(1));
+        assert_close(balance.irrigation, 2.0);
+        assert_close(balance.et, 6.374 + 6.367);
+        assert_close(balance.deficit, 10.0 - 2.0 + 6.374 + 6.367);
+
+        // too old to carry on from, so the soil is taken to be full
+        let balance = zone_balance(&entries, [0.0; 3], date(2));
+        assert_close(balance.deficit, 6.374 + 6.367);
+    }
+
+    #[test]
+    fn scales_runs_to_the_deficit() {
+        let config = config();
+        let adjusted_minutes = |deficit| {
+            let balances = BTreeMap::from([(
+                ZONE_1,
+                ZoneBalance {
+                    et: 0.0,
+                    rainfall: 0.0,
+                    irrigation: 0.0,
+                    deficit,
+                },
+            )]);
+            let mut periods = [ActivePeriod {
+                zone: ZONE_1,
+                duration_minutes: 10,
+                moisture: None,
+            }];
+            let adjustments = adjust(&config, &balances, &mut periods);
+            assert_eq!(adjustments[0].scheduled_depth, 2.0);
+            assert_eq!(adjustments[0].adjusted_minutes, periods[0].duration_minutes);
+            periods[0].duration_minutes
+        };
+
+        // wet enough already
+        assert_eq!(adjusted_minutes(0.3), 0);
+        assert_eq!(adjusted_minutes(1.0), 5);
+        assert_eq!(adjusted_minutes(2.0), 10);
+        // catching up is capped at half again
+        assert_eq!(adjusted_minutes(10.0), 15);
+    }
+}
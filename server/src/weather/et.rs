@@ -0,0 +1,81 @@
+use crate::weather::DailyWeather;
+
+use chrono::{Datelike, NaiveDate};
+use std::f64::consts::PI;
+
+/// MJ/m²/min.
+const SOLAR_CONSTANT: f64 = 0.0820;
+/// Converts MJ/m²/day of radiation into mm/day of evaporation.
+const MJ_TO_MM: f64 = 0.408;
+
+/// Radiation reaching the top of the atmosphere on `date`, in mm/day of
+/// evaporation (FAO-56 eq. 21).
+fn extraterrestrial_radiation(latitude: f64, date: NaiveDate) -> f64 {
+    let latitude = latitude.to_radians();
+    let day_of_year = date.ordinal() as f64;
+
+    let inverse_distance = 1.0 + 0.033 * (2.0 * PI * day_of_year / 365.0).cos();
+    let declination = 0.409 * (2.0 * PI * day_of_year / 365.0 - 1.39).sin();
+    // clamped for the polar day and night
+    let sunset_angle = (-latitude.tan() * declination.tan())
+        .clamp(-1.0, 1.0)
+        .acos();
+
+    let radiation = 24.0 * 60.0 / PI
+        * SOLAR_CONSTANT
+        * inverse_distance
+        * (sunset_angle * latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * sunset_angle.sin());
+    radiation * MJ_TO_MM
+}
+
+/// Hargreaves reference evapotranspiration for `weather`, in mm/day.
+pub fn hargreaves(weather: &DailyWeather, latitude: f64) -> f64 {
+    let temp_mean = (weather.temp_min + weather.temp_max) / 2.0;
+    let temp_range = (weather.temp_max - weather.temp_min).max(0.0);
+
+    let et = 0.0023
+        * (temp_mean + 17.8)
+        * temp_range.sqrt()
+        * extraterrestrial_radiation(latitude, weather.date);
+    et.max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: NaiveDate, temp_min: f64, temp_max: f64) -> DailyWeather {
+        DailyWeather {
+            date,
+            temp_min,
+            temp_max,
+            humidity: None,
+            rainfall: 0.0,
+        }
+    }
+
+    #[test]
+    fn matches_the_fao_56_radiation_example() {
+        // example 8: 20°S on 3 September gets 32.2 MJ/m²/day
+        let date = NaiveDate::from_ymd_opt(2025, 9, 3).unwrap();
+        let radiation = extraterrestrial_radiation(-20.0, date) / MJ_TO_MM;
+        assert!((radiation - 32.2).abs() < 0.05, "{radiation}");
+    }
+
+    #[test]
+    fn computes_hargreaves_et() {
+        let date = NaiveDate::from_ymd_opt(2025, 9, 3).unwrap();
+        let et = hargreaves(&day(date, 15.0, 25.0), -20.0);
+        assert!((et - 3.611).abs() < 0.001, "{et}");
+    }
+
+    #[test]
+    fn never_goes_negative() {
+        let winter = NaiveDate::from_ymd_opt(2025, 12, 21).unwrap();
+        // no sun in the polar night
+        assert_eq!(hargreaves(&day(winter, -30.0, -20.0), 80.0), 0.0);
+        // a maximum below the minimum is read as no range
+        assert_eq!(hargreaves(&day(winter, 20.0, 10.0), 40.0), 0.0);
+    }
+}
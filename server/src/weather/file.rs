@@ -0,0 +1,132 @@
+use crate::error::ServerError;
+use crate::weather::{DailyWeather, HourlyWeather, WeatherProvider};
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::str::FromStr;
+
+/// Reads weather from local files, either JSON arrays or CSVs with a header
+/// row naming the fields. Daily files have `date,tempMin,tempMax,humidity,rainfall`
+/// columns and hourly files `time,temp,rainfall,rainProbability,windSpeed`.
+/// Files are re-read on every call so they can be updated in place.
+pub struct FileWeatherProvider {
+    path: String,
+    hourly_path: Option<String>,
+}
+
+impl FileWeatherProvider {
+    pub fn new(path: &str, hourly_path: Option<&str>) -> Self {
+        Self {
+            path: path.to_string(),
+            hourly_path: hourly_path.map(str::to_string),
+        }
+    }
+}
+
+impl WeatherProvider for FileWeatherProvider {
+    fn daily(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<DailyWeather>, ServerError> {
+        let mut days = read(&self.path, |row| {
+            Ok(DailyWeather {
+                date: row.required("date")?,
+                temp_min: row.required("tempMin")?,
+                temp_max: row.required("tempMax")?,
+                humidity: row.optional("humidity")?,
+                rainfall: row.optional("rainfall")?.unwrap_or(0.0),
+            })
+        })?;
+
+        days.retain(|day| day.date >= from && day.date <= to);
+        days.sort_by_key(|day| day.date);
+        Ok(days)
+    }
+
+    fn hourly(
+        &self,
+        from: DateTime<Local>,
+        to: DateTime<Local>,
+    ) -> Result<Vec<HourlyWeather>, ServerError> {
+        let Some(path) = &self.hourly_path else {
+            return Ok(vec![]);
+        };
+
+        let mut hours = read(path, |row| {
+            Ok(HourlyWeather {
+                time: row.required("time")?,
+                temp: row.optional("temp")?,
+                rainfall: row.optional("rainfall")?,
+                rain_probability: row.optional("rainProbability")?,
+                wind_speed: row.optional("windSpeed")?,
+            })
+        })?;
+
+        hours.retain(|hour| hour.time >= from && hour.time <= to);
+        hours.sort_by_key(|hour| hour.time);
+        Ok(hours)
+    }
+}
+
+fn read<T: DeserializeOwned>(
+    path: &str,
+    parse_row: impl Fn(&CsvRow) -> Result<T, ServerError>,
+) -> Result<Vec<T>, ServerError> {
+    let contents =
+        fs::read_to_string(path).map_err(|_| ServerError::FailedToReadFile(path.to_string()))?;
+
+    if path.ends_with(".json") {
+        return serde_json::from_str(&contents)
+            .map_err(|e| ServerError::InvalidWeatherData(e.to_string()));
+    }
+
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or_else(|| ServerError::InvalidWeatherData("missing header".to_string()))?
+        .split(',')
+        .map(str::trim)
+        .collect();
+
+    lines
+        .enumerate()
+        .map(|(index, line)| {
+            parse_row(&CsvRow {
+                number: index + 2,
+                header: &header,
+                fields: line.split(',').map(str::trim).collect(),
+            })
+        })
+        .collect()
+}
+
+struct CsvRow<'a> {
+    number: usize,
+    header: &'a [&'a str],
+    fields: Vec<&'a str>,
+}
+
+impl CsvRow<'_> {
+    fn optional<T: FromStr>(&self, column: &str) -> Result<Option<T>, ServerError> {
+        let Some(field) = self
+            .header
+            .iter()
+            .position(|name| *name == column)
+            .and_then(|index| self.fields.get(index))
+            .filter(|field| !field.is_empty())
+        else {
+            return Ok(None);
+        };
+
+        field.parse().map(Some).map_err(|_| {
+            ServerError::InvalidWeatherData(format!(
+                "row {}: invalid {column} {field}",
+                self.number
+            ))
+        })
+    }
+
+    fn required<T: FromStr>(&self, column: &str) -> Result<T, ServerError> {
+        self.optional(column)?.ok_or_else(|| {
+            ServerError::InvalidWeatherData(format!("row {}: missing {column}", self.number))
+        })
+    }
+}
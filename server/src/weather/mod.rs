@@ -0,0 +1,184 @@
+pub mod balance;
+pub mod et;
+pub mod file;
+pub mod skip_rules;
+
+use crate::config::Config;
+use crate::error::ServerError;
+use crate::history::HistoryFilter;
+use crate::scheduler_runner::rules::SkipReason;
+use crate::types::{ActivePeriod, HistoryMutex, Schedule};
+use crate::usage;
+
+use balance::{BALANCE_DAYS, EtAdjustment};
+use chrono::{DateTime, Days, Local, NaiveDate, TimeDelta};
+use file::FileWeatherProvider;
+use serde::{Deserialize, Serialize};
+
+/// One day of observed weather.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyWeather {
+    pub date: NaiveDate,
+    /// In °C.
+    pub temp_min: f64,
+    /// In °C.
+    pub temp_max: f64,
+    /// Mean relative humidity, in percent.
+    #[serde(default)]
+    pub humidity: Option<f64>,
+    /// In mm.
+    #[serde(default)]
+    pub rainfall: f64,
+}
+
+/// One hour of weather, observed if it's past and forecast if it's not.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HourlyWeather {
+    pub time: DateTime<Local>,
+    /// In °C.
+    #[serde(default)]
+    pub temp: Option<f64>,
+    /// In mm.
+    #[serde(default)]
+    pub rainfall: Option<f64>,
+    /// In percent.
+    #[serde(default)]
+    pub rain_probability: Option<f64>,
+    /// In km/h.
+    #[serde(default)]
+    pub wind_speed: Option<f64>,
+}
+
+pub trait WeatherProvider: Send + Sync {
+    /// The days between `from` and `to`, inclusive, that the provider has
+    /// weather for, oldest first.
+    fn daily(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<DailyWeather>, ServerError>;
+
+    /// The hours between `from` and `to`, inclusive, that the provider has
+    /// weather for, oldest first.
+    fn hourly(
+        &self,
+        from: DateTime<Local>,
+        to: DateTime<Local>,
+    ) -> Result<Vec<HourlyWeather>, ServerError>;
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum WeatherSource {
+    /// Local `.json` or `.csv` files of daily and, optionally, hourly weather.
+    #[serde(rename_all = "camelCase")]
+    File {
+        path: String,
+        #[serde(default)]
+        hourly_path: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WeatherConfig {
+    pub source: WeatherSource,
+    /// In degrees, negative south of the equator.
+    pub latitude: f64,
+    /// Whether scheduled runs are scaled to each zone's soil-moisture balance.
+    #[serde(default)]
+    pub et_adjust: bool,
+}
+
+pub fn provider(config: &WeatherConfig) -> Box<dyn WeatherProvider> {
+    match &config.source {
+        WeatherSource::File { path, hourly_path } => {
+            Box::new(FileWeatherProvider::new(path, hourly_path.as_deref()))
+        }
+    }
+}
+
+/// The first of `schedule`'s skip rules broken by the weather around `at`.
+/// Runs go ahead if the weather can't be read.
+pub fn skip_reason(
+    config: &Config,
+    schedule: &Schedule,
+    at: DateTime<Local>,
+) -> Option<SkipReason> {
+    if schedule.skip_rules.is_empty() {
+        return None;
+    }
+    let Some(weather_config) = &config.weather else {
+        println!(
+            "Schedule {} has skip rules but no weather source is configured",
+            schedule.name
+        );
+        return None;
+    };
+
+    let from = at - TimeDelta::hours(skip_rules::RECENT_RAIN_HOURS);
+    let to = at + TimeDelta::hours(skip_rules::FORECAST_HOURS);
+    match provider(weather_config).hourly(from, to) {
+        Ok(weather) => skip_rules::broken_rule(&schedule.skip_rules, &weather, at),
+        Err(e) => {
+            println!("Failed to read weather for schedule {}: {e}", schedule.name);
+            None
+        }
+    }
+}
+
+/// Scales `periods` to the soil-moisture deficit of their zones, returning
+/// each change made. Periods are left as scheduled if ET adjustment is off
+/// or there's no weather to go on.
+pub async fn et_adjust(
+    config: &Config,
+    history: &HistoryMutex,
+    periods: &mut [ActivePeriod],
+) -> Vec<EtAdjustment> {
+    let Some(weather_config) = config.weather.as_ref().filter(|weather| weather.et_adjust) else {
+        return vec![];
+    };
+
+    let now = Local::now();
+    let today = now.date_naive();
+    let first_day = today - Days::new(BALANCE_DAYS);
+    let weather = match provider(weather_config).daily(first_day, today) {
+        Ok(weather) if !weather.is_empty() => weather,
+        Ok(_) => {
+            println!("No weather since {first_day}, running as scheduled");
+            return vec![];
+        }
+        Err(e) => {
+            println!("Failed to read weather, running as scheduled: {e}");
+            return vec![];
+        }
+    };
+
+    let Some(from) = first_day
+        .and_hms_opt(0, 0, 0)
+        .and_then(|from| from.and_local_timezone(Local).earliest())
+    else {
+        return vec![];
+    };
+    let filter = HistoryFilter {
+        from: Some(from),
+        ..Default::default()
+    };
+    let entries = match history.lock().await.entries(&filter) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Failed to read history, running as scheduled: {e}");
+            return vec![];
+        }
+    };
+
+    let usage = usage::usage_report(config, &entries, from, now);
+    let balances = balance::zone_balances(
+        config,
+        &weather,
+        weather_config.latitude,
+        &usage,
+        &balance::known_balances(&entries, today),
+        first_day,
+        today,
+    );
+    balance::adjust(config, &balances, periods)
+}
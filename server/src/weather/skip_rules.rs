@@ -0,0 +1,195 @@
+use crate::scheduler_runner::rules::SkipReason;
+use crate::types::SkipRule;
+use crate::weather::HourlyWeather;
+
+use chrono::{DateTime, Local, TimeDelta};
+
+pub const RECENT_RAIN_HOURS: i64 = 24;
+pub const FORECAST_HOURS: i64 = 12;
+/// How far from now an hour can be and still count as current conditions.
+const CURRENT_HOURS: i64 = 1;
+const FREEZING_C: f64 = 0.0;
+
+/// The reading closest to `at` within the current hour, if any has one.
+fn current(
+    weather: &[HourlyWeather],
+    at: DateTime<Local>,
+    reading: impl Fn(&HourlyWeather) -> Option<f64>,
+) -> Option<f64> {
+    weather
+        .iter()
+        .filter(|hour| (hour.time - at).abs() <= TimeDelta::hours(CURRENT_HOURS))
+        .filter_map(|hour| Some(((hour.time - at).abs(), reading(hour)?)))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, value)| value)
+}
+
+/// The value `rule` is judged on, or `None` if there's no weather for it.
+fn reading(rule: &SkipRule, weather: &[HourlyWeather], at: DateTime<Local>) -> Option<f64> {
+    match rule {
+        SkipRule::RecentRain { .. } => {
+            let since = at - TimeDelta::hours(RECENT_RAIN_HOURS);
+            weather
+                .iter()
+                .filter(|hour| hour.time > since && hour.time <= at)
+                .filter_map(|hour| hour.rainfall)
+                .reduce(|total, rainfall| total + rainfall)
+        }
+        SkipRule::RainForecast { .. } => {
+            let until = at + TimeDelta::hours(FORECAST_HOURS);
+            weather
+                .iter()
+                .filter(|hour| hour.time > at && hour.time <= until)
+                .filter_map(|hour| hour.rain_probability)
+                .reduce(f64::max)
+        }
+        SkipRule::Wind { .. } => current(weather, at, |hour| hour.wind_speed),
+        SkipRule::Freezing => current(weather, at, |hour| hour.temp),
+    }
+}
+
+fn is_broken(rule: &SkipRule, value: f64) -> bool {
+    match rule {
+        SkipRule::RecentRain { mm } => value >= *mm,
+        SkipRule::RainForecast { percent } => value >= *percent,
+        SkipRule::Wind { speed } => value > *speed,
+        SkipRule::Freezing => value < FREEZING_C,
+    }
+}
+
+/// The first of `rules` that the weather around `at` breaks. Rules without
+/// weather to judge them on never skip a run.
+pub fn broken_rule(
+    rules: &[SkipRule],
+    weather: &[HourlyWeather],
+    at: DateTime<Local>,
+) -> Option<SkipReason> {
+    rules.iter().find_map(|rule| {
+        let value = reading(rule, weather, at)?;
+        is_broken(rule, value).then(|| SkipReason::Weather {
+            rule: rule.clone(),
+            value,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::WeatherProvider;
+    use crate::weather::file::FileWeatherProvider;
+
+    use std::slice;
+
+    const FIXTURES: [&str; 2] = ["hourly_weather.csv", "hourly_weather.json"];
+
+    fn at(time: &str) -> DateTime<Local> {
+        time.parse().unwrap()
+    }
+
+    fn now() -> DateTime<Local> {
+        at("2025-06-01T06:00:00+00:00")
+    }
+
+    /// What `rules` skip on at `at`, reading the fixture the way
+    /// `skip_reason` reads the configured weather.
+    fn broken(fixture: &str, rules: &[SkipRule], at: DateTime<Local>) -> Option<SkipReason> {
+        let path = format!("{}/tests/fixtures/{fixture}", env!("CARGO_MANIFEST_DIR"));
+        // only the hourly file is read
+        let provider = FileWeatherProvider::new("", Some(&path));
+        let weather = provider
+            .hourly(
+                at - TimeDelta::hours(RECENT_RAIN_HOURS),
+                at + TimeDelta::hours(FORECAST_HOURS),
+            )
+            .unwrap();
+        broken_rule(rules, &weather, at)
+    }
+
+    fn skipped(rule: SkipRule, value: f64) -> Option<SkipReason> {
+        Some(SkipReason::Weather { rule, value })
+    }
+
+    #[test]
+    fn skips_after_recent_rain() {
+        for fixture in FIXTURES {
+            // 2 + 1.5mm, the 10mm fell too long ago
+            let rule = SkipRule::RecentRain { mm: 3.5 };
+            assert_eq!(
+                broken(fixture, slice::from_ref(&rule), now()),
+                skipped(rule, 3.5)
+            );
+            let rule = SkipRule::RecentRain { mm: 4.0 };
+            assert_eq!(broken(fixture, &[rule], now()), None);
+        }
+    }
+
+    #[test]
+    fn skips_when_rain_is_forecast() {
+        for fixture in FIXTURES {
+            // the 95% chance is beyond the forecast window
+            let rule = SkipRule::RainForecast { percent: 70.0 };
+            assert_eq!(
+                broken(fixture, slice::from_ref(&rule), now()),
+                skipped(rule, 70.0)
+            );
+            let rule = SkipRule::RainForecast { percent: 71.0 };
+            assert_eq!(broken(fixture, &[rule], now()), None);
+        }
+    }
+
+    #[test]
+    fn skips_in_wind() {
+        for fixture in FIXTURES {
+            let rule = SkipRule::Wind { speed: 17.5 };
+            assert_eq!(
+                broken(fixture, slice::from_ref(&rule), now()),
+                skipped(rule, 18.0)
+            );
+            let rule = SkipRule::Wind { speed: 18.0 };
+            assert_eq!(broken(fixture, &[rule], now()), None);
+        }
+    }
+
+    #[test]
+    fn skips_below_freezing() {
+        for fixture in FIXTURES {
+            let rule = SkipRule::Freezing;
+            assert_eq!(
+                broken(fixture, slice::from_ref(&rule), now()),
+                skipped(rule, -1.5)
+            );
+            let later = at("2025-06-01T09:00:00+00:00");
+            assert_eq!(broken(fixture, &[SkipRule::Freezing], later), None);
+        }
+    }
+
+    #[test]
+    fn reports_the_first_broken_rule() {
+        let rules = [
+            SkipRule::RecentRain { mm: 4.0 },
+            SkipRule::Wind { speed: 10.0 },
+            SkipRule::Freezing,
+        ];
+        for fixture in FIXTURES {
+            assert_eq!(
+                broken(fixture, &rules, now()),
+                skipped(SkipRule::Wind { speed: 10.0 }, 18.0)
+            );
+        }
+    }
+
+    #[test]
+    fn runs_without_weather() {
+        let rules = [
+            SkipRule::RecentRain { mm: 0.0 },
+            SkipRule::RainForecast { percent: 0.0 },
+            SkipRule::Wind { speed: 0.0 },
+            SkipRule::Freezing,
+        ];
+        let later = at("2025-06-10T06:00:00+00:00");
+        for fixture in FIXTURES {
+            assert_eq!(broken(fixture, &rules, later), None);
+        }
+    }
+}
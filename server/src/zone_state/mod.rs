@@ -0,0 +1,244 @@
+use crate::config::Config;
+use crate::error::ServerError;
+use crate::freeze::FreezeGuard;
+use crate::history::{self, HistoryEvent, ZoneSource};
+use crate::message::send_to_controller;
+use crate::types::{ClientMap, HistoryMutex, HydraulicGroups, ZoneId, ZoneStateMutex};
+
+use shared::{ServerMessage, ToggleZonePayload};
+use std::collections::BTreeSet;
+
+/// Tracks which zones the server has opened and enforces the configured
+/// hydraulic limits before any more are opened.
+pub struct ZoneState {
+    open_zones: BTreeSet<ZoneId>,
+    max_concurrent_zones: Option<u8>,
+    hydraulic_groups: HydraulicGroups,
+    freeze_guard: Option<FreezeGuard>,
+}
+
+impl ZoneState {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            open_zones: BTreeSet::new(),
+            max_concurrent_zones: config.max_concurrent_zones,
+            hydraulic_groups: config.hydraulic_groups.clone(),
+            freeze_guard: None,
+        }
+    }
+
+    pub fn check_open(&self, zone: ZoneId) -> Result<(), ServerError> {
+        if self.open_zones.contains(&zone) {
+            return Ok(());
+        }
+
+        if let Some(max) = self.max_concurrent_zones
+            && self.open_zones.len() >= max as usize
+        {
+            return Err(ServerError::TooManyOpenZones { zone, max });
+        }
+
+        for group in self.hydraulic_groups.iter() {
+            if !group.zones.contains(&zone) {
+                continue;
+            }
+
+            if let Some(open_zone) = self.open_zones.iter().find(|z| group.zones.contains(z)) {
+                return Err(ServerError::HydraulicGroupConflict {
+                    zone,
+                    open_zone: *open_zone,
+                    group: group.name.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn freeze_guard(&self) -> Option<&FreezeGuard> {
+        self.freeze_guard.as_ref()
+    }
+
+    pub fn set_freeze_guard(&mut self, freeze_guard: Option<FreezeGuard>) {
+        self.freeze_guard = freeze_guard;
+    }
+
+    pub fn open_zones(&self) -> &BTreeSet<ZoneId> {
+        &self.open_zones
+    }
+
+    pub fn set(&mut self, zone: ZoneId, activate: bool) {
+        if activate {
+            self.open_zones.insert(zone);
+        } else {
+            self.open_zones.remove(&zone);
+        }
+    }
+}
+
+/// Sends a toggle command to the zone's controller if the zone limits allow it.
+/// Schedules can't open zones while the freeze guard is on.
+pub async fn toggle_zone(
+    clients: &ClientMap,
+    zone_state: &ZoneStateMutex,
+    history: &HistoryMutex,
+    zone: ZoneId,
+    activate: bool,
+    source: ZoneSource,
+) -> Result<(), ServerError> {
+    let mut zone_state = zone_state.lock().await;
+    if activate {
+        if let (Some(guard), ZoneSource::Schedule { .. }) = (zone_state.freeze_guard(), &source) {
+            return Err(ServerError::FreezeGuardActive { temp: guard.temp });
+        }
+        zone_state.check_open(zone)?;
+    }
+
+    let sent = send_to_controller(
+        clients,
+        zone.controller,
+        &ServerMessage::ToggleZone(ToggleZonePayload {
+            zone: zone.zone.into(),
+            activate,
+        }),
+    )
+    .await;
+
+    // a zone we failed to close is still counted as closed, otherwise a lost
+    // controller would hold its slot forever
+    if sent || !activate {
+        zone_state.set(zone, activate);
+    }
+
+    if sent {
+        history::record(
+            history,
+            HistoryEvent::ZoneToggled {
+                zone,
+                activate,
+                source,
+            },
+        )
+        .await;
+        Ok(())
+    } else {
+        Err(ServerError::ControllerNotConnected(zone.controller))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::History;
+    use crate::types::{DeviceId, HydraulicGroup, Zone};
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    const ZONE_1: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone1,
+    };
+    const ZONE_2: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone2,
+    };
+    const ZONE_3: ZoneId = ZoneId {
+        controller: DeviceId::UNKNOWN,
+        zone: Zone::Zone3,
+    };
+
+    fn zone_state(max_concurrent_zones: Option<u8>, groups: &[&[ZoneId]]) -> ZoneState {
+        ZoneState::new(&Config {
+            max_concurrent_zones,
+            hydraulic_groups: groups
+                .iter()
+                .enumerate()
+                .map(|(index, zones)| HydraulicGroup {
+                    name: format!("group {index}"),
+                    zones: zones.iter().copied().collect(),
+                })
+                .collect(),
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn stops_at_the_zone_limit() {
+        let mut state = zone_state(Some(2), &[]);
+        state.set(ZONE_1, true);
+        assert!(state.check_open(ZONE_2).is_ok());
+        state.set(ZONE_2, true);
+
+        assert!(matches!(
+            state.check_open(ZONE_3),
+            Err(ServerError::TooManyOpenZones {
+                zone: ZONE_3,
+                max: 2
+            })
+        ));
+        assert!(zone_state(None, &[]).check_open(ZONE_3).is_ok());
+    }
+
+    #[test]
+    fn keeps_one_zone_open_per_group() {
+        let mut state = zone_state(None, &[&[ZONE_1, ZONE_2]]);
+        state.set(ZONE_1, true);
+
+        match state.check_open(ZONE_2) {
+            Err(ServerError::HydraulicGroupConflict {
+                zone,
+                open_zone,
+                group,
+            }) => {
+                assert_eq!(zone, ZONE_2);
+                assert_eq!(open_zone, ZONE_1);
+                assert_eq!(group, "group 0");
+            }
+            other => panic!("expected a group conflict, got {other:?}"),
+        }
+        // outside the group
+        assert!(state.check_open(ZONE_3).is_ok());
+    }
+
+    #[test]
+    fn an_open_zone_can_be_opened_again() {
+        let mut state = zone_state(Some(1), &[&[ZONE_1, ZONE_2]]);
+        state.set(ZONE_1, true);
+
+        assert!(state.check_open(ZONE_1).is_ok());
+        assert!(state.check_open(ZONE_2).is_err());
+    }
+
+    #[tokio::test]
+    async fn closing_frees_the_slot_even_when_the_controller_is_gone() {
+        let mut state = zone_state(Some(1), &[]);
+        state.set(ZONE_1, true);
+        assert!(state.check_open(ZONE_2).is_err());
+        let zone_state: ZoneStateMutex = Arc::new(Mutex::new(state));
+
+        let history_path =
+            std::env::temp_dir().join(format!("zone-state-test-{}.jsonl", std::process::id()));
+        let history: HistoryMutex = Arc::new(Mutex::new(
+            History::open(history_path.to_str().unwrap()).unwrap(),
+        ));
+
+        // no controllers are connected
+        let closed = toggle_zone(
+            &ClientMap::default(),
+            &zone_state,
+            &history,
+            ZONE_1,
+            false,
+            ZoneSource::User,
+        )
+        .await;
+
+        assert!(matches!(
+            closed,
+            Err(ServerError::ControllerNotConnected(_))
+        ));
+        assert!(zone_state.lock().await.open_zones().is_empty());
+        assert!(zone_state.lock().await.check_open(ZONE_2).is_ok());
+        let _ = std::fs::remove_file(history_path);
+    }
+}
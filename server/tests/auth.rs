@@ -0,0 +1,142 @@
+mod common;
+
+use common::{Server, free_port, mint_named_token, mint_token, work_dir};
+use futures_util::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Retries until the server is listening.
+async fn connect_when_up(url: &str) -> Socket {
+    for _ in 0..50 {
+        if let Ok((ws, _)) = connect_async(url).await {
+            return ws;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("couldn't connect to {url}");
+}
+
+/// Sends `handshake` then asks for the status, returning the text messages
+/// received and the close frame's code and reason, if the server closed.
+async fn exchange(url: &str, handshake: &str) -> (Vec<String>, Option<(CloseCode, String)>) {
+    let mut ws = connect_when_up(url).await;
+    ws.send(Message::Text(handshake.to_string())).await.unwrap();
+    let _ = ws
+        .send(Message::Text(
+            r#"{"type":"status","payload":{}}"#.to_string(),
+        ))
+        .await;
+
+    let mut texts = vec![];
+    while let Ok(Some(Ok(msg))) = tokio::time::timeout(Duration::from_secs(2), ws.next()).await {
+        match msg {
+            Message::Text(text) if text.contains("statusResponse") => {
+                texts.push(text);
+                return (texts, None);
+            }
+            Message::Text(text) => texts.push(text),
+            Message::Close(frame) => {
+                return (
+                    texts,
+                    frame.map(|frame| (frame.code, frame.reason.to_string())),
+                );
+            }
+            _ => {}
+        }
+    }
+    (texts, None)
+}
+
+fn authenticate(token: &str) -> String {
+    format!(r#"{{"type":"authenticate","payload":{{"token":"{token}"}}}}"#)
+}
+
+#[tokio::test]
+async fn closes_connections_that_fail_to_authenticate() {
+    let dir = work_dir("auth");
+    let token = mint_token(&dir, "viewer");
+    let port = free_port();
+    let _server = Server::start(&dir, &["--port", &port.to_string()]);
+    let url = format!("ws://127.0.0.1:{port}");
+
+    let (texts, close) = exchange(&url, &authenticate(&token)).await;
+    assert!(texts.last().unwrap().contains("statusResponse"));
+    assert_eq!(close, None);
+
+    // only the challenge every connection gets comes before the close
+    let (texts, close) = exchange(&url, &authenticate("not-a-token")).await;
+    assert_eq!(texts.len(), 1);
+    assert!(texts[0].contains(r#""type":"challenge""#));
+    assert_eq!(
+        close,
+        Some((CloseCode::Policy, "Invalid token".to_string()))
+    );
+
+    // the hash isn't accepted in place of the token
+    let config = std::fs::read_to_string(dir.join(".config.toml")).unwrap();
+    let hash = config
+        .lines()
+        .find_map(|line| line.strip_prefix("hash = "))
+        .unwrap()
+        .trim_matches('"');
+    let (_, close) = exchange(&url, &authenticate(hash)).await;
+    assert_eq!(
+        close,
+        Some((CloseCode::Policy, "Invalid token".to_string()))
+    );
+
+    let (_, close) = exchange(&url, r#"{"type":"status","payload":{}}"#).await;
+    assert_eq!(
+        close,
+        Some((CloseCode::Policy, "Invalid handshake".to_string()))
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn accepts_tokens_minted_while_running() {
+    let dir = work_dir("auth-mint");
+    let admin = mint_token(&dir, "admin");
+    let port = free_port();
+    let _server = Server::start(&dir, &["--port", &port.to_string()]);
+    let url = format!("ws://127.0.0.1:{port}");
+    connect_when_up(&url).await;
+
+    let late = mint_named_token(&dir, "late", "viewer");
+    let (texts, close) = exchange(&url, &authenticate(&late)).await;
+    assert!(texts.last().unwrap().contains("statusResponse"));
+    assert_eq!(close, None);
+
+    // a change saved by the server keeps the new token
+    let mut ws = connect_when_up(&url).await;
+    ws.send(Message::Text(authenticate(&admin))).await.unwrap();
+    ws.send(Message::Text(
+        r#"{"type":"setRainDelay","payload":{"until":null,"revision":1}}"#.to_string(),
+    ))
+    .await
+    .unwrap();
+    loop {
+        let msg = tokio::time::timeout(Duration::from_secs(2), ws.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        if let Message::Text(text) = msg
+            && text.contains("setRainDelayResponse")
+        {
+            assert!(text.contains(r#""success":true"#), "{text}");
+            break;
+        }
+    }
+    let config = std::fs::read_to_string(dir.join(".config.toml")).unwrap();
+    assert!(config.contains(r#"name = "late""#));
+    assert!(config.contains(r#"name = "test""#));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
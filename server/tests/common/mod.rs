@@ -0,0 +1,59 @@
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+pub const SERVER: &str = env!("CARGO_BIN_EXE_websocket-server");
+
+/// Stops the server when the test ends, pass or fail.
+pub struct Server(Child);
+
+impl Server {
+    /// Starts the server in `dir` with `args`, keeping its output quiet.
+    pub fn start(dir: &Path, args: &[&str]) -> Self {
+        Self(
+            Command::new(SERVER)
+                .args(args)
+                .current_dir(dir)
+                .stdout(Stdio::null())
+                .spawn()
+                .unwrap(),
+        )
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// An empty directory for one test's config and logs.
+pub fn work_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("websocket-server-{test}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn mint_token(dir: &Path, role: &str) -> String {
+    mint_named_token(dir, "test", role)
+}
+
+pub fn mint_named_token(dir: &Path, name: &str, role: &str) -> String {
+    let output = Command::new(SERVER)
+        .args(["mint-token", name, role])
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::{Server, free_port, mint_token, work_dir};
+use futures_util::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{Connector, connect_async_tls_with_config};
+
+/// Connects, authenticates and asks for the status, returning the reply.
+async fn status(url: &str, connector: Option<Connector>, token: &str) -> Result<String, String> {
+    let (mut ws, _) = connect_async_tls_with_config(url, None, false, connector)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let authenticate = format!(r#"{{"type":"authenticate","payload":{{"token":"{token}"}}}}"#);
+    ws.send(Message::Text(authenticate)).await.unwrap();
+    ws.send(Message::Text(
+        r#"{"type":"status","payload":{}}"#.to_string(),
+    ))
+    .await
+    .unwrap();
+
+    while let Some(Ok(msg)) = ws.next().await {
+        if let Message::Text(text) = msg
+            && text.contains("statusResponse")
+        {
+            return Ok(text);
+        }
+    }
+    Err("closed without a status response".to_string())
+}
+
+/// Retries until the server is listening.
+async fn status_when_up(
+    url: &str,
+    connector: impl Fn() -> Option<Connector>,
+    token: &str,
+) -> String {
+    for _ in 0..50 {
+        if let Ok(response) = status(url, connector(), token).await {
+            return response;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("no status response from {url}");
+}
+
+#[tokio::test]
+async fn serves_plain_and_tls_at_once() {
+    let dir = work_dir("tls");
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let cert_pem = certified.cert.pem();
+    std::fs::write(dir.join("cert.pem"), &cert_pem).unwrap();
+    std::fs::write(dir.join("key.pem"), certified.key_pair.serialize_pem()).unwrap();
+    let token = mint_token(&dir, "viewer");
+
+    let port = free_port();
+    let tls_port = free_port();
+    let _server = Server::start(
+        &dir,
+        &[
+            "--port",
+            &port.to_string(),
+            "--tls-port",
+            &tls_port.to_string(),
+            "--tls-cert",
+            "cert.pem",
+            "--tls-key",
+            "key.pem",
+        ],
+    );
+
+    let trusting = || {
+        let certificate = native_tls::Certificate::from_pem(cert_pem.as_bytes()).unwrap();
+        let connector = native_tls::TlsConnector::builder()
+            .add_root_certificate(certificate)
+            .build()
+            .unwrap();
+        Some(Connector::NativeTls(connector))
+    };
+    let tls_url = format!("wss://localhost:{tls_port}");
+    let response = status_when_up(&tls_url, trusting, &token).await;
+    assert!(response.contains("statusResponse"));
+
+    let plain_url = format!("ws://127.0.0.1:{port}");
+    let response = status_when_up(&plain_url, || None, &token).await;
+    assert!(response.contains("statusResponse"));
+
+    // the certificate isn't trusted without being added
+    assert!(status(&tls_url, None, &token).await.is_err());
+    // and plain websockets aren't spoken on the TLS port
+    let plain_to_tls = format!("ws://127.0.0.1:{tls_port}");
+    assert!(status(&plain_to_tls, None, &token).await.is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,77 @@
+/// Filters a noisy digital input. A new level only counts once the raw input
+/// has held it for `settle_ms` without bouncing back.
+pub struct Debouncer {
+    settle_ms: u64,
+    stable: bool,
+    candidate: bool,
+    candidate_since_ms: u64,
+}
+
+impl Debouncer {
+    pub const fn new(initial: bool, settle_ms: u64) -> Self {
+        Self {
+            settle_ms,
+            stable: initial,
+            candidate: initial,
+            candidate_since_ms: 0,
+        }
+    }
+
+    /// The debounced level.
+    pub fn level(&self) -> bool {
+        self.stable
+    }
+
+    /// Feeds a raw sample read at `now_ms`, returning the new debounced level
+    /// if it just changed.
+    pub fn update(&mut self, raw: bool, now_ms: u64) -> Option<bool> {
+        if raw != self.candidate {
+            self.candidate = raw;
+            self.candidate_since_ms = now_ms;
+        }
+
+        let settled = now_ms.saturating_sub(self.candidate_since_ms) >= self.settle_ms;
+        if self.candidate != self.stable && settled {
+            self.stable = self.candidate;
+            return Some(self.stable);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_once_settled() {
+        let mut debouncer = Debouncer::new(false, 100);
+
+        assert_eq!(debouncer.update(true, 1_000), None);
+        assert_eq!(debouncer.update(true, 1_050), None);
+        assert_eq!(debouncer.update(true, 1_100), Some(true));
+        assert_eq!(debouncer.update(true, 1_200), None);
+        assert!(debouncer.level());
+    }
+
+    #[test]
+    fn ignores_bounces() {
+        let mut debouncer = Debouncer::new(false, 100);
+
+        for now_ms in (0..1_000).step_by(30) {
+            assert_eq!(debouncer.update(now_ms % 60 == 0, now_ms), None);
+        }
+        assert!(!debouncer.level());
+    }
+
+    #[test]
+    fn restarts_settling_after_a_bounce() {
+        let mut debouncer = Debouncer::new(true, 100);
+
+        assert_eq!(debouncer.update(false, 0), None);
+        assert_eq!(debouncer.update(true, 80), None);
+        assert_eq!(debouncer.update(false, 90), None);
+        assert_eq!(debouncer.update(false, 180), None);
+        assert_eq!(debouncer.update(false, 190), Some(false));
+    }
+}
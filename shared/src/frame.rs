@@ -0,0 +1,213 @@
+//! Websocket framing (RFC 6455) for the controller, which has no websocket
+//! library to lean on.
+
+pub const OPCODE_TEXT: u8 = 0x1;
+pub const OPCODE_CLOSE: u8 = 0x8;
+
+/// The longest header a frame can have: two bytes, a 64-bit length and a
+/// masking key.
+pub const MAX_HEADER_LEN: usize = 14;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// More bytes are needed before the frame can be read.
+    Incomplete,
+    /// The frame doesn't fit in the buffer it's read into or written to.
+    TooLarge,
+}
+
+/// A frame read from the start of a buffer.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: u8,
+    /// Where the payload is in the buffer, already unmasked.
+    pub payload: core::ops::Range<usize>,
+    /// How many bytes of the buffer the frame took up.
+    pub len: usize,
+}
+
+/// Reads the frame at the start of `buffer`, unmasking its payload in place.
+pub fn read_frame(buffer: &mut [u8]) -> Result<Frame, FrameError> {
+    if buffer.len() < 2 {
+        return Err(FrameError::Incomplete);
+    }
+
+    let fin = buffer[0] & 0x80 != 0;
+    let opcode = buffer[0] & 0x0f;
+    let masked = buffer[1] & 0x80 != 0;
+
+    let (payload_len, mut header_len) = match buffer[1] & 0x7f {
+        126 => {
+            let bytes = buffer.get(2..4).ok_or(FrameError::Incomplete)?;
+            (u16::from_be_bytes([bytes[0], bytes[1]]) as u64, 4)
+        }
+        127 => {
+            let bytes = buffer.get(2..10).ok_or(FrameError::Incomplete)?;
+            let mut len = [0; 8];
+            len.copy_from_slice(bytes);
+            (u64::from_be_bytes(len), 10)
+        }
+        len => (len as u64, 2),
+    };
+
+    let mut masking_key = [0; 4];
+    if masked {
+        let bytes = buffer
+            .get(header_len..header_len + 4)
+            .ok_or(FrameError::Incomplete)?;
+        masking_key.copy_from_slice(bytes);
+        header_len += 4;
+    }
+
+    let payload_len = usize::try_from(payload_len).map_err(|_| FrameError::TooLarge)?;
+    let len = header_len
+        .checked_add(payload_len)
+        .ok_or(FrameError::TooLarge)?;
+    if len > buffer.len() {
+        return Err(FrameError::Incomplete);
+    }
+
+    if masked {
+        for (i, byte) in buffer[header_len..len].iter_mut().enumerate() {
+            *byte ^= masking_key[i % 4];
+        }
+    }
+
+    Ok(Frame {
+        fin,
+        opcode,
+        payload: header_len..len,
+        len,
+    })
+}
+
+/// Writes `payload` as a single masked text frame into `out`, returning the
+/// frame's length. Clients have to mask every frame they send.
+pub fn write_text_frame(
+    payload: &[u8],
+    masking_key: [u8; 4],
+    out: &mut [u8],
+) -> Result<usize, FrameError> {
+    let mut header_len = 2;
+    let length_marker = match payload.len() {
+        len @ 0..=125 => len as u8,
+        len if len <= u16::MAX as usize => {
+            header_len += 2;
+            126
+        }
+        _ => {
+            header_len += 8;
+            127
+        }
+    };
+    let len = header_len + 4 + payload.len();
+    if len > out.len() {
+        return Err(FrameError::TooLarge);
+    }
+
+    out[0] = 0x80 | OPCODE_TEXT;
+    out[1] = 0x80 | length_marker;
+    match length_marker {
+        126 => out[2..4].copy_from_slice(&(payload.len() as u16).to_be_bytes()),
+        127 => out[2..10].copy_from_slice(&(payload.len() as u64).to_be_bytes()),
+        _ => {}
+    }
+    out[header_len..header_len + 4].copy_from_slice(&masking_key);
+
+    for (i, byte) in payload.iter().enumerate() {
+        out[header_len + 4 + i] = byte ^ masking_key[i % 4];
+    }
+
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    fn unmasked(payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x80 | OPCODE_TEXT];
+        match payload.len() {
+            len @ 0..=125 => frame.push(len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn reads_a_short_frame() {
+        let mut buffer = unmasked(b"{\"type\":\"challenge\"}");
+
+        let frame = read_frame(&mut buffer).unwrap();
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, OPCODE_TEXT);
+        assert_eq!(frame.len, buffer.len());
+        assert_eq!(&buffer[frame.payload], b"{\"type\":\"challenge\"}");
+    }
+
+    #[test]
+    fn reads_a_frame_longer_than_125_bytes() {
+        // about the size of a signed toggleZone
+        let payload = [b'x'; 151];
+        let mut buffer = unmasked(&payload);
+        assert_eq!(&buffer[1..4], &[126, 0x00, 0x97]);
+
+        let frame = read_frame(&mut buffer).unwrap();
+        assert_eq!(frame.payload, 4..155);
+        assert_eq!(&buffer[frame.payload], &payload[..]);
+    }
+
+    #[test]
+    fn reads_a_64_bit_length() {
+        let payload = [b'y'; 70_000];
+        let mut buffer = unmasked(&payload);
+        assert_eq!(buffer[1], 127);
+
+        let frame = read_frame(&mut buffer).unwrap();
+        assert_eq!(frame.payload, 10..70_010);
+        assert_eq!(&buffer[frame.payload], &payload[..]);
+    }
+
+    #[test]
+    fn reads_back_a_masked_frame() {
+        let payload = [b'z'; 200];
+        let mut buffer = [0; 256];
+        let len = write_text_frame(&payload, [1, 2, 3, 4], &mut buffer).unwrap();
+        assert_eq!(len, 208);
+        assert_eq!(buffer[1], 0x80 | 126);
+        assert_ne!(&buffer[8..len], &payload[..]);
+
+        let frame = read_frame(&mut buffer[..len]).unwrap();
+        assert_eq!(frame.len, len);
+        assert_eq!(&buffer[frame.payload], &payload[..]);
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_frame() {
+        let mut buffer = unmasked(&[b'x'; 151]);
+        assert_eq!(read_frame(&mut buffer[..1]), Err(FrameError::Incomplete));
+        assert_eq!(read_frame(&mut buffer[..3]), Err(FrameError::Incomplete));
+        assert_eq!(read_frame(&mut buffer[..100]), Err(FrameError::Incomplete));
+    }
+
+    #[test]
+    fn refuses_frames_that_dont_fit() {
+        let mut buffer = [0; 16];
+        assert_eq!(
+            write_text_frame(&[b'x'; 13], [0; 4], &mut buffer),
+            Err(FrameError::TooLarge)
+        );
+        assert_eq!(write_text_frame(&[b'x'; 10], [0; 4], &mut buffer), Ok(16));
+    }
+}
@@ -1,5 +1,15 @@
 #![no_std]
 
+extern crate alloc;
+
+mod debounce;
+mod frame;
 mod messages;
+mod signing;
 
+pub use debounce::Debouncer;
+pub use frame::{
+    Frame, FrameError, MAX_HEADER_LEN, OPCODE_CLOSE, OPCODE_TEXT, read_frame, write_text_frame,
+};
 pub use messages::*;
+pub use signing::{Signer, Verifier, VerifyError, identify_proof, verify_identify_proof};
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowPayload {
+    /// Average over the last report interval.
+    pub liters_per_minute: f32,
+    /// Since the controller started.
+    pub total_liters: f32,
+}
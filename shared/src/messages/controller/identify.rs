@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentifyPayload<'a> {
+    /// The MAC address of the controller's Wi-Fi interface, written
+    /// `AA:BB:CC:DD:EE:FF`.
+    pub device_id: &'a str,
+    /// Picked at random for each connection. The server signs what it sends
+    /// with it, so messages can't be replayed from an earlier connection.
+    pub nonce: u64,
+    /// `identify_proof` over the server's challenge, made with the secret
+    /// flashed into the firmware. The secret itself is never sent.
+    pub proof: &'a str,
+}
@@ -1,12 +1,32 @@
+pub mod flow;
+pub mod identify;
 pub mod keep_alive;
+pub mod rain_sensor;
+pub mod soil_moisture;
 
+pub use flow::FlowPayload;
+pub use identify::IdentifyPayload;
 pub use keep_alive::{KeepAlivePayload, KeepAliveResponse};
+pub use rain_sensor::RainSensorPayload;
 use serde::{Deserialize, Serialize};
+pub use soil_moisture::SoilMoisturePayload;
+
+/// The first message a controller sends after connecting, in place of the
+/// `"user"` a dashboard sends.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", content = "payload", rename_all = "camelCase")]
+pub enum ControllerHandshake<'a> {
+    #[serde(borrow)]
+    Identify(IdentifyPayload<'a>),
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type", content = "payload", rename_all = "camelCase")]
 pub enum ControllerMessage {
     KeepAlive(KeepAlivePayload),
+    RainSensor(RainSensorPayload),
+    Flow(FlowPayload),
+    SoilMoisture(SoilMoisturePayload),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -0,0 +1,8 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RainSensorPayload {
+    /// Whether the sensor is wet, or its circuit is broken.
+    pub active: bool,
+}
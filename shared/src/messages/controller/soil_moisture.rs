@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SoilMoisturePayload {
+    pub zone: u8,
+    /// Volumetric water content.
+    pub percent: f32,
+}
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengePayload<'a> {
+    /// Random hex, new for every connection. Controllers prove they hold
+    /// their secret with a MAC over it, rather than sending the secret.
+    pub challenge: &'a str,
+}
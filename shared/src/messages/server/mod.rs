@@ -1,8 +1,19 @@
+pub mod challenge;
 pub mod toggle_zone;
 
+pub use challenge::ChallengePayload;
 use serde::{Deserialize, Serialize};
 pub use toggle_zone::{ToggleZonePayload, ToggleZoneResponse};
 
+/// The first message on every connection, sent before the client identifies
+/// itself and so unsigned.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", content = "payload", rename_all = "camelCase")]
+pub enum ServerHandshake<'a> {
+    #[serde(borrow)]
+    Challenge(ChallengePayload<'a>),
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type", content = "payload", rename_all = "camelCase")]
 pub enum ServerMessage {
@@ -0,0 +1,349 @@
+use alloc::string::String;
+use core::fmt::Display;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use sha2::Sha256;
+
+use crate::ServerMessage;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const MAC_LEN: usize = 32;
+/// Keeps identify proofs from passing as the MAC of anything else.
+const IDENTIFY_TAG: &[u8] = b"identify\0";
+
+/// A `ServerMessage` as it goes over the wire. The MAC covers the
+/// connection's nonce, `seq` and the exact bytes of `message`.
+#[derive(Serialize, Deserialize)]
+struct SignedMessage<'a> {
+    seq: u64,
+    #[serde(borrow)]
+    message: &'a RawValue,
+    mac: &'a str,
+}
+
+fn keyed(secret: &[u8]) -> HmacSha256 {
+    HmacSha256::new_from_slice(secret).expect("HMAC takes keys of any length")
+}
+
+fn mac(keyed: &HmacSha256, nonce: u64, seq: u64, message: &str) -> HmacSha256 {
+    let mut mac = keyed.clone();
+    mac.update(&nonce.to_be_bytes());
+    mac.update(&seq.to_be_bytes());
+    mac.update(message.as_bytes());
+    mac
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    bytes
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0xf])
+        .map(|digit| DIGITS[digit as usize] as char)
+        .collect()
+}
+
+fn from_hex(hex: &str) -> Option<[u8; MAC_LEN]> {
+    if hex.len() != MAC_LEN * 2 {
+        return None;
+    }
+    let mut bytes = [0; MAC_LEN];
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = core::str::from_utf8(pair).ok()?;
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn identify_mac(secret: &[u8], device_id: &str, challenge: &str, nonce: u64) -> HmacSha256 {
+    let mut mac = keyed(secret);
+    mac.update(IDENTIFY_TAG);
+    mac.update(&nonce.to_be_bytes());
+    mac.update(challenge.as_bytes());
+    mac.update(b"\0");
+    mac.update(device_id.as_bytes());
+    mac
+}
+
+/// Proves a controller holds `secret` without sending it, in answer to the
+/// server's `challenge` for the connection.
+pub fn identify_proof(secret: &[u8], device_id: &str, challenge: &str, nonce: u64) -> String {
+    to_hex(
+        &identify_mac(secret, device_id, challenge, nonce)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// Checks a proof made by `identify_proof`, in constant time.
+pub fn verify_identify_proof(
+    secret: &[u8],
+    device_id: &str,
+    challenge: &str,
+    nonce: u64,
+    proof: &str,
+) -> bool {
+    from_hex(proof).is_some_and(|proof| {
+        identify_mac(secret, device_id, challenge, nonce)
+            .verify_slice(&proof)
+            .is_ok()
+    })
+}
+
+/// Signs the messages the server sends one controller connection.
+#[derive(Clone)]
+pub struct Signer {
+    keyed: HmacSha256,
+    nonce: u64,
+    seq: u64,
+}
+
+impl Signer {
+    /// `nonce` is the one the controller picked for this connection.
+    pub fn new(secret: &[u8], nonce: u64) -> Self {
+        Self {
+            keyed: keyed(secret),
+            nonce,
+            seq: 0,
+        }
+    }
+
+    pub fn sign(&mut self, message: &ServerMessage) -> String {
+        self.seq += 1;
+        let message = serde_json::to_string(message).unwrap();
+        let mac = mac(&self.keyed, self.nonce, self.seq, &message).finalize();
+        let signed = SignedMessage {
+            seq: self.seq,
+            message: serde_json::from_str(&message).unwrap(),
+            mac: &to_hex(&mac.into_bytes()),
+        };
+        serde_json::to_string(&signed).unwrap()
+    }
+}
+
+impl core::fmt::Debug for Signer {
+    // leaves out the key
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Signer")
+            .field("nonce", &self.nonce)
+            .field("seq", &self.seq)
+            .finish()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    Malformed,
+    /// Signed with another key, for another connection, or tampered with.
+    BadMac,
+    /// Already seen, or older than one that was.
+    Replayed {
+        seq: u64,
+        last_seq: u64,
+    },
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            VerifyError::Malformed => write!(f, "malformed signed message"),
+            VerifyError::BadMac => write!(f, "bad MAC"),
+            VerifyError::Replayed { seq, last_seq } => {
+                write!(f, "replayed message {seq}, already at {last_seq}")
+            }
+        }
+    }
+}
+
+/// Checks the messages a controller receives on one connection.
+pub struct Verifier {
+    keyed: HmacSha256,
+    nonce: u64,
+    last_seq: u64,
+}
+
+impl Verifier {
+    /// `nonce` has to be new for every connection, so that messages signed
+    /// for an earlier one don't verify.
+    pub fn new(secret: &[u8], nonce: u64) -> Self {
+        Self {
+            keyed: keyed(secret),
+            nonce,
+            last_seq: 0,
+        }
+    }
+
+    pub fn verify(&mut self, text: &str) -> Result<ServerMessage, VerifyError> {
+        let signed: SignedMessage =
+            serde_json::from_str(text).map_err(|_| VerifyError::Malformed)?;
+        let expected = from_hex(signed.mac).ok_or(VerifyError::Malformed)?;
+
+        mac(&self.keyed, self.nonce, signed.seq, signed.message.get())
+            .verify_slice(&expected)
+            .map_err(|_| VerifyError::BadMac)?;
+        if signed.seq <= self.last_seq {
+            return Err(VerifyError::Replayed {
+                seq: signed.seq,
+                last_seq: self.last_seq,
+            });
+        }
+
+        let message =
+            serde_json::from_str(signed.message.get()).map_err(|_| VerifyError::Malformed)?;
+        self.last_seq = signed.seq;
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ToggleZonePayload;
+
+    const SECRET: &[u8] = b"front yard";
+    const NONCE: u64 = 0x5eed;
+
+    fn toggle(zone: u8) -> ServerMessage {
+        ServerMessage::ToggleZone(ToggleZonePayload {
+            zone,
+            activate: true,
+        })
+    }
+
+    fn zone(message: ServerMessage) -> u8 {
+        let ServerMessage::ToggleZone(payload) = message;
+        payload.zone
+    }
+
+    #[test]
+    fn verifies_signed_messages_in_order() {
+        let mut signer = Signer::new(SECRET, NONCE);
+        let mut verifier = Verifier::new(SECRET, NONCE);
+
+        for n in 1..=3 {
+            let signed = signer.sign(&toggle(n));
+            assert_eq!(verifier.verify(&signed).map(zone), Ok(n));
+        }
+    }
+
+    #[test]
+    fn rejects_replays() {
+        let mut signer = Signer::new(SECRET, NONCE);
+        let mut verifier = Verifier::new(SECRET, NONCE);
+        let first = signer.sign(&toggle(1));
+        let second = signer.sign(&toggle(2));
+
+        assert!(verifier.verify(&first).is_ok());
+        assert_eq!(
+            verifier.verify(&first).map(zone),
+            Err(VerifyError::Replayed {
+                seq: 1,
+                last_seq: 1
+            })
+        );
+
+        // skipping ahead is fine, going back isn't
+        assert!(verifier.verify(&signer.sign(&toggle(3))).is_ok());
+        assert_eq!(
+            verifier.verify(&second).map(zone),
+            Err(VerifyError::Replayed {
+                seq: 2,
+                last_seq: 3
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_other_keys_and_connections() {
+        let mut verifier = Verifier::new(SECRET, NONCE);
+
+        let forged = Signer::new(b"guessed", NONCE).sign(&toggle(1));
+        assert_eq!(verifier.verify(&forged).map(zone), Err(VerifyError::BadMac));
+
+        let earlier_connection = Signer::new(SECRET, NONCE + 1).sign(&toggle(1));
+        assert_eq!(
+            verifier.verify(&earlier_connection).map(zone),
+            Err(VerifyError::BadMac)
+        );
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let mut verifier = Verifier::new(SECRET, NONCE);
+        let signed = Signer::new(SECRET, NONCE).sign(&toggle(1));
+
+        let other_zone = signed.replace(r#""zone":1"#, r#""zone":2"#);
+        assert_ne!(other_zone, signed);
+        assert_eq!(
+            verifier.verify(&other_zone).map(zone),
+            Err(VerifyError::BadMac)
+        );
+
+        let later_seq = signed.replace(r#""seq":1"#, r#""seq":5"#);
+        assert_eq!(
+            verifier.verify(&later_seq).map(zone),
+            Err(VerifyError::BadMac)
+        );
+
+        // still good once the tampered copies are turned away
+        assert_eq!(verifier.verify(&signed).map(zone), Ok(1));
+    }
+
+    #[test]
+    fn verifies_identify_proofs() {
+        const DEVICE: &str = "AA:BB:CC:DD:EE:FF";
+        const CHALLENGE: &str = "0123456789abcdef0123456789abcdef";
+        let proof = identify_proof(SECRET, DEVICE, CHALLENGE, NONCE);
+
+        assert!(verify_identify_proof(
+            SECRET, DEVICE, CHALLENGE, NONCE, &proof
+        ));
+        assert!(!verify_identify_proof(
+            b"guessed", DEVICE, CHALLENGE, NONCE, &proof
+        ));
+        assert!(!verify_identify_proof(
+            SECRET,
+            "AA:BB:CC:DD:EE:00",
+            CHALLENGE,
+            NONCE,
+            &proof
+        ));
+        // a proof recorded on another connection doesn't answer this one
+        assert!(!verify_identify_proof(
+            SECRET,
+            DEVICE,
+            "fedcba9876543210fedcba9876543210",
+            NONCE,
+            &proof
+        ));
+        assert!(!verify_identify_proof(
+            SECRET,
+            DEVICE,
+            CHALLENGE,
+            NONCE + 1,
+            &proof
+        ));
+        assert!(!verify_identify_proof(
+            SECRET, DEVICE, CHALLENGE, NONCE, "00"
+        ));
+    }
+
+    #[test]
+    fn rejects_unsigned_and_malformed_messages() {
+        let mut verifier = Verifier::new(SECRET, NONCE);
+
+        let unsigned = serde_json::to_string(&toggle(1)).unwrap();
+        assert_eq!(
+            verifier.verify(&unsigned).map(zone),
+            Err(VerifyError::Malformed)
+        );
+
+        let signed = Signer::new(SECRET, NONCE).sign(&toggle(1));
+        let short_mac = signed.replace(r#""mac":""#, r#""mac":"0"#);
+        assert_eq!(
+            verifier.verify(&short_mac).map(zone),
+            Err(VerifyError::Malformed)
+        );
+    }
+}